@@ -1,9 +1,69 @@
 use std::path::Path;
+use std::str::FromStr;
 
 use std::fs;
-use toml_edit::{Array, DocumentMut, Formatted, Value};
+use toml_edit::{Array, DocumentMut, Formatted, InlineTable, Value};
 
-use crate::{Config, config::ConfigLoadError};
+use crate::{Config, VersionRequirement, config::ConfigLoadError};
+
+/// A package given to `rv add`, either `name` or `name@version`.
+/// A version without an operator, eg `dplyr@1.1.4`, means that exact version.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PackageToAdd {
+    name: String,
+    /// Stored as written in the config file, eg `>= 1.1.0`
+    version: Option<String>,
+}
+
+impl PackageToAdd {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_value(&self) -> Value {
+        if let Some(version) = &self.version {
+            let mut table = InlineTable::new();
+            table.insert("name", self.name.as_str().into());
+            table.insert("version", version.as_str().into());
+            Value::InlineTable(table)
+        } else {
+            Value::String(Formatted::new(self.name.clone()))
+        }
+    }
+}
+
+impl FromStr for PackageToAdd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, version) = match s.split_once('@') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (s.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(format!("`{s}` is missing a package name"));
+        }
+
+        let version = match version {
+            Some(v) => {
+                let v = if v.starts_with(['<', '>', '=']) {
+                    v.to_string()
+                } else {
+                    format!("== {v}")
+                };
+                VersionRequirement::from_str(&v)
+                    .map_err(|e| format!("`{s}` has an invalid version: {e}"))?;
+                Some(v)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            version,
+        })
+    }
+}
 
 pub fn read_and_verify_config(config_file: impl AsRef<Path>) -> Result<DocumentMut, AddError> {
     let config_file = config_file.as_ref();
@@ -16,25 +76,61 @@ pub fn read_and_verify_config(config_file: impl AsRef<Path>) -> Result<DocumentM
     Ok(config_content.parse::<DocumentMut>().unwrap()) // Verify config was valid toml above
 }
 
-pub fn add_packages(config_doc: &mut DocumentMut, packages: Vec<String>) -> Result<(), AddError> {
+/// Adds the packages to the dependencies of the config.
+/// Packages already in the config are refused unless `upgrade` is set, in which case their
+/// version requirement is replaced by the new one if one was given.
+pub fn add_packages(
+    config_doc: &mut DocumentMut,
+    packages: Vec<PackageToAdd>,
+    upgrade: bool,
+) -> Result<(), AddPackagesError> {
     // get the dependencies array
     let config_deps = get_mut_array(config_doc);
 
     // collect the names of all of the dependencies
     let config_dep_names = config_deps
         .iter()
-        .filter_map(|v| match v {
-            Value::String(s) => Some(s.value().as_str()),
-            Value::InlineTable(t) => t.get("name").and_then(|v| v.as_str()),
-            _ => None,
-        })
-        .map(|s| s.to_string()) // Need to allocate so values are not a reference to a mut
+        .map(dependency_name)
+        .map(|s| s.map(|s| s.to_string())) // Need to allocate so values are not a reference to a mut
         .collect::<Vec<_>>();
 
+    if !upgrade {
+        let already_present: Vec<_> = packages
+            .iter()
+            .filter(|p| config_dep_names.iter().flatten().any(|n| n == p.name()))
+            .map(|p| p.name().to_string())
+            .collect();
+        if !already_present.is_empty() {
+            return Err(AddPackagesError::AlreadyPresent(already_present));
+        }
+    }
+
     // Determine if the dep to add is in the config, if not add it
     for d in packages {
-        if !config_dep_names.contains(&d) {
-            config_deps.push(Value::String(Formatted::new(d)));
+        if let Some(idx) = config_dep_names
+            .iter()
+            .position(|n| n.as_deref() == Some(d.name()))
+        {
+            let Some(version) = &d.version else {
+                continue;
+            };
+            let existing = config_deps.get_mut(idx).unwrap();
+            match existing {
+                Value::String(_) => {
+                    let decor = existing.decor().clone();
+                    *existing = d.as_value();
+                    *existing.decor_mut() = decor;
+                }
+                Value::InlineTable(t) => {
+                    if ["git", "path", "url"].iter().any(|k| t.contains_key(k)) {
+                        return Err(AddPackagesError::VersionNotSupported(d.name));
+                    }
+                    t.insert("version", version.as_str().into());
+                }
+                _ => (),
+            }
+        } else {
+            config_deps.push(d.as_value());
             // Couldn't format value before pushing, so adding formatting after its added
             if let Some(last) = config_deps.iter_mut().last() {
                 last.decor_mut().set_prefix("\n    ");
@@ -49,6 +145,46 @@ pub fn add_packages(config_doc: &mut DocumentMut, packages: Vec<String>) -> Resu
     Ok(())
 }
 
+/// Removes the packages from the dependencies of the config.
+/// If one of them is not a dependency, nothing is removed.
+pub fn remove_packages(
+    config_doc: &mut DocumentMut,
+    packages: &[String],
+) -> Result<(), NotADependencyError> {
+    let config_deps = get_mut_array(config_doc);
+
+    let missing: Vec<_> = packages
+        .iter()
+        .filter(|p| {
+            !config_deps
+                .iter()
+                .any(|d| dependency_name(d) == Some(p.as_str()))
+        })
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(NotADependencyError(missing));
+    }
+
+    config_deps.retain(|d| !dependency_name(d).is_some_and(|n| packages.iter().any(|p| p == n)));
+
+    // Same formatting as when adding packages
+    if !config_deps.is_empty() {
+        config_deps.set_trailing("\n");
+        config_deps.set_trailing_comma(true);
+    }
+
+    Ok(())
+}
+
+fn dependency_name(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(s) => Some(s.value().as_str()),
+        Value::InlineTable(t) => t.get("name").and_then(|v| v.as_str()),
+        _ => None,
+    }
+}
+
 fn get_mut_array(doc: &mut DocumentMut) -> &mut Array {
     // the dependnecies array is behind the project table
     let deps = doc
@@ -83,15 +219,115 @@ pub enum AddErrorKind {
     ConfigLoad(#[from] ConfigLoadError),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum AddPackagesError {
+    #[error(
+        "{} already in the dependencies, use `rv add --upgrade` to change {}",
+        .0.join(", "),
+        if .0.len() == 1 { "it" } else { "them" }
+    )]
+    AlreadyPresent(Vec<String>),
+    #[error("A version can only be set on a repository dependency, `{0}` is not one")]
+    VersionNotSupported(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{} not in the dependencies", .0.join(", "))]
+pub struct NotADependencyError(Vec<String>);
+
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::{add_packages, read_and_verify_config};
 
+    fn to_add(packages: &[&str]) -> Vec<PackageToAdd> {
+        packages.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
     #[test]
     fn add_remove() {
         let config_file = "src/tests/valid_config/all_fields.toml";
         let mut doc = read_and_verify_config(&config_file).unwrap();
-        add_packages(&mut doc, vec!["pkg1".to_string(), "pkg2".to_string()]).unwrap();
+        add_packages(&mut doc, to_add(&["pkg1", "pkg2"]), false).unwrap();
         insta::assert_snapshot!("add_remove", doc.to_string());
     }
+
+    #[test]
+    fn can_add_package_with_version() {
+        let config_file = "src/tests/valid_config/all_fields.toml";
+        let mut doc = read_and_verify_config(config_file).unwrap();
+        add_packages(&mut doc, to_add(&["pkg1@1.0.0", "pkg2@>=2.1"]), false).unwrap();
+        let config: Config = doc.to_string().parse().unwrap();
+        let added = &config.dependencies()[config.dependencies().len() - 2..];
+        assert_eq!(added[0].name(), "pkg1");
+        assert_eq!(
+            added[0].version_requirement().unwrap().to_string(),
+            "(== 1.0.0)"
+        );
+        assert_eq!(added[1].name(), "pkg2");
+        assert_eq!(
+            added[1].version_requirement().unwrap().to_string(),
+            "(>= 2.1)"
+        );
+    }
+
+    #[test]
+    fn refuses_already_present_package() {
+        let config_file = "src/tests/valid_config/all_fields.toml";
+        let mut doc = read_and_verify_config(config_file).unwrap();
+        let before = doc.to_string();
+        let err = add_packages(&mut doc, to_add(&["pkg1", "dplyr"]), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dplyr already in the dependencies, use `rv add --upgrade` to change it"
+        );
+        assert_eq!(doc.to_string(), before);
+    }
+
+    #[test]
+    fn can_upgrade_already_present_package() {
+        let config_file = "src/tests/valid_config/all_fields.toml";
+        let mut doc = read_and_verify_config(config_file).unwrap();
+        add_packages(&mut doc, to_add(&["dplyr@>= 1.1.0"]), true).unwrap();
+        let config: Config = doc.to_string().parse().unwrap();
+        assert_eq!(config.dependencies()[0].name(), "dplyr");
+        assert_eq!(
+            config.dependencies()[0]
+                .version_requirement()
+                .unwrap()
+                .to_string(),
+            "(>= 1.1.0)"
+        );
+
+        let mut doc: DocumentMut = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = []
+dependencies = [{ name = "scicalc", git = "https://github.com/A2-ai/scicalc", tag = "v0.1.1" }]
+"#
+        .parse()
+        .unwrap();
+        // Git dependencies can't have a version
+        assert!(add_packages(&mut doc, to_add(&["scicalc@1.0"]), true).is_err());
+    }
+
+    #[test]
+    fn can_remove_packages() {
+        let config_file = "src/tests/valid_config/all_fields.toml";
+        let mut doc = read_and_verify_config(config_file).unwrap();
+        remove_packages(&mut doc, &["dplyr".to_string()]).unwrap();
+        let config: Config = doc.to_string().parse().unwrap();
+        assert!(config.dependencies().iter().all(|d| d.name() != "dplyr"));
+        assert_eq!(config.dependencies().len(), 5);
+
+        let err = remove_packages(&mut doc, &["dplyr".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "dplyr not in the dependencies");
+    }
+
+    #[test]
+    fn errors_on_invalid_package_to_add() {
+        assert!("@1.0".parse::<PackageToAdd>().is_err());
+        assert!("dplyr@abc".parse::<PackageToAdd>().is_err());
+    }
 }
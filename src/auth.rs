@@ -0,0 +1,282 @@
+//! Credentials for the servers we download from, sent in the `Authorization` header of the
+//! requests to their host.
+//! They come from the `.netrc` file and from the repository tokens, set in the config or in a
+//! `RV_REPO_TOKEN_<alias>` env var. They are never logged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use base64::Engine;
+use serde::Deserialize;
+use url::Url;
+
+use crate::consts::{NETRC_ENV_VAR_NAME, REPO_TOKEN_ENV_VAR_PREFIX};
+
+/// A string that is not shown in `Debug` so it doesn't end up in logs
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Auth {
+    Bearer(Secret),
+    Basic { login: String, password: Secret },
+}
+
+impl Auth {
+    fn header_value(&self) -> String {
+        match self {
+            Auth::Bearer(token) => format!("Bearer {}", token.expose()),
+            Auth::Basic { login, password } => {
+                format!(
+                    "Basic {}",
+                    base64_encode(format!("{login}:{}", password.expose()).as_bytes())
+                )
+            }
+        }
+    }
+}
+
+fn base64_encode(input: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(input)
+}
+
+/// The credentials to use for each host
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Credentials {
+    hosts: HashMap<String, Auth>,
+    /// From the `default` entry of the netrc file, used for hosts without credentials
+    default: Option<Auth>,
+}
+
+impl Credentials {
+    /// Parses a netrc file: `machine <host> login <login> password <password>` entries,
+    /// and optionally a `default` one for all other hosts.
+    /// Macros and accounts are not used and entries without a password are skipped.
+    pub fn from_netrc(content: &str) -> Self {
+        let mut credentials = Self::default();
+        // Comments go until the end of the line
+        let mut tokens = content
+            .lines()
+            .flat_map(|l| l.split('#').next().unwrap_or_default().split_whitespace())
+            .peekable();
+        // `None` for the default entry
+        let mut current: Option<Option<String>> = None;
+        let mut login = None;
+        let mut password = None;
+
+        let mut finish =
+            |current: Option<Option<String>>, login: Option<String>, password: Option<String>| {
+                if let (Some(machine), Some(password)) = (current, password) {
+                    let auth = Auth::Basic {
+                        login: login.unwrap_or_default(),
+                        password: Secret(password),
+                    };
+                    match machine {
+                        Some(host) => {
+                            credentials.hosts.insert(host.to_lowercase(), auth);
+                        }
+                        None => credentials.default = Some(auth),
+                    }
+                }
+            };
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" | "default" => {
+                    finish(current.take(), login.take(), password.take());
+                    current = Some(if token == "machine" {
+                        tokens.next().map(|m| m.to_string())
+                    } else {
+                        None
+                    });
+                }
+                "login" => login = tokens.next().map(|l| l.to_string()),
+                "password" => password = tokens.next().map(|p| p.to_string()),
+                "account" => {
+                    tokens.next();
+                }
+                "macdef" => {
+                    // The macro goes until an empty line, which we can't see anymore, we just
+                    // skip until the next entry
+                    finish(current.take(), login.take(), password.take());
+                    while tokens
+                        .peek()
+                        .is_some_and(|t| *t != "machine" && *t != "default")
+                    {
+                        tokens.next();
+                    }
+                }
+                _ => (),
+            }
+        }
+        finish(current, login, password);
+
+        credentials
+    }
+
+    /// Reads the netrc file from the `NETRC` env var or from the home directory, no file means
+    /// no credentials
+    pub fn from_default_netrc() -> Self {
+        let Some(path) = netrc_path() else {
+            return Self::default();
+        };
+        match fs_err::read_to_string(&path) {
+            Ok(content) => Self::from_netrc(&content),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to read {}: {e}", path.display());
+                }
+                Self::default()
+            }
+        }
+    }
+
+    /// Sends that token as a bearer token to the host of the URL, replacing what the netrc
+    /// file had for it
+    pub fn add_token(&mut self, url: &Url, token: Secret) {
+        if let Some(host) = url.host_str() {
+            self.hosts.insert(host.to_lowercase(), Auth::Bearer(token));
+        }
+    }
+
+    /// The value of the `Authorization` header for a request to that URL, if we have
+    /// credentials for its host
+    pub(crate) fn authorization(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?.to_lowercase();
+        self.hosts
+            .get(&host)
+            .or(self.default.as_ref())
+            .map(|a| a.header_value())
+    }
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var(NETRC_ENV_VAR_NAME) {
+        return Some(PathBuf::from(path));
+    }
+    let name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    etcetera::home_dir().ok().map(|h| h.join(name))
+}
+
+/// The env var holding the token of the repository with that alias, eg `RV_REPO_TOKEN_PPM`.
+/// Characters that can't be in an env var name are replaced by `_`.
+pub fn repository_token_env_var(alias: &str) -> String {
+    let alias: String = alias
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{REPO_TOKEN_ENV_VAR_PREFIX}{alias}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn can_parse_netrc() {
+        let credentials = Credentials::from_netrc(
+            r#"
+# Posit Package Manager
+machine packagemanager.example.com
+  login alice
+  password s3cret
+machine github.com login bob password token123 account unused
+machine nopassword.example.com login carol
+"#,
+        );
+        assert_eq!(
+            credentials
+                .authorization(&url("https://packagemanager.example.com/cran/latest"))
+                .unwrap(),
+            "Basic YWxpY2U6czNjcmV0"
+        );
+        assert_eq!(
+            credentials
+                .authorization(&url("https://GitHub.com/org/repo"))
+                .unwrap(),
+            "Basic Ym9iOnRva2VuMTIz"
+        );
+        assert!(
+            credentials
+                .authorization(&url("https://nopassword.example.com"))
+                .is_none()
+        );
+        assert!(
+            credentials
+                .authorization(&url("https://cran.r-project.org"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn netrc_default_is_used_for_other_hosts() {
+        let credentials = Credentials::from_netrc(
+            "machine example.com login a password b\ndefault login anon password guest",
+        );
+        assert_eq!(
+            credentials.authorization(&url("https://cran.r-project.org")),
+            Some("Basic YW5vbjpndWVzdA==".to_string())
+        );
+    }
+
+    #[test]
+    fn tokens_replace_netrc_for_their_host() {
+        let mut credentials =
+            Credentials::from_netrc("machine packagemanager.example.com login a password b");
+        credentials.add_token(
+            &url("https://packagemanager.example.com/private/latest"),
+            Secret::new("abc"),
+        );
+        assert_eq!(
+            credentials.authorization(&url("https://packagemanager.example.com/cran/latest")),
+            Some("Bearer abc".to_string())
+        );
+    }
+
+    #[test]
+    fn secrets_are_not_in_debug_output() {
+        let mut credentials = Credentials::from_netrc("machine example.com login a password b");
+        credentials.add_token(&url("https://private.example.com"), Secret::new("abc"));
+        let debug = format!("{credentials:?}");
+        assert!(!debug.contains("abc"));
+        assert!(!debug.contains("\"b\""));
+        assert!(debug.contains("***"));
+    }
+
+    #[test]
+    fn can_get_token_env_var_name() {
+        assert_eq!(repository_token_env_var("ppm"), "RV_REPO_TOKEN_PPM");
+        assert_eq!(
+            repository_token_env_var("my-repo.1"),
+            "RV_REPO_TOKEN_MY_REPO_1"
+        );
+    }
+}
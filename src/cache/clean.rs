@@ -0,0 +1,538 @@
+//! Removes the packages from the cache that are not used by any project anymore.
+//! rv doesn't know all the projects on the machine so each sync registers its lockfile in the
+//! cache, see `DiskCache::register_lockfile`, and the packages they lock are kept.
+//!
+//! The cache can also be capped in size: the least recently used package versions are evicted
+//! after each sync. The mtime of their folders is used as last access time since the atime is
+//! often not updated, it's bumped every time a package is installed from the cache.
+//! Their size is recorded at the same time so evicting doesn't need to walk the whole cache, and
+//! the ones used in the last few minutes are never evicted as another sync might be using them.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use fs_err as fs;
+use serde::Serialize;
+
+use crate::cache::utils::{get_current_system_path, hash_string};
+use crate::consts::DESCRIPTION_FILENAME;
+use crate::fs::mtime_recursive_filtered;
+use crate::lockfile::Source;
+use crate::{DiskCache, Lockfile, ResolvedDependency};
+
+/// Package versions used more recently than that are never evicted
+const RECENTLY_USED: Duration = Duration::from_secs(10 * 60);
+
+/// What the cache contained and what was removed from it
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CleanReport {
+    /// Size of all the cached package versions before cleaning, in bytes
+    pub total_size: u64,
+    /// Number of cached package versions before cleaning
+    pub entries: usize,
+    pub removed: usize,
+    /// Size of the removed package versions, in bytes
+    pub freed: u64,
+}
+
+impl fmt::Display for CleanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Cache: {} in {} package versions",
+            format_size(self.total_size),
+            self.entries
+        )?;
+        write!(
+            f,
+            "Removed {} package versions, freed {}",
+            self.removed,
+            format_size(self.freed)
+        )
+    }
+}
+
+/// Formats a size in bytes with decimal units, eg `1.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = u;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// Parses a size like `10GB`, `500 MB` or `1.5G`, in decimal units. A number without unit is in
+/// bytes.
+pub(crate) fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size `{s}`, expected something like `10GB`");
+    let value = s.trim();
+    let value = value.strip_suffix(['B', 'b']).unwrap_or(value);
+    let (number, multiplier) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 1e3),
+        Some('m' | 'M') => (&value[..value.len() - 1], 1e6),
+        Some('g' | 'G') => (&value[..value.len() - 1], 1e9),
+        Some('t' | 'T') => (&value[..value.len() - 1], 1e12),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    let bytes = number * multiplier;
+    if !bytes.is_finite() || bytes < 1.0 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+/// Total size of the files in that folder, in bytes
+pub(crate) fn dir_size(path: impl AsRef<Path>) -> u64 {
+    // Unlike for mtimes, nothing is ignored: a git clone takes space in its `.git` folder
+    mtime_recursive_filtered(path, &[], None, false)
+        .map(|stats| stats.total_size_bytes)
+        .unwrap_or(0)
+}
+
+/// Where the size of a cache entry is recorded, outside of it since the whole binary folder of
+/// a package gets linked in the library
+fn size_record_path(cache: &DiskCache, entry: &Path) -> PathBuf {
+    cache
+        .root
+        .join("sizes")
+        .join(hash_string(&entry.to_string_lossy()))
+}
+
+/// Records the current size of that cache entry, returning it
+fn record_size(cache: &DiskCache, entry: &Path) -> u64 {
+    let size = dir_size(entry);
+    let path = size_record_path(cache, entry);
+    let res =
+        fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, size.to_string()));
+    if let Err(e) = res {
+        log::debug!("Failed to record the size of {}: {e}", entry.display());
+    }
+    size
+}
+
+/// The size recorded for that cache entry, recording it first if it was not
+fn entry_size(cache: &DiskCache, entry: &Path) -> u64 {
+    fs::read_to_string(size_record_path(cache, entry))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or_else(|| record_size(cache, entry))
+}
+
+fn remove_entry(cache: &DiskCache, entry: &Path) -> Result<(), std::io::Error> {
+    fs::remove_dir_all(entry)?;
+    let _ = fs::remove_file(size_record_path(cache, entry));
+    Ok(())
+}
+
+fn subdirs(path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Folders named from `hash_string`, which are the ones holding packages from a given source
+fn is_hash(name: &str) -> bool {
+    name.len() == 10 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_r_version(name: &str) -> bool {
+    name.split_once('.')
+        .is_some_and(|(major, minor)| major.parse::<u32>().is_ok() && minor.parse::<u32>().is_ok())
+}
+
+/// All the package versions in the cache, as the folders that would be deleted to remove them:
+/// - `{hash}/src/{name}/{version}` for the source of packages from repositories
+/// - `{hash}/{R version}/{arch?}/{codename?}/{name}/{version}` for their binaries
+/// - `{hash}/{R version}/{arch?}/{codename?}/{sha}` for the binaries of git and url packages
+/// - `git/{hash}` and `urls/{hash}/{sha}` for their sources
+/// - `content/{R version}/{arch?}/{codename?}/{name}/{version}/{sha}` for their binaries kept by
+///   content
+///
+/// Package databases, logs and the other files at the root of the cache are not included.
+pub(crate) fn cache_entries(cache: &DiskCache) -> Vec<PathBuf> {
+    let mut entries = Vec::new();
+
+    for dir in subdirs(&cache.root) {
+        let name = dir.file_name().unwrap().to_string_lossy().to_string();
+        match name.as_str() {
+            "git" => entries.extend(subdirs(&dir)),
+            "urls" => entries.extend(subdirs(&dir).iter().flat_map(|d| subdirs(d))),
+            "content" => {
+                let system_path = get_current_system_path(&cache.system_info, cache.r_version);
+                let packages = system_path.iter().fold(dir.clone(), |p, c| p.join(c));
+                entries.extend(
+                    subdirs(&packages)
+                        .iter()
+                        .flat_map(|d| subdirs(d))
+                        .flat_map(|d| subdirs(&d)),
+                );
+            }
+            _ if is_hash(&name) => {
+                for child in subdirs(&dir) {
+                    let child_name = child.file_name().unwrap().to_string_lossy().to_string();
+                    if child_name == "src" {
+                        entries.extend(subdirs(&child).iter().flat_map(|d| subdirs(d)));
+                    } else if is_r_version(&child_name) {
+                        // The rest of the path only depends on the current system
+                        let system_path =
+                            get_current_system_path(&cache.system_info, cache.r_version);
+                        let binaries = system_path
+                            .iter()
+                            .skip(1)
+                            .fold(child.clone(), |p, c| p.join(c));
+                        for entry in subdirs(&binaries) {
+                            // A git/url binary directly contains the package folder
+                            let is_sha_folder = subdirs(&entry)
+                                .iter()
+                                .any(|p| p.join(DESCRIPTION_FILENAME).is_file());
+                            if is_sha_folder {
+                                entries.push(entry);
+                            } else {
+                                entries.extend(subdirs(&entry));
+                            }
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    entries
+}
+
+/// The cache folders of that package version, as found by `cache_entries`.
+/// Builtin and local packages are not in the cache.
+fn package_entries(cache: &DiskCache, source: &Source, name: &str, version: &str) -> Vec<PathBuf> {
+    if source.is_builtin() || matches!(source, Source::Local { .. }) {
+        return Vec::new();
+    }
+    let paths = cache.get_package_paths(source, Some(name), Some(version));
+    let mut entries = vec![paths.source, paths.binary];
+    if !matches!(source, Source::Repository { .. }) {
+        entries.push(cache.get_content_path(name, version, source.sha()));
+    }
+    entries
+}
+
+/// The cache folders of the packages in those lockfiles, for the R version of each lockfile
+fn referenced_entries(cache: &DiskCache, lockfiles: &[Lockfile]) -> HashSet<PathBuf> {
+    let mut out = HashSet::new();
+    for lockfile in lockfiles {
+        let mut cache = cache.clone();
+        cache.r_version = lockfile.r_version().major_minor();
+        for package in lockfile.packages() {
+            out.extend(package_entries(
+                &cache,
+                &package.source,
+                &package.name,
+                &package.version,
+            ));
+        }
+    }
+    out
+}
+
+/// The cache folders of those resolved dependencies
+pub(crate) fn resolved_entries(cache: &DiskCache, deps: &[ResolvedDependency]) -> HashSet<PathBuf> {
+    deps.iter()
+        .flat_map(|d| package_entries(cache, &d.source, &d.name, &d.version.original))
+        .collect()
+}
+
+/// Marks that package version as just used so it's the last one to be evicted, recording its
+/// size since it was just installed
+pub(crate) fn mark_used(cache: &DiskCache, dep: &ResolvedDependency) {
+    let now = filetime::FileTime::now();
+    for path in package_entries(cache, &dep.source, &dep.name, &dep.version.original)
+        .into_iter()
+        .filter(|p| p.is_dir())
+    {
+        record_size(cache, &path);
+        if let Err(e) = filetime::set_file_mtime(&path, now) {
+            log::debug!("Failed to update the mtime of {}: {e}", path.display());
+        }
+    }
+}
+
+/// Removes the package versions from the cache that are not in any of the lockfiles, or all of
+/// them if `all` is set.
+pub fn clean_cache(
+    cache: &DiskCache,
+    lockfiles: &[Lockfile],
+    all: bool,
+) -> Result<CleanReport, std::io::Error> {
+    let referenced = if all {
+        HashSet::new()
+    } else {
+        referenced_entries(cache, lockfiles)
+    };
+    let mut report = CleanReport::default();
+
+    for entry in cache_entries(cache) {
+        let size = dir_size(&entry);
+        report.total_size += size;
+        report.entries += 1;
+        if referenced.contains(&entry) {
+            continue;
+        }
+        log::debug!("Removing {} from the cache", entry.display());
+        remove_entry(cache, &entry)?;
+        report.removed += 1;
+        report.freed += size;
+    }
+
+    Ok(report)
+}
+
+/// Removes the least recently used package versions until the cache takes at most `max_size`
+/// bytes. The ones in `keep` or used in the last few minutes are never removed, even if that
+/// means staying above the limit.
+/// Sizes are the ones recorded when the packages were installed.
+pub(crate) fn evict_least_recently_used(
+    cache: &DiskCache,
+    max_size: u64,
+    keep: &HashSet<PathBuf>,
+) -> Result<CleanReport, std::io::Error> {
+    let mut entries: Vec<_> = cache_entries(cache)
+        .into_iter()
+        .map(|entry| {
+            let last_used = fs::metadata(&entry)
+                .map(|m| filetime::FileTime::from_last_modification_time(&m))
+                .unwrap_or_else(|_| filetime::FileTime::zero());
+            let size = entry_size(cache, &entry);
+            (last_used, size, entry)
+        })
+        .collect();
+    entries.sort();
+    let recent = filetime::FileTime::from_system_time(SystemTime::now() - RECENTLY_USED);
+
+    let mut report = CleanReport {
+        total_size: entries.iter().map(|(_, size, _)| size).sum(),
+        entries: entries.len(),
+        ..Default::default()
+    };
+    let mut size = report.total_size;
+    for (last_used, entry_size, entry) in entries {
+        if size <= max_size {
+            break;
+        }
+        if keep.contains(&entry) || last_used > recent {
+            continue;
+        }
+        log::debug!("Evicting {} from the cache", entry.display());
+        remove_entry(cache, &entry)?;
+        size -= entry_size;
+        report.removed += 1;
+        report.freed += entry_size;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SystemInfo;
+
+    fn make_package(folder: &Path, name: &str, size: usize) {
+        let path = folder.join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("DESCRIPTION"), format!("Package: {name}\n")).unwrap();
+        std::fs::write(path.join("data"), vec![0; size]).unwrap();
+    }
+
+    #[test]
+    fn can_format_sizes() {
+        assert_eq!(format_size(12), "12 B");
+        assert_eq!(format_size(1_500), "1.5 KB");
+        assert_eq!(format_size(20_000_000), "20.0 MB");
+        assert_eq!(format_size(3_210_000_000), "3.2 GB");
+    }
+
+    #[test]
+    fn can_parse_sizes() {
+        assert_eq!(parse_size("10GB"), Ok(10_000_000_000));
+        assert_eq!(parse_size("500 MB"), Ok(500_000_000));
+        assert_eq!(parse_size("1.5G"), Ok(1_500_000_000));
+        assert_eq!(parse_size("2k"), Ok(2_000));
+        assert_eq!(parse_size("1234"), Ok(1234));
+        for invalid in ["", "big", "0", "-1GB", "10GiB"] {
+            assert!(parse_size(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn only_removes_unreferenced_packages() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4".parse().unwrap(),
+            SystemInfo::from_os_info(),
+            tempdir.path(),
+        )
+        .unwrap();
+        let lockfile = Lockfile::load("src/tests/verify/rv.lock").unwrap().unwrap();
+
+        // R6 and cli are locked, old_pkg is not
+        let source = Source::Repository {
+            repository: url::Url::parse("https://cran.r-project.org/").unwrap(),
+        };
+        let mut referenced = Vec::new();
+        for (name, version) in [("R6", "2.5.1"), ("cli", "3.6.4")] {
+            let paths = cache.get_package_paths(&source, Some(name), Some(version));
+            make_package(&paths.binary, name, 100);
+            referenced.push(paths.binary);
+        }
+        let paths = cache.get_package_paths(&source, Some("cli"), Some("3.6.4"));
+        std::fs::create_dir_all(&paths.source).unwrap();
+        std::fs::write(paths.source.join("cli_3.6.4.tar.gz"), vec![0; 50]).unwrap();
+        referenced.push(paths.source);
+
+        let mut unreferenced = Vec::new();
+        for (name, version) in [("old_pkg", "1.0.0"), ("cli", "3.6.3")] {
+            let paths = cache.get_package_paths(&source, Some(name), Some(version));
+            make_package(&paths.binary, name, 1000);
+            unreferenced.push(paths.binary);
+        }
+        // A url package no project uses anymore
+        let url_source = Source::Url {
+            url: url::Url::parse("https://example.com/pkg.tar.gz").unwrap(),
+            sha: "abcdef0123456789".to_string(),
+        };
+        let paths = cache.get_package_paths(&url_source, None, None);
+        make_package(&paths.source, "pkg", 10);
+        make_package(&paths.binary, "pkg", 10);
+        let content_path = cache.get_content_path("pkg", "1.0.0", "abcdef0123456789");
+        make_package(&content_path, "pkg", 10);
+        unreferenced.extend([paths.source, paths.binary, content_path]);
+        // Not package versions, kept in all cases
+        let (db_path, _) = cache.get_package_db_entry("https://cran.r-project.org/");
+        std::fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+        std::fs::write(&db_path, "db").unwrap();
+        let (manifest_path, _) = cache.get_r_versions_manifest_entry();
+        std::fs::write(&manifest_path, "[]").unwrap();
+
+        let mut entries = cache_entries(&cache);
+        entries.sort();
+        let mut expected: Vec<_> = referenced.iter().chain(&unreferenced).cloned().collect();
+        expected.sort();
+        assert_eq!(entries, expected);
+
+        let report = clean_cache(&cache, std::slice::from_ref(&lockfile), false).unwrap();
+        assert_eq!(report.entries, 8);
+        assert_eq!(report.removed, 5);
+        assert!(report.freed > 2000 && report.freed < report.total_size);
+        for path in &referenced {
+            assert!(path.is_dir(), "{} should be kept", path.display());
+        }
+        for path in &unreferenced {
+            assert!(!path.exists(), "{} should be removed", path.display());
+        }
+        assert!(db_path.is_file());
+        assert!(manifest_path.is_file());
+
+        let report = clean_cache(&cache, &[lockfile], true).unwrap();
+        assert_eq!(report.entries, 3);
+        assert_eq!(report.removed, 3);
+        assert_eq!(report.freed, report.total_size);
+        assert!(cache_entries(&cache).is_empty());
+        assert!(db_path.is_file());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_packages_first() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4".parse().unwrap(),
+            SystemInfo::from_os_info(),
+            tempdir.path(),
+        )
+        .unwrap();
+        let source = Source::Repository {
+            repository: url::Url::parse("https://cran.r-project.org/").unwrap(),
+        };
+        // 5 packages of about 1KB each, pkg0 being the least recently used
+        let mut entries = Vec::new();
+        for i in 0..5 {
+            let name = format!("pkg{i}");
+            let paths = cache.get_package_paths(&source, Some(&name), Some("1.0.0"));
+            make_package(&paths.binary, &name, 1000);
+            filetime::set_file_mtime(
+                &paths.binary,
+                filetime::FileTime::from_unix_time(1_700_000_000 + i * 60, 0),
+            )
+            .unwrap();
+            entries.push(paths.binary);
+        }
+        let entry_size = dir_size(&entries[0]);
+        // pkg1 is in use by the current resolution
+        let keep = HashSet::from([entries[1].clone()]);
+
+        let report = evict_least_recently_used(&cache, entry_size * 2, &keep).unwrap();
+        assert_eq!(report.entries, 5);
+        assert_eq!(report.removed, 3);
+        assert_eq!(report.freed, entry_size * 3);
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(entry.is_dir(), [1, 4].contains(&i), "pkg{i}");
+        }
+
+        // Nothing to do once under the limit
+        let report = evict_least_recently_used(&cache, entry_size * 2, &keep).unwrap();
+        assert_eq!(report.removed, 0);
+    }
+
+    #[test]
+    fn eviction_uses_recorded_sizes_and_skips_recent_packages() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4".parse().unwrap(),
+            SystemInfo::from_os_info(),
+            tempdir.path(),
+        )
+        .unwrap();
+        let source = Source::Repository {
+            repository: url::Url::parse("https://cran.r-project.org/").unwrap(),
+        };
+        let old = cache.get_package_paths(&source, Some("old"), Some("1.0.0"));
+        make_package(&old.binary, "old", 1000);
+        let size = record_size(&cache, &old.binary);
+        // Not walked again
+        std::fs::write(old.binary.join("old").join("data"), vec![0; 5000]).unwrap();
+        assert_eq!(entry_size(&cache, &old.binary), size);
+        filetime::set_file_mtime(
+            &old.binary,
+            filetime::FileTime::from_unix_time(1_700_000_000, 0),
+        )
+        .unwrap();
+        // Just installed by another sync
+        let new = cache.get_package_paths(&source, Some("new"), Some("1.0.0"));
+        make_package(&new.binary, "new", 1000);
+
+        let report = evict_least_recently_used(&cache, 0, &HashSet::new()).unwrap();
+        assert_eq!(report.entries, 2);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.freed, size);
+        assert!(!old.binary.exists());
+        assert!(!size_record_path(&cache, &old.binary).exists());
+        assert!(new.binary.is_dir());
+    }
+}
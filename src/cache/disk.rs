@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
@@ -10,20 +11,51 @@ use fs_err as fs;
 use url::Url;
 
 use crate::cache::utils::{
-    get_current_system_path, get_packages_timeout, get_user_cache_dir, hash_string,
+    get_current_system_path, get_manifest_timeout, get_packages_timeout, get_user_cache_dir,
+    hash_string,
 };
 use crate::consts::BUILD_LOG_FILENAME;
+use crate::fs::{CopyOptions, copy_folder, replace_dir_atomically};
 use crate::lockfile::Source;
 use crate::package::{BuiltinPackages, Package, get_builtin_versions_from_library};
+use crate::sync::{LinkError, LinkMode};
 use crate::system_req::get_system_requirements;
 use crate::{RCmd, SystemInfo, Version};
 
+/// Whether the file exists and was modified less than `timeout` seconds ago
+fn is_fresh(path: &Path, timeout: u64) -> bool {
+    path.metadata().is_ok_and(|m| {
+        let modified = FileTime::from_last_modification_time(&m).unix_seconds() as u64;
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        now.saturating_sub(modified) <= timeout
+    })
+}
+
+/// Temporary files are dotfiles, see `DiskCache::register_lockfile`
+fn is_being_written(entry: &fs::DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
 #[derive(Debug, Clone)]
 pub struct PackagePaths {
     pub binary: PathBuf,
     pub source: PathBuf,
 }
 
+/// A package needs to be downloaded but rv is running offline
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error(
+    "{package}{} is not in the cache and can't be downloaded offline",
+    version.as_ref().map(|v| format!(" ({v})")).unwrap_or_default()
+)]
+pub struct MissingFromCache {
+    pub package: String,
+    pub version: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum InstallationStatus {
     Source,
@@ -73,6 +105,8 @@ pub struct DiskCache {
     /// How long the compiled databases are considered fresh for, in seconds
     /// Defaults to 3600s (1 hour)
     packages_timeout: u64,
+    /// How long the R versions manifest is considered fresh for, in seconds
+    manifest_timeout: u64,
     // TODO: check if it's worth keeping a hashmap of repo_url -> encoded
     // TODO: or if the overhead is the same as base64 directly
 }
@@ -105,6 +139,7 @@ impl DiskCache {
             system_info,
             r_version: r_version.major_minor(),
             packages_timeout: get_packages_timeout(),
+            manifest_timeout: get_manifest_timeout(),
         })
     }
 
@@ -187,6 +222,66 @@ impl DiskCache {
         self.root.join("git").join(encoded)
     }
 
+    /// Where an installed package is stored when keyed by its content rather than its source.
+    /// Like binaries, it depends on the OS and R version.
+    /// In practice it looks like: `CACHE_DIR/rv/content/r_maj.r_min/{arch?}/{codename?}/{name}/{version}/{sha[..10]}`
+    pub fn get_content_path(&self, name: &str, version: &str, sha: &str) -> PathBuf {
+        self.root
+            .join("content")
+            .join(get_current_system_path(&self.system_info, self.r_version))
+            .join(name)
+            .join(version)
+            .join(&sha[..10])
+    }
+
+    /// Copies the installed package in `folder` to the content cache, replacing any previous entry.
+    /// Files are hard linked when possible since nothing writes to installed packages.
+    /// Returns the folder containing the cached package.
+    pub(crate) fn store_package(
+        &self,
+        name: &str,
+        version: &str,
+        sha: &str,
+        folder: impl AsRef<Path>,
+    ) -> std::io::Result<PathBuf> {
+        let path = self.get_content_path(name, version, sha);
+        fs::create_dir_all(&path)?;
+        // Copy next to the final destination first so a failed copy doesn't leave a partial
+        // package in the cache
+        let staging = tempfile::Builder::new()
+            .prefix(".rv-store-")
+            .tempdir_in(&path)?;
+        copy_folder(
+            folder.as_ref(),
+            staging.path().join(name),
+            &CopyOptions {
+                use_hard_links: true,
+                ..Default::default()
+            },
+        )?;
+        replace_dir_atomically(&staging.path().join(name), &path.join(name))?;
+
+        Ok(path)
+    }
+
+    /// Links the package from the content cache to the library if it was stored before.
+    /// Returns false if it's not in the cache.
+    pub(crate) fn restore_package(
+        &self,
+        name: &str,
+        version: &str,
+        sha: &str,
+        library: impl AsRef<Path>,
+    ) -> Result<bool, LinkError> {
+        let path = self.get_content_path(name, version, sha);
+        if !path.join(name).is_dir() {
+            return Ok(false);
+        }
+        LinkMode::new().link_files(name, &path, library)?;
+
+        Ok(true)
+    }
+
     /// Search the cache for the related package db file.
     /// If it's not found or the entry is too old, the bool param will be false
     pub fn get_package_db_entry(&self, repo_url: &str) -> (PathBuf, bool) {
@@ -293,15 +388,126 @@ impl DiskCache {
         }
     }
 
-    pub fn get_system_requirements(&self) -> HashMap<String, Vec<String>> {
+    /// Where the CRAN mirror list is cached and whether it is recent enough to be used.
+    /// It uses the same timeout as the package databases.
+    pub fn get_cran_mirrors_entry(&self) -> (PathBuf, bool) {
+        let path = self.root.join("cran_mirrors.csv");
+        let is_fresh = is_fresh(&path, self.packages_timeout);
+        (path, is_fresh)
+    }
+
+    /// Where the R versions manifest is cached and whether it is recent enough to be used.
+    /// The timeout can be set with the `RV_MANIFEST_TTL` env var.
+    pub fn get_r_versions_manifest_entry(&self) -> (PathBuf, bool) {
+        let path = self.root.join("manifest.json");
+        let is_fresh = is_fresh(&path, self.manifest_timeout);
+        (path, is_fresh)
+    }
+
+    fn get_last_working_mirror_path(&self, repo_url: &str) -> PathBuf {
+        let encoded = hash_string(repo_url.trim_end_matches('/'));
+        self.root.join("mirrors").join(encoded)
+    }
+
+    /// The last URL we managed to download a package from for that repository, either the
+    /// repository itself or one of its mirrors
+    pub fn get_last_working_mirror(&self, repo_url: &str) -> Option<Url> {
+        let content = fs::read_to_string(self.get_last_working_mirror_path(repo_url)).ok()?;
+        Url::parse(content.trim()).ok()
+    }
+
+    pub(crate) fn set_last_working_mirror(&self, repo_url: &str, mirror: &Url) {
+        if self.get_last_working_mirror(repo_url).as_ref() == Some(mirror) {
+            return;
+        }
+        let path = self.get_last_working_mirror_path(repo_url);
+        // It's only an optimisation, no need to fail if we can't write it
+        let res = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, mirror.as_str()));
+        if let Err(e) = res {
+            log::warn!("Failed to save the last working mirror for {repo_url}: {e}");
+        }
+    }
+
+    /// One file per project containing the path to its lockfile, named from the hash of that
+    /// path so projects synced at the same time don't overwrite each other
+    fn get_known_lockfiles_path(&self) -> PathBuf {
+        self.root.join("projects")
+    }
+
+    /// The lockfiles of the projects synced on this machine, so cleaning the cache keeps the
+    /// packages they use. Lockfiles that don't exist anymore are skipped.
+    pub fn get_known_lockfiles(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.get_known_lockfiles_path()) else {
+            return Vec::new();
+        };
+        let mut lockfiles: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| !is_being_written(e))
+            .filter_map(|e| fs::read_to_string(e.path()).ok())
+            .map(PathBuf::from)
+            .filter(|p| p.is_file())
+            .collect();
+        lockfiles.sort();
+        lockfiles
+    }
+
+    /// Adds the lockfile to the known ones, dropping those that don't exist anymore
+    pub fn register_lockfile(&self, lockfile: impl AsRef<Path>) {
+        let Ok(lockfile) = std::path::absolute(lockfile.as_ref()) else {
+            return;
+        };
+        let folder = self.get_known_lockfiles_path();
+        let lockfile = lockfile.to_string_lossy();
+        let path = folder.join(hash_string(&lockfile));
+        // Written elsewhere first so nobody reads a partial path and thinks it's gone
+        let res = fs::create_dir_all(&folder)
+            .and_then(|_| tempfile::NamedTempFile::new_in(&folder))
+            .and_then(|mut f| {
+                f.write_all(lockfile.as_bytes())?;
+                f.persist(&path).map_err(|e| e.error)?;
+                Ok(())
+            });
+        // Only used when cleaning the cache, no need to fail if we can't write it
+        if let Err(e) = res {
+            log::warn!("Failed to register the lockfile in the cache: {e}");
+            return;
+        }
+
+        for entry in fs::read_dir(&folder).into_iter().flatten().flatten() {
+            if is_being_written(&entry) {
+                continue;
+            }
+            let is_stale = fs::read_to_string(entry.path()).is_ok_and(|p| !Path::new(&p).is_file());
+            if is_stale {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    fn get_system_requirements_path(&self) -> PathBuf {
         let (distrib, version) = self.system_info.sysreq_data();
         let key = format!("sysreq-{distrib}-{version}.json",);
-        let path = self.root.join(&key);
-        // TODO: Handle expiration, what would be a reasonable time?
+        self.root.join(&key)
+    }
+
+    /// Same as `get_system_requirements` but never fetches them if they are not in the cache
+    pub fn get_cached_system_requirements(&self) -> Option<HashMap<String, Vec<String>>> {
+        let path = self.get_system_requirements_path();
         if path.exists() {
             let content = fs::read_to_string(&path).expect("to work");
-            serde_json::from_str(&content).unwrap()
+            Some(serde_json::from_str(&content).unwrap())
         } else {
+            None
+        }
+    }
+
+    pub fn get_system_requirements(&self) -> HashMap<String, Vec<String>> {
+        // TODO: Handle expiration, what would be a reasonable time?
+        if let Some(sysreq) = self.get_cached_system_requirements() {
+            sysreq
+        } else {
+            let path = self.get_system_requirements_path();
             let sysreq = get_system_requirements(&self.system_info);
             let content = serde_json::to_string(&sysreq).unwrap();
             fs::write(&path, content).expect("to work");
@@ -309,3 +515,98 @@ impl DiskCache {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn can_register_lockfiles_concurrently() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &Version::from_str("4.4.1").unwrap(),
+            SystemInfo::from_os_info(),
+            tempdir.path().join("cache"),
+        )
+        .unwrap();
+        let lockfiles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = tempdir.path().join(format!("project{i}")).join("rv.lock");
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, "").unwrap();
+                path
+            })
+            .collect();
+
+        std::thread::scope(|s| {
+            for lockfile in &lockfiles {
+                let cache = &cache;
+                s.spawn(move || cache.register_lockfile(lockfile));
+            }
+        });
+        assert_eq!(cache.get_known_lockfiles(), lockfiles);
+
+        // Registering again changes nothing, and deleted projects are dropped
+        fs::remove_file(&lockfiles[0]).unwrap();
+        cache.register_lockfile(&lockfiles[1]);
+        assert_eq!(cache.get_known_lockfiles(), lockfiles[1..]);
+        assert_eq!(
+            fs::read_dir(cache.get_known_lockfiles_path())
+                .unwrap()
+                .count(),
+            7
+        );
+    }
+
+    #[test]
+    fn can_store_and_restore_packages() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &Version::from_str("4.4.1").unwrap(),
+            SystemInfo::from_os_info(),
+            tempdir.path().join("cache"),
+        )
+        .unwrap();
+        let sha = "6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b";
+        let installed = tempdir.path().join("installed").join("mypkg");
+        fs::create_dir_all(installed.join("R")).unwrap();
+        fs::write(installed.join("DESCRIPTION"), "Package: mypkg").unwrap();
+        fs::write(installed.join("R").join("mypkg"), "").unwrap();
+
+        let library = tempdir.path().join("library");
+        assert!(
+            !cache
+                .restore_package("mypkg", "0.1.0", sha, &library)
+                .unwrap()
+        );
+
+        let path = cache
+            .store_package("mypkg", "0.1.0", sha, &installed)
+            .unwrap();
+        assert_eq!(path, cache.get_content_path("mypkg", "0.1.0", sha));
+        // Only the package is left in the cache
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 1);
+
+        for project in ["a", "b"] {
+            let library = tempdir.path().join(project).join("library");
+            fs::create_dir_all(&library).unwrap();
+            assert!(
+                cache
+                    .restore_package("mypkg", "0.1.0", sha, &library)
+                    .unwrap()
+            );
+            assert_eq!(
+                fs::read_to_string(library.join("mypkg").join("DESCRIPTION")).unwrap(),
+                "Package: mypkg"
+            );
+            assert!(library.join("mypkg").join("R").join("mypkg").is_file());
+        }
+        // Another version is not there
+        assert!(
+            !cache
+                .restore_package("mypkg", "0.2.0", sha, &library)
+                .unwrap()
+        );
+    }
+}
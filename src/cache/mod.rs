@@ -1,6 +1,11 @@
+mod clean;
 pub mod disk;
 mod info;
 pub mod utils;
 
-pub use disk::{DiskCache, InstallationStatus, PackagePaths};
+pub use clean::{CleanReport, clean_cache, format_size};
+pub(crate) use clean::{
+    dir_size, evict_least_recently_used, mark_used, parse_size, resolved_entries,
+};
+pub use disk::{DiskCache, InstallationStatus, MissingFromCache, PackagePaths};
 pub use info::CacheInfo;
@@ -34,6 +34,16 @@ pub fn get_packages_timeout() -> u64 {
     }
 }
 
+/// How long the cached R versions manifest is used before fetching it again, in seconds.
+/// Invalid values are ignored.
+pub fn get_manifest_timeout() -> u64 {
+    std::env::var(crate::consts::MANIFEST_TTL_ENV_VAR_NAME)
+        .ok()
+        .and_then(|v| crate::http::parse_duration(&v).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(crate::consts::MANIFEST_TTL)
+}
+
 /// Try to get where the rv cache dir should be
 pub fn get_user_cache_dir() -> Option<PathBuf> {
     etcetera::base_strategy::choose_base_strategy()
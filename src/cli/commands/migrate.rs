@@ -7,8 +7,9 @@ use std::{
 use anyhow::{Result, anyhow};
 
 use crate::{
-    DiskCache, RenvLock, Repository, SystemInfo,
+    DiskCache, Lockfile, RenvLock, Repository, SystemInfo, Version,
     cli::context::load_databases,
+    consts::LOCKFILE_NAME,
     renv::{ResolvedRenv, UnresolvedRenv},
 };
 
@@ -31,12 +32,8 @@ pub fn migrate_renv(
     config_file: impl AsRef<Path>,
     strict_r_version: bool,
 ) -> Result<Vec<UnresolvedRenv>> {
-    // project name is the parent directory of the renv project
     let abs_renv_file = absolute(renv_file.as_ref())?;
-    let project_name = abs_renv_file
-        .parent()
-        .and_then(|p| p.to_str())
-        .unwrap_or("renv migrated project");
+    let project_name = project_name(abs_renv_file.parent());
 
     // use the repositories and r version from the renv.lock to determine the repository databases
     let renv_lock = RenvLock::parse_renv_lock(&renv_file)?;
@@ -44,31 +41,122 @@ pub fn migrate_renv(
         Ok(c) => c,
         Err(e) => return Err(anyhow!(e)),
     };
-    let databases = load_databases(&renv_lock.config_repositories(), &cache)?;
+    let databases = load_databases(&renv_lock.config_repositories(), &cache, false)?;
 
     // resolve the renv.lock file to determine the true source of packages
     let (resolved, unresolved) = renv_lock.resolve(&databases);
 
     // Write config out to the config file specified in the cli, even if config file is outside of the renv.lock project
-    let r_version = if strict_r_version {
-        &renv_lock.r_version().original
-    } else {
-        let [major, minor] = renv_lock.r_version().major_minor();
-        &format!("{major}.{minor}")
-    };
+    let config = render_config(
+        &renv_file.as_ref().to_string_lossy(),
+        project_name,
+        &config_r_version(&renv_lock, strict_r_version),
+        &renv_lock.config_repositories(),
+        &resolved,
+    );
+    let mut file = File::create(&config_file)?;
+    file.write_all(config.as_bytes())?;
+    Ok(unresolved)
+}
+
+/// Like `migrate_renv` but without looking at the repositories: each package is taken from the
+/// source recorded in the renv.lock, at the version recorded there.
+/// This also writes a `rv.lock` next to the config so the next sync installs exactly what renv
+/// had, unless some packages could not be converted.
+pub fn import_renv(
+    renv_file: impl AsRef<Path>,
+    config_file: impl AsRef<Path>,
+    strict_r_version: bool,
+) -> Result<Vec<UnresolvedRenv>> {
+    let renv_lock = RenvLock::parse_renv_lock(&renv_file)?;
+    let abs_renv_file = absolute(renv_file.as_ref())?;
+    import_lock(
+        &renv_lock,
+        renv_file,
+        project_name(abs_renv_file.parent()),
+        config_file,
+        strict_r_version,
+    )
+}
+
+/// Same as `import_renv` for a packrat.lock
+pub fn import_packrat(
+    packrat_file: impl AsRef<Path>,
+    config_file: impl AsRef<Path>,
+    strict_r_version: bool,
+) -> Result<Vec<UnresolvedRenv>> {
+    let packrat_lock = RenvLock::parse_packrat_lock(&packrat_file)?;
+    // the lockfile is in the packrat folder of the project
+    let abs_packrat_file = absolute(packrat_file.as_ref())?;
+    import_lock(
+        &packrat_lock,
+        packrat_file,
+        project_name(abs_packrat_file.parent().and_then(|p| p.parent())),
+        config_file,
+        strict_r_version,
+    )
+}
+
+/// Writes the renv.lock matching the lockfile so the project can also be used with renv
+pub fn export_renv(
+    lockfile: &Lockfile,
+    repositories: &[Repository],
+    r_version: &Version,
+    renv_file: impl AsRef<Path>,
+) -> Result<()> {
+    let renv_lock = RenvLock::from_lockfile(lockfile, repositories, r_version);
+    let mut file = File::create(renv_file)?;
+    file.write_all(renv_lock.to_json().as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+
+fn import_lock(
+    renv_lock: &RenvLock,
+    renv_file: impl AsRef<Path>,
+    project_name: &str,
+    config_file: impl AsRef<Path>,
+    strict_r_version: bool,
+) -> Result<Vec<UnresolvedRenv>> {
+    let (resolved, unresolved, lockfile) = renv_lock.import();
 
     let config = render_config(
         &renv_file.as_ref().to_string_lossy(),
         project_name,
-        r_version,
+        &config_r_version(renv_lock, strict_r_version),
         &renv_lock.config_repositories(),
         &resolved,
     );
     let mut file = File::create(&config_file)?;
     file.write_all(config.as_bytes())?;
+
+    // A partial lockfile would be resolved again anyway
+    if unresolved.is_empty() {
+        let lockfile_path = absolute(config_file.as_ref())?
+            .parent()
+            .map(|p| p.join(LOCKFILE_NAME))
+            .ok_or_else(|| anyhow!("Invalid config file path"))?;
+        lockfile.save(lockfile_path)?;
+    }
     Ok(unresolved)
 }
 
+/// The project name is the directory of the renv project
+fn project_name(project_dir: Option<&Path>) -> &str {
+    project_dir
+        .and_then(|p| p.to_str())
+        .unwrap_or("renv migrated project")
+}
+
+fn config_r_version(renv_lock: &RenvLock, strict_r_version: bool) -> String {
+    if strict_r_version {
+        renv_lock.r_version().original.clone()
+    } else {
+        let [major, minor] = renv_lock.r_version().major_minor();
+        format!("{major}.{minor}")
+    }
+}
+
 fn render_config(
     renv_file: &str,
     project_name: &str,
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use fs_err::write;
+use toml_edit::{Array, DocumentMut, InlineTable, Value};
+use url::Url;
+
+use crate::add::AddError;
+use crate::read_and_verify_config;
+
+/// Puts the mirror first in the `mirrors` of the repository with the given alias, or of the
+/// first repository if no alias is given, so it's the first one tried if the repository itself
+/// can't be reached.
+pub fn set_mirror(
+    config_file: impl AsRef<Path>,
+    mirror: &Url,
+    alias: Option<&str>,
+) -> Result<(), MirrorError> {
+    let config_file = config_file.as_ref();
+    let mut doc = read_and_verify_config(config_file)?;
+    add_mirror(&mut doc, mirror, alias)?;
+    write(config_file, doc.to_string())?;
+    Ok(())
+}
+
+fn add_mirror(doc: &mut DocumentMut, mirror: &Url, alias: Option<&str>) -> Result<(), MirrorError> {
+    let not_found = || MirrorError::RepositoryNotFound(alias.map(|a| a.to_string()));
+    let repositories = doc
+        .get_mut("project")
+        .and_then(|p| p.get_mut("repositories"))
+        .and_then(|r| r.as_array_mut())
+        .ok_or_else(not_found)?;
+    let repository: &mut InlineTable = repositories
+        .iter_mut()
+        .filter_map(|r| r.as_inline_table_mut())
+        .find(|r| alias.is_none_or(|a| r.get("alias").and_then(|x| x.as_str()) == Some(a)))
+        .ok_or_else(not_found)?;
+
+    if !repository.contains_key("mirrors") {
+        // The last value has the space before the closing brace, it would end up before the comma
+        if let Some((_, last)) = repository.iter_mut().last() {
+            last.decor_mut().set_suffix("");
+        }
+        let mut mirrors = Value::Array(Array::new());
+        mirrors.decor_mut().set_suffix(" ");
+        repository.insert("mirrors", mirrors);
+    }
+    let mirrors = repository
+        .entry("mirrors")
+        .or_insert_with(|| Value::Array(Array::new()))
+        .as_array_mut()
+        .ok_or(MirrorError::InvalidMirrors)?;
+    let same = |v: &Value| {
+        v.as_str()
+            .is_some_and(|m| m.trim_end_matches('/') == mirror.as_str().trim_end_matches('/'))
+    };
+    mirrors.retain(|v| !same(v));
+    mirrors.insert(0, mirror.as_str());
+    mirrors.fmt();
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MirrorError {
+    #[error(transparent)]
+    Config(#[from] AddError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{}", match .0 {
+        Some(alias) => format!("Repository `{alias}` not found in the config"),
+        None => "No repositories in the config".to_string(),
+    })]
+    RepositoryNotFound(Option<String>),
+    #[error("The `mirrors` of the repository need to be an array")]
+    InvalidMirrors,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc() -> DocumentMut {
+        r#"[project]
+name = "test"
+r_version = "4.4"
+repositories = [
+    { alias = "cran", url = "https://cran.r-project.org" },
+    { alias = "ppm", url = "https://packagemanager.posit.co/cran/latest", mirrors = ["https://example.com"] },
+]
+dependencies = []
+"#
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn can_set_mirror() {
+        let mut doc = doc();
+        let mirror = Url::parse("https://cran.csiro.au/").unwrap();
+        add_mirror(&mut doc, &mirror, None).unwrap();
+        add_mirror(&mut doc, &mirror, Some("ppm")).unwrap();
+        // Setting a mirror already there moves it first
+        add_mirror(
+            &mut doc,
+            &Url::parse("https://example.com").unwrap(),
+            Some("ppm"),
+        )
+        .unwrap();
+        insta::assert_snapshot!(doc.to_string());
+    }
+
+    #[test]
+    fn errors_on_unknown_repository() {
+        let mirror = Url::parse("https://cran.csiro.au/").unwrap();
+        assert!(add_mirror(&mut doc(), &mirror, Some("bioc")).is_err());
+    }
+}
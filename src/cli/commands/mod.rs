@@ -1,7 +1,15 @@
 mod init;
 mod migrate;
+mod mirror;
+mod outdated;
 mod tree;
+mod verify;
+mod why;
 
 pub use init::{find_r_repositories, init, init_structure};
-pub use migrate::migrate_renv;
+pub use migrate::{export_renv, import_packrat, import_renv, migrate_renv};
+pub use mirror::{MirrorError, set_mirror};
+pub use outdated::{OUTDATED_HEADER, outdated};
 pub use tree::tree;
+pub use verify::{Verification, VersionMismatch, verify};
+pub use why::why;
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::lockfile::Source;
+use crate::{ConfigDependency, Lockfile, RepositoryDatabase, Version, VersionRequirement};
+
+/// A locked package with a newer version in its repository
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OutdatedPackage<'a> {
+    pub name: &'a str,
+    pub current: &'a str,
+    pub latest: &'a Version,
+    /// Whether the latest version satisfies all the requirements on that package, from the
+    /// config and from the other packages depending on it
+    pub compatible: bool,
+    /// Whether the latest version has a higher major version than the current one
+    pub major: bool,
+}
+
+impl fmt::Display for OutdatedPackage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        write!(
+            f,
+            "{:<20} {:<12} {:<12} {:<10} {}",
+            self.name,
+            self.current,
+            self.latest.original,
+            yes_no(self.compatible),
+            yes_no(self.major)
+        )
+    }
+}
+
+/// The header matching the `Display` of `OutdatedPackage`
+pub const OUTDATED_HEADER: &str = "Package              Current      Latest       Compatible Major";
+
+/// Compares each package from a repository in the lockfile with the latest version available
+/// in that repository for the given R version, returning the ones that can be updated in
+/// alphabetical order.
+/// Packages from other sources or from a repository we don't have the database of are skipped.
+pub fn outdated<'a>(
+    lockfile: &'a Lockfile,
+    dependencies: &[ConfigDependency],
+    databases: &'a [(RepositoryDatabase, bool)],
+    r_version: &Version,
+) -> Vec<OutdatedPackage<'a>> {
+    let mut out = Vec::new();
+
+    for package in lockfile.packages() {
+        let Source::Repository { repository } = &package.source else {
+            continue;
+        };
+        let Some((db, _)) = databases.iter().find(|(db, _)| {
+            db.url.trim_end_matches('/') == repository.as_str().trim_end_matches('/')
+        }) else {
+            continue;
+        };
+        let Ok(current) = package.version.parse::<Version>() else {
+            continue;
+        };
+        let Some(latest) = db.latest_version(&package.name, r_version) else {
+            continue;
+        };
+        if latest <= &current {
+            continue;
+        }
+
+        let compatible =
+            requirements(lockfile, dependencies, &package.name).all(|req| req.is_satisfied(latest));
+        out.push(OutdatedPackage {
+            name: &package.name,
+            current: &package.version,
+            latest,
+            compatible,
+            major: latest.major_minor()[0] > current.major_minor()[0],
+        });
+    }
+
+    out.sort_by_key(|p| p.name);
+    out
+}
+
+/// All the version requirements on that package, from the config and from the locked packages
+/// depending on it
+fn requirements<'a>(
+    lockfile: &'a Lockfile,
+    dependencies: &'a [ConfigDependency],
+    name: &'a str,
+) -> impl Iterator<Item = &'a VersionRequirement> {
+    let from_config = dependencies
+        .iter()
+        .filter(move |d| d.name() == name)
+        .filter_map(|d| d.version_requirement());
+    let from_lockfile = lockfile
+        .packages()
+        .iter()
+        .flat_map(|p| p.dependencies.iter().chain(&p.suggests))
+        .filter(move |d| d.name() == name)
+        .filter_map(|d| d.version_requirement());
+    from_config.chain(from_lockfile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn can_find_outdated_packages() {
+        let config = Config::from_file("src/tests/outdated/rproject.toml").unwrap();
+        let lockfile = Lockfile::load("src/tests/outdated/rv.lock")
+            .unwrap()
+            .unwrap();
+        let mut db = RepositoryDatabase::new("https://cran.r-project.org");
+        db.parse_source(&std::fs::read_to_string("src/tests/outdated/PACKAGES").unwrap());
+        let databases = vec![(db, false)];
+
+        let res = outdated(
+            &lockfile,
+            config.dependencies(),
+            &databases,
+            &"4.4.1".parse().unwrap(),
+        );
+        let res: Vec<_> = res
+            .iter()
+            .map(|p| {
+                (
+                    p.name,
+                    p.current,
+                    p.latest.original.as_str(),
+                    p.compatible,
+                    p.major,
+                )
+            })
+            .collect();
+        assert_eq!(
+            res,
+            vec![
+                // Over the `< 3.0.0` of the config
+                ("R6", "2.5.1", "3.0.1", false, true),
+                // Over the `< 4.0.0` of pkgA
+                ("cli", "3.6.4", "4.0.0", false, true),
+                ("glue", "1.7.0", "1.8.0", true, false),
+            ]
+        );
+    }
+}
@@ -3,7 +3,8 @@ use crate::lockfile::Source;
 use crate::package::PackageType;
 use crate::{ResolvedDependency, UnresolvedDependency, Version};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 enum NodeKind {
@@ -78,8 +79,18 @@ impl TreeNode<'_> {
         }
     }
 
-    fn print_recursive(
-        &self,
+    /// Whether the children of that node are within `max_depth`
+    fn shows_children(&self, current_depth: usize, max_depth: Option<usize>) -> bool {
+        !self.children.is_empty() && max_depth.is_none_or(|d| current_depth < d)
+    }
+
+    /// Packages whose dependencies were already displayed are marked with `(*)` and
+    /// not expanded again, like `cargo tree` does
+    #[allow(clippy::too_many_arguments)]
+    fn render_recursive<'a>(
+        &'a self,
+        out: &mut String,
+        seen: &mut HashSet<&'a str>,
         prefix: &str,
         kind: NodeKind,
         current_depth: usize,
@@ -92,12 +103,23 @@ impl TreeNode<'_> {
             }
         }
 
-        println!(
-            "{prefix}{} {} [{}]",
+        let shows_children = self.shows_children(current_depth, max_depth);
+        let already_seen = shows_children && seen.contains(self.name);
+        writeln!(
+            out,
+            "{prefix}{} {} [{}]{}",
             kind.prefix(),
             self.name,
-            self.get_details(show_sys_deps)
-        );
+            self.get_details(show_sys_deps),
+            if already_seen { " (*)" } else { "" },
+        )
+        .unwrap();
+        if already_seen {
+            return;
+        }
+        if shows_children {
+            seen.insert(self.name);
+        }
 
         let child_prefix = match kind {
             NodeKind::Normal => &format!("{prefix}│ "),
@@ -110,7 +132,9 @@ impl TreeNode<'_> {
             } else {
                 NodeKind::Normal
             };
-            child.print_recursive(
+            child.render_recursive(
+                out,
+                seen,
                 child_prefix,
                 child_kind,
                 current_depth + 1,
@@ -123,26 +147,30 @@ impl TreeNode<'_> {
 
 fn recursive_finder<'d>(
     name: &'d str,
-    deps: Vec<&'d str>,
+    edges: &HashMap<&'d str, Vec<&'d str>>,
     deps_by_name: &HashMap<&'d str, &'d ResolvedDependency>,
     unresolved_deps_by_name: &HashMap<&'d str, &'d UnresolvedDependency>,
-    context: &'d CliContext,
+    system_dependencies: &'d HashMap<String, Vec<String>>,
+    ancestors: &mut Vec<&'d str>,
 ) -> TreeNode<'d> {
     if let Some(resolved) = deps_by_name.get(name) {
-        let sys_deps = context.system_dependencies.get(name);
-        let children: Vec<_> = deps
-            .iter()
-            .map(|x| {
-                let resolved = deps_by_name[*x];
-                recursive_finder(
+        let sys_deps = system_dependencies.get(name);
+        // A dependency cycle would recurse forever, it is reported by the resolver
+        ancestors.push(name);
+        let mut children = Vec::new();
+        for x in edges.get(name).into_iter().flatten() {
+            if !ancestors.contains(x) {
+                children.push(recursive_finder(
                     x,
-                    resolved.all_dependencies_names(),
+                    edges,
                     deps_by_name,
                     unresolved_deps_by_name,
-                    context,
-                )
-            })
-            .collect();
+                    system_dependencies,
+                    ancestors,
+                ));
+            }
+        }
+        ancestors.pop();
 
         TreeNode {
             name,
@@ -182,9 +210,14 @@ pub struct Tree<'a> {
 }
 
 impl Tree<'_> {
-    pub fn print(&self, max_depth: Option<usize>, show_sys_deps: bool) {
+    fn render(&self, max_depth: Option<usize>, show_sys_deps: bool) -> String {
+        let mut out = String::new();
+        let mut seen = HashSet::new();
         for (i, tree) in self.nodes.iter().enumerate() {
-            println!("▶ {} [{}]", tree.name, tree.get_details(show_sys_deps),);
+            writeln!(out, "▶ {} [{}]", tree.name, tree.get_details(show_sys_deps)).unwrap();
+            if tree.shows_children(1, max_depth) {
+                seen.insert(tree.name);
+            }
 
             // Print children with standard indentation
             for (j, child) in tree.children.iter().enumerate() {
@@ -193,20 +226,62 @@ impl Tree<'_> {
                 } else {
                     NodeKind::Normal
                 };
-                child.print_recursive("", child_kind, 2, max_depth, show_sys_deps);
+                child.render_recursive(
+                    &mut out,
+                    &mut seen,
+                    "",
+                    child_kind,
+                    2,
+                    max_depth,
+                    show_sys_deps,
+                );
             }
 
             if i < self.nodes.len() - 1 {
-                println!();
+                out.push('\n');
             }
         }
+        out
+    }
+
+    pub fn print(&self, max_depth: Option<usize>, show_sys_deps: bool) {
+        print!("{}", self.render(max_depth, show_sys_deps));
     }
 }
 
+/// Builds the tree of the project dependencies, or only of `package` if set.
+/// With `invert`, the children of a package are the packages depending on it instead.
 pub fn tree<'a>(
     context: &'a CliContext,
     resolved_deps: &'a [ResolvedDependency],
     unresolved_deps: &'a [UnresolvedDependency],
+    package: Option<&'a str>,
+    invert: bool,
+) -> Tree<'a> {
+    let roots = match package {
+        Some(p) => vec![p],
+        None => context
+            .config
+            .dependencies()
+            .iter()
+            .map(|d| d.name())
+            .collect(),
+    };
+    build_tree(
+        roots,
+        resolved_deps,
+        unresolved_deps,
+        invert,
+        &context.system_dependencies,
+    )
+}
+
+fn build_tree<'a>(
+    roots: Vec<&'a str>,
+    resolved_deps: &'a [ResolvedDependency],
+    unresolved_deps: &'a [UnresolvedDependency],
+    invert: bool,
+    system_dependencies: &'a HashMap<String, Vec<String>>,
 ) -> Tree<'a> {
     let deps_by_name: HashMap<_, _> = resolved_deps.iter().map(|d| (d.name.as_ref(), d)).collect();
     let unresolved_deps_by_name: HashMap<_, _> = unresolved_deps
@@ -214,36 +289,72 @@ pub fn tree<'a>(
         .map(|d| (d.name.as_ref(), d))
         .collect();
 
-    let mut out = Vec::new();
+    let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+    for dep in resolved_deps {
+        for child in dep.all_dependencies_names() {
+            if invert {
+                edges.entry(child).or_default().push(dep.name.as_ref());
+            } else {
+                edges.entry(dep.name.as_ref()).or_default().push(child);
+            }
+        }
+    }
+    for children in edges.values_mut() {
+        children.sort_unstable();
+    }
 
-    for top_level_dep in context.config.dependencies() {
-        if let Some(found) = deps_by_name.get(top_level_dep.name()) {
-            out.push(recursive_finder(
-                found.name.as_ref(),
-                found.all_dependencies_names(),
+    let nodes = roots
+        .into_iter()
+        .map(|name| {
+            recursive_finder(
+                name,
+                &edges,
                 &deps_by_name,
                 &unresolved_deps_by_name,
-                context,
-            ));
-        } else {
-            let unresolved = unresolved_deps_by_name[top_level_dep.name()];
-            out.push(TreeNode {
-                name: top_level_dep.name(),
-                version: None,
-                source: None,
-                package_type: None,
-                sys_deps: None,
-                error: unresolved.error.clone(),
-                version_req: unresolved
-                    .version_requirement
-                    .clone()
-                    .map(|x| x.to_string()),
-                resolved: false,
-                children: vec![],
-                ignored: false,
+                system_dependencies,
+                &mut Vec::new(),
+            )
+        })
+        .collect();
+
+    Tree { nodes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lockfile;
+    use crate::cache::InstallationStatus;
+
+    #[test]
+    fn can_render_tree() {
+        let lockfile = Lockfile::load("src/tests/lockfiles/tree.lock")
+            .unwrap()
+            .unwrap();
+        let mut names: Vec<_> = lockfile.package_names().into_iter().collect();
+        names.sort_unstable();
+        let resolved: Vec<_> = names
+            .into_iter()
+            .map(|name| {
+                ResolvedDependency::from_locked_package(
+                    lockfile.get_package(name, None).unwrap(),
+                    InstallationStatus::Absent,
+                )
             })
+            .collect();
+        let sys_deps = HashMap::new();
+
+        let mut out = String::new();
+        for (roots, depth, invert) in [
+            (vec!["gsm", "lifecycle"], None, false),
+            (vec!["gsm", "lifecycle"], Some(2), false),
+            (vec!["glue"], None, true),
+        ] {
+            let tree = build_tree(roots.clone(), &resolved, &[], invert, &sys_deps);
+            writeln!(out, "roots={roots:?} depth={depth:?} invert={invert}").unwrap();
+            out.push_str(&tree.render(depth, false));
+            out.push('\n');
         }
+        insta::assert_snapshot!(out);
     }
-
-    Tree { nodes: out }
 }
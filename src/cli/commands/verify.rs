@@ -0,0 +1,147 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{Library, Lockfile, Version};
+
+/// A package installed with a different version than the locked one
+#[derive(Debug, PartialEq, Serialize)]
+pub struct VersionMismatch<'a> {
+    pub name: &'a str,
+    pub locked: &'a str,
+    pub installed: &'a str,
+}
+
+/// The differences between the lockfile and the packages actually installed in the library
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Verification<'a> {
+    /// Locked packages that are not in the library
+    pub missing: Vec<&'a str>,
+    /// Packages in the library that are not in the lockfile, eg installed manually
+    pub extra: Vec<&'a str>,
+    pub mismatched: Vec<VersionMismatch<'a>>,
+    /// Folders of the library without a readable DESCRIPTION file
+    pub broken: Vec<&'a str>,
+}
+
+impl Verification<'_> {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.mismatched.is_empty()
+            && self.broken.is_empty()
+    }
+}
+
+impl fmt::Display for Verification<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "The library matches the lockfile");
+        }
+        let mut lines = Vec::new();
+        for name in &self.missing {
+            lines.push(format!("- {name} is missing"));
+        }
+        for name in &self.extra {
+            lines.push(format!("+ {name} is not in the lockfile"));
+        }
+        for m in &self.mismatched {
+            lines.push(format!(
+                "~ {} is installed in version {} but {} is locked",
+                m.name, m.installed, m.locked
+            ));
+        }
+        for name in &self.broken {
+            lines.push(format!("! {name} has no valid DESCRIPTION file"));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Compares the packages found in the library, see `Library::find_content`, with the lockfile.
+/// Builtin packages are installed with R, not in the library, so they are not checked.
+/// Everything is sorted alphabetically.
+pub fn verify<'a>(lockfile: &'a Lockfile, library: &'a Library) -> Verification<'a> {
+    let mut out = Verification::default();
+
+    for package in lockfile.packages() {
+        if package.source.is_builtin() || library.broken.contains(&package.name) {
+            continue;
+        }
+        match library.packages.get(&package.name) {
+            None => out.missing.push(package.name.as_str()),
+            Some(installed) => {
+                // Compare as versions first, `1.0` and `1.0.0` are the same
+                let same = package
+                    .version
+                    .parse::<Version>()
+                    .is_ok_and(|v| &v == installed)
+                    || package.version == installed.original;
+                if !same {
+                    out.mismatched.push(VersionMismatch {
+                        name: &package.name,
+                        locked: &package.version,
+                        installed: &installed.original,
+                    });
+                }
+            }
+        }
+    }
+
+    out.extra = library
+        .packages
+        .keys()
+        .filter(|name| lockfile.get_package(name, None).is_none())
+        .map(|name| name.as_str())
+        .collect();
+    out.broken = library.broken.iter().map(|name| name.as_str()).collect();
+
+    out.missing.sort();
+    out.extra.sort();
+    out.mismatched.sort_by_key(|m| m.name);
+    out.broken.sort();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SystemInfo;
+
+    #[test]
+    fn can_verify_library() {
+        let lockfile = Lockfile::load("src/tests/verify/rv.lock").unwrap().unwrap();
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut library = Library::new(tempdir.path(), &SystemInfo::from_os_info(), [4, 4]);
+        for (name, version) in [
+            ("R6", "2.5.1"),
+            ("cli", "3.6.3"),
+            ("glue", "1.7.0"),
+            ("stray", "0.1.0"),
+        ] {
+            let path = library.path.join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(
+                path.join("DESCRIPTION"),
+                format!("Package: {name}\nVersion: {version}\n"),
+            )
+            .unwrap();
+        }
+        library.find_content();
+
+        let res = verify(&lockfile, &library);
+        assert!(!res.is_ok());
+        assert_eq!(res.missing, vec!["pkgA"]);
+        assert_eq!(res.extra, vec!["stray"]);
+        assert_eq!(
+            res.mismatched,
+            vec![VersionMismatch {
+                name: "cli",
+                locked: "3.6.4",
+                installed: "3.6.3",
+            }]
+        );
+        assert!(res.broken.is_empty());
+        insta::assert_snapshot!(res.to_string());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::Lockfile;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// A package in a dependency path, with the version requirement its parent has on it
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WhyStep<'a> {
+    name: &'a str,
+    requirement: Option<String>,
+}
+
+impl fmt::Display for WhyStep<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(req) = &self.requirement {
+            write!(f, " {req}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A chain of dependencies from a project dependency down to the package we are looking for
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WhyPath<'a> {
+    steps: Vec<WhyStep<'a>>,
+}
+
+impl fmt::Display for WhyPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<_> = self.steps.iter().map(|s| s.to_string()).collect();
+        write!(f, "{}", steps.join(" → "))
+    }
+}
+
+/// Finds the shortest path from each of the `top_level` packages to `package` in the lockfile.
+/// Top level packages that do not depend on it are skipped, a package listed in `top_level`
+/// gets a path with only itself.
+pub fn why<'a>(lockfile: &'a Lockfile, top_level: &[&'a str], package: &str) -> Vec<WhyPath<'a>> {
+    let mut out = Vec::new();
+
+    for root in top_level {
+        // The parent of each package we reached and the requirement it has on it
+        let mut parents: HashMap<&str, Option<(&str, Option<String>)>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        parents.insert(root, None);
+        queue.push_back(*root);
+
+        while let Some(name) = queue.pop_front() {
+            if name == package {
+                let mut steps = Vec::new();
+                let mut current = Some(name);
+                while let Some(name) = current {
+                    let parent = &parents[name];
+                    steps.push(WhyStep {
+                        name,
+                        requirement: parent.as_ref().and_then(|(_, req)| req.clone()),
+                    });
+                    current = parent.as_ref().map(|(p, _)| *p);
+                }
+                steps.reverse();
+                out.push(WhyPath { steps });
+                break;
+            }
+
+            let Some(locked) = lockfile.get_package(name, None) else {
+                continue;
+            };
+            let suggests = locked.suggests.iter().filter(|_| locked.install_suggests());
+            for dep in locked.dependencies.iter().chain(suggests) {
+                if !parents.contains_key(dep.name()) {
+                    parents.insert(
+                        dep.name(),
+                        Some((
+                            locked.name.as_str(),
+                            dep.version_requirement().map(|r| r.to_string()),
+                        )),
+                    );
+                    queue.push_back(dep.name());
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(package: &str) -> Vec<String> {
+        let lockfile = Lockfile::load("src/tests/lockfiles/tree.lock")
+            .unwrap()
+            .unwrap();
+        why(&lockfile, &["gsm", "lifecycle"], package)
+            .into_iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn can_explain_direct_dependency() {
+        assert_eq!(paths("gsm"), vec!["gsm"]);
+        assert_eq!(
+            paths("cli"),
+            vec!["gsm → cli (>= 3.6.0)", "lifecycle → cli"]
+        );
+    }
+
+    #[test]
+    fn can_explain_transitive_dependency() {
+        assert_eq!(
+            paths("utils"),
+            vec!["gsm → glue → utils", "lifecycle → cli → utils"]
+        );
+    }
+
+    #[test]
+    fn no_paths_for_missing_package() {
+        assert!(paths("dplyr").is_empty());
+    }
+}
@@ -2,12 +2,13 @@
 
 use crate::cli::utils::write_err;
 use crate::consts::{RUNIVERSE_PACKAGES_API_PATH, RV_DIR_NAME, STAGING_DIR_NAME};
-use crate::lockfile::Lockfile;
+use crate::lockfile::{Lockfile, needs_resolve};
 use crate::package::Package;
 use crate::utils::create_spinner;
 use crate::{
-    Config, DiskCache, Library, RCommandLine, Repository, RepositoryDatabase, SystemInfo, Version,
-    find_r_version_command, get_package_file_urls, http, system_req, timeit,
+    Config, DiskCache, Http, Library, RCommandLine, RVersionFile, Repository, RepositoryDatabase,
+    SystemInfo, Version, find_r_version_command, get_package_file_urls, get_r_versions_manifest,
+    http, system_req, timeit,
 };
 use anyhow::{Result, anyhow, bail};
 use fs_err as fs;
@@ -16,6 +17,22 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use url::Url;
 
+/// Whether the lockfile has everything the config needs, so we can resolve without the
+/// repositories.
+/// If the config is the one on disk and the lockfile was written after its last change, we don't
+/// check the dependencies one by one. A config edited in memory, eg for a dry run, is always
+/// checked.
+fn lockfile_is_enough(
+    lockfile: &Lockfile,
+    config: &Config,
+    config_path: &Path,
+    lockfile_path: &Path,
+) -> bool {
+    let config_on_disk = || Config::from_file(config_path).is_ok_and(|c| &c == config);
+    (!needs_resolve(config_path, lockfile_path) && config_on_disk())
+        || lockfile.can_resolve(config.dependencies(), config.repositories())
+}
+
 /// Method on how to find the R Version on the system
 #[derive(Debug, Clone, PartialEq)]
 pub enum RCommandLookup {
@@ -41,9 +58,55 @@ impl From<Option<Version>> for RCommandLookup {
     }
 }
 
+/// The R version pinned by a `.R-version` file, which takes precedence over the config
+fn pinned_r_version(file: &RVersionFile, config_r_version: &Version) -> Result<Version> {
+    let r_version = file.resolve(|| -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+        // Only the manifest is needed so the R version doesn't matter here
+        let cache = DiskCache::new(config_r_version, SystemInfo::from_os_info())?;
+        Ok(get_r_versions_manifest(&Http {}, &cache, false)?)
+    })?;
+    log::debug!("Using R {r_version} from {}", file.path.display());
+    check_pinned_r_version(&r_version, config_r_version, &file.path)?;
+    Ok(r_version)
+}
+
+/// A `.R-version` file can only be more precise than the config, eg `4.4.1` with `4.4` in the
+/// config. Any other version is an error rather than silently ignoring the lockfile.
+pub fn check_pinned_r_version(
+    r_version: &Version,
+    config_r_version: &Version,
+    path: &Path,
+) -> Result<()> {
+    if !config_r_version.hazy_match(r_version) && !r_version.hazy_match(config_r_version) {
+        bail!(
+            "R {r_version} from {} doesn't match R {config_r_version} from the config, change one of them",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The R version the project uses: the one of the closest `.R-version` file, starting from the
+/// project directory, or the one of the config
+pub fn project_r_version(config: &Config, project_dir: &Path) -> Result<Version> {
+    // The project dir is empty if the config file is given as a file name
+    let project_dir = if project_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        project_dir
+    };
+    Ok(
+        match RVersionFile::find(std::path::absolute(project_dir)?)? {
+            Some(file) => pinned_r_version(&file, config.r_version())?,
+            None => config.r_version().clone(),
+        },
+    )
+}
+
 #[derive(Debug)]
 pub struct CliContext {
     pub config: Config,
+    pub config_path: PathBuf,
     pub project_dir: PathBuf,
     pub r_version: Version,
     pub cache: DiskCache,
@@ -56,17 +119,30 @@ pub struct CliContext {
     // on mac/windows/arch etc
     pub system_dependencies: HashMap<String, Vec<String>>,
     pub show_progress_bar: bool,
+    /// Only use what is already in the cache, failing instead of downloading anything
+    pub offline: bool,
+    /// Fail instead of writing the lockfile if it's not up to date
+    pub frozen: bool,
 }
 
 impl CliContext {
     pub fn new(config_file: &PathBuf, r_command_lookup: RCommandLookup) -> Result<Self> {
         let config = Config::from_file(config_file)?;
+        http::set_retry_policy(config.retry_policy());
+        let http_client = config.http_client()?;
+        for repo in config.repositories() {
+            if let Ok(url) = Url::parse(repo.url()) {
+                http_client.check_proxy(&url)?;
+            }
+        }
+        http::set_http_client(http_client);
 
+        let project_dir = config_file.parent().unwrap().to_path_buf();
         // This can only be set to false if the user passed a r_version to rv plan
         let mut r_version_found = true;
         let (r_version, r_cmd) = match r_command_lookup {
             RCommandLookup::Strict => {
-                let r_version = config.r_version().clone();
+                let r_version = project_r_version(&config, &project_dir)?;
                 let r_cmd = find_r_version_command(&r_version)?;
                 (r_version, r_cmd)
             }
@@ -88,7 +164,6 @@ impl CliContext {
             Err(e) => return Err(anyhow!(e)),
         };
 
-        let project_dir = config_file.parent().unwrap().to_path_buf();
         let lockfile_path = project_dir.join(config.lockfile_name());
         let lockfile = if lockfile_path.exists() && config.use_lockfile() {
             if let Some(lockfile) = Lockfile::load(&lockfile_path)? {
@@ -127,6 +202,7 @@ impl CliContext {
 
         Ok(Self {
             config,
+            config_path: config_file.clone(),
             cache,
             r_version,
             project_dir,
@@ -135,6 +211,8 @@ impl CliContext {
             databases: Vec::new(),
             r_cmd,
             show_progress_bar: false,
+            offline: http::is_offline(),
+            frozen: false,
             builtin_packages,
             system_dependencies: HashMap::new(),
         })
@@ -147,18 +225,16 @@ impl CliContext {
     pub fn load_databases(&mut self) -> Result<()> {
         let pb = create_spinner(self.show_progress_bar, "Loading databases...");
         let reset_pb = || pb.finish_and_clear();
-        self.databases = load_databases(self.config.repositories(), &self.cache)?;
+        self.databases = load_databases(self.config.repositories(), &self.cache, self.offline)?;
         reset_pb();
 
         Ok(())
     }
 
     pub fn load_databases_if_needed(&mut self) -> Result<()> {
-        let can_resolve = self
-            .lockfile
-            .as_ref()
-            .map(|l| l.can_resolve(self.config.dependencies(), self.config.repositories()))
-            .unwrap_or(false);
+        let can_resolve = self.lockfile.as_ref().is_some_and(|l| {
+            lockfile_is_enough(l, &self.config, &self.config_path, &self.lockfile_path())
+        });
 
         if !can_resolve {
             self.load_databases()?;
@@ -171,6 +247,14 @@ impl CliContext {
             return Ok(());
         }
 
+        if self.offline {
+            self.system_dependencies = self
+                .cache
+                .get_cached_system_requirements()
+                .unwrap_or_default();
+            return Ok(());
+        }
+
         let pb = create_spinner(self.show_progress_bar, "Loading system requirements...");
         let reset_pb = || pb.finish_and_clear();
         self.system_dependencies = self.cache.get_system_requirements();
@@ -198,18 +282,25 @@ impl CliContext {
 pub(crate) fn load_databases(
     repositories: &[Repository],
     cache: &DiskCache,
+    offline: bool,
 ) -> Result<Vec<(RepositoryDatabase, bool)>> {
     let dbs: Vec<std::result::Result<_, anyhow::Error>> = repositories
         .par_iter()
         .map(|r| {
             // 1. Generate path to add to URL to get the src PACKAGE and binary PACKAGE for current OS
             let (path, exists) = cache.get_package_db_entry(r.url());
-            // 2. Check in cache whether we have the database and is not expired
-            if exists {
+            // 2. Check in cache whether we have the database and is not expired.
+            // When offline, an expired database is better than nothing
+            if exists || (offline && path.exists()) {
                 // load the archive
                 let db = RepositoryDatabase::load(&path)?;
                 log::debug!("Loaded packages db from {path:?}");
                 Ok((db, r.force_source))
+            } else if offline {
+                bail!(
+                    "The package database for {} is not in the cache and can't be downloaded offline",
+                    r.url()
+                );
             } else if r.url().contains("r-universe.dev") {
                 if path.exists() {
                     fs::remove_file(&path)?;
@@ -305,3 +396,71 @@ pub(crate) fn load_databases(
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+
+    const CONFIG: &str = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = [{ alias = "CRAN", url = "https://cran.r-project.org" }]
+dependencies = ["A"]
+"#;
+
+    #[test]
+    fn project_r_version_is_looked_for_from_project_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let project_dir = tempdir.path().join("project");
+        std::fs::create_dir(&project_dir).unwrap();
+        let config: Config = CONFIG.parse().unwrap();
+        assert_eq!(
+            project_r_version(&config, &project_dir).unwrap().original,
+            "4.4"
+        );
+
+        // Above the project, eg in $HOME
+        RVersionFile::write(tempdir.path(), "4.5.1").unwrap();
+        let err = project_r_version(&config, &project_dir).unwrap_err();
+        assert!(err.to_string().contains("doesn't match R 4.4"), "{err}");
+
+        RVersionFile::write(&project_dir, "4.4.1").unwrap();
+        assert_eq!(
+            project_r_version(&config, &project_dir).unwrap().original,
+            "4.4.1"
+        );
+    }
+
+    #[test]
+    fn dry_run_config_is_checked_against_fresh_lockfile() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let config_path = tempdir.path().join("rproject.toml");
+        let lockfile_path = tempdir.path().join("rv.lock");
+        std::fs::write(&config_path, CONFIG).unwrap();
+        std::fs::copy("src/tests/lockfiles/remove.lock", &lockfile_path).unwrap();
+        // As after a sync
+        filetime::set_file_mtime(&config_path, FileTime::from_unix_time(1_600_000_000, 0)).unwrap();
+        filetime::set_file_mtime(&lockfile_path, FileTime::from_unix_time(1_700_000_000, 0))
+            .unwrap();
+        let lockfile = Lockfile::load(&lockfile_path).unwrap().unwrap();
+
+        let config = Config::from_file(&config_path).unwrap();
+        assert!(lockfile_is_enough(
+            &lockfile,
+            &config,
+            &config_path,
+            &lockfile_path
+        ));
+
+        // `rv add --dry-run Z` only adds it to the config in memory
+        let dry_run: Config = CONFIG.replace(r#"["A"]"#, r#"["A", "Z"]"#).parse().unwrap();
+        assert!(!lockfile_is_enough(
+            &lockfile,
+            &dry_run,
+            &config_path,
+            &lockfile_path
+        ));
+    }
+}
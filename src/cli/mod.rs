@@ -2,5 +2,9 @@ mod commands;
 mod context;
 pub mod utils;
 
-pub use commands::{find_r_repositories, init, init_structure, migrate_renv, tree};
-pub use context::{CliContext, RCommandLookup};
+pub use commands::{
+    MirrorError, OUTDATED_HEADER, Verification, VersionMismatch, export_renv, find_r_repositories,
+    import_packrat, import_renv, init, init_structure, migrate_renv, outdated, set_mirror, tree,
+    verify, why,
+};
+pub use context::{CliContext, RCommandLookup, check_pinned_r_version, project_r_version};
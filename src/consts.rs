@@ -3,11 +3,15 @@ pub const DESCRIPTION_FILENAME: &str = "DESCRIPTION";
 pub const SOURCE_PACKAGES_PATH: &str = "/src/contrib/PACKAGES";
 pub const RUNIVERSE_PACKAGES_API_PATH: &str = "api/packages";
 pub const LOCKFILE_NAME: &str = "rv.lock";
+/// Pins the R version to use in a folder and its subfolders
+pub const R_VERSION_FILENAME: &str = ".R-version";
 
 pub const RV_DIR_NAME: &str = "rv";
 pub const LIBRARY_ROOT_DIR_NAME: &str = "library";
 pub const STAGING_DIR_NAME: &str = "__rv__staging";
 pub(crate) const LIBRARY_METADATA_FILENAME: &str = ".rv.metadata";
+/// How a package version got in the cache, see `InstallMetadata`
+pub(crate) const INSTALL_METADATA_FILENAME: &str = ".rv.install.json";
 pub const BUILD_LOG_FILENAME: &str = "__rv_build.log";
 
 /// How long are the package databases cached for
@@ -16,11 +20,22 @@ pub const BUILD_LOG_FILENAME: &str = "__rv_build.log";
 pub const PACKAGE_TIMEOUT: u64 = 60 * 60;
 pub const PACKAGE_TIMEOUT_ENV_VAR_NAME: &str = "PKGCACHE_TIMEOUT";
 pub const PACKAGE_DB_FILENAME: &str = "packages.bin";
+/// How long the R versions manifest is cached for by default, in seconds
+pub const MANIFEST_TTL: u64 = 60 * 60;
+pub const MANIFEST_TTL_ENV_VAR_NAME: &str = "RV_MANIFEST_TTL";
 
 pub const NUM_CPUS_ENV_VAR_NAME: &str = "RV_NUM_CPUS";
 pub const SYS_REQ_URL_ENV_VAR_NAME: &str = "RV_SYS_REQ_URL";
 pub const NO_CHECK_OPEN_FILE_ENV_VAR_NAME: &str = "RV_NO_CHECK_OPEN_FILE";
 pub const SYS_DEPS_CHECK_IN_PATH_ENV_VAR_NAME: &str = "RV_SYS_DEPS_CHECK_IN_PATH";
+pub const OFFLINE_ENV_VAR_NAME: &str = "RV_OFFLINE";
+pub const BANDWIDTH_LIMIT_ENV_VAR_NAME: &str = "RV_BANDWIDTH_LIMIT";
+pub const CONNECT_TIMEOUT_ENV_VAR_NAME: &str = "RV_CONNECT_TIMEOUT";
+pub const READ_TIMEOUT_ENV_VAR_NAME: &str = "RV_READ_TIMEOUT";
+pub const LOCAL_FINGERPRINT_ENV_VAR_NAME: &str = "RV_LOCAL_FINGERPRINT";
+pub const NETRC_ENV_VAR_NAME: &str = "NETRC";
+/// Followed by the repository alias, see `auth::repository_token_env_var`
+pub const REPO_TOKEN_ENV_VAR_PREFIX: &str = "RV_REPO_TOKEN_";
 
 // List obtained from the REPL: `rownames(installed.packages(priority="base"))`
 // Those will have the same version as R
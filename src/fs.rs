@@ -1,38 +1,470 @@
 use fs_err as fs;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::Metadata;
 use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use bzip2::read::BzDecoder;
 use filetime::FileTime;
 use flate2::read::GzDecoder;
-use sha2::{Digest, Sha256};
+use liblzma::read::XzDecoder;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256, Sha512};
 use tar::Archive;
 use walkdir::WalkDir;
 
-/// Copy the whole content of a folder to another folder
+/// Under that many files, copying in parallel is not worth it
+const PARALLEL_COPY_THRESHOLD: usize = 64;
+/// How much memory is reserved upfront to read a zip archive
+const MAX_ZIP_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+/// What `copy_folder` does when it finds a symlink
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SymlinkBehavior {
+    /// Copy whatever the symlink points to as regular files and folders
+    Follow,
+    /// Recreate the symlink pointing to the same target, eg `libR.so -> libR.so.0.0.1`.
+    /// Windows requires admin rights or developer mode for symlinks so we copy the target instead
+    /// if we can't create them.
+    #[default]
+    Preserve,
+    /// Fail the copy
+    // Not used outside of tests for now
+    #[allow(dead_code)]
+    Error,
+}
+
+/// When `copy_folder` copies a file that already exists in the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum CopyMode {
+    /// Always copy
+    #[default]
+    Always,
+    /// Skip files with the same size and mtime in the destination, like rsync does.
+    IfChanged,
+}
+
+/// Progress callback of `copy_folder`, see `CopyOptions::on_progress`
+pub(crate) type CopyProgressFn<'a> = dyn Fn(u64, u64, &Path) + Sync + 'a;
+
+/// Options for `copy_folder`. The default copies everything, preserving symlinks.
+#[derive(Default)]
+pub(crate) struct CopyOptions<'a> {
+    /// Called with the path of each entry relative to the source folder, entries it returns false
+    /// for are skipped, without walking into them for folders.
+    pub(crate) filter: Option<&'a dyn Fn(&Path) -> bool>,
+    pub(crate) symlinks: SymlinkBehavior,
+    pub(crate) mode: CopyMode,
+    /// Delete the files and folders of the destination that are not in the source.
+    /// Entries skipped by `filter` are left alone.
+    pub(crate) prune: bool,
+    /// Hard link files instead of copying them, falling back to a copy when it's not possible,
+    /// eg across filesystems. Only use it if nothing is going to modify the destination files as
+    /// it would also modify the source ones.
+    pub(crate) use_hard_links: bool,
+    /// Called with the number of files done, the total number of files and the file that was just
+    /// done, skipped files included.
+    /// Folders are not counted. When copying in parallel, it is called from multiple threads.
+    pub(crate) on_progress: Option<&'a CopyProgressFn<'a>>,
+}
+
+impl CopyOptions<'_> {
+    fn keeps(&self, relative: &Path) -> bool {
+        self.filter.is_none_or(|filter| filter(relative))
+    }
+}
+
+/// What happened during a `copy_folder`. Folders are only counted in `dirs_created`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CopyStats {
+    pub(crate) copied: usize,
+    /// Total size of the copied files, hard links included
+    pub(crate) bytes_copied: u64,
+    /// Folders that did not exist in the destination
+    pub(crate) dirs_created: usize,
+    /// Already up to date in the destination
+    pub(crate) skipped: usize,
+    /// Removed from the destination when pruning
+    pub(crate) deleted: usize,
+}
+
+/// Copy the whole content of a folder to another folder, keeping the mtime of the files
 pub(crate) fn copy_folder(
     from: impl AsRef<Path>,
     to: impl AsRef<Path>,
-) -> Result<(), std::io::Error> {
-    let from = from.as_ref();
-    let to = to.as_ref();
+    options: &CopyOptions,
+) -> Result<CopyStats, std::io::Error> {
+    copy_folder_impl(from.as_ref(), to.as_ref(), options, false)
+}
 
-    for entry in WalkDir::new(from) {
-        let entry = entry?;
-        let path = entry.path();
+/// Same as `copy_folder` but the files are copied in parallel, which is a lot faster for
+/// libraries with thousands of files.
+/// All the folders are created first so files never need a folder that doesn't exist yet.
+/// Small folders are copied serially.
+pub(crate) fn copy_folder_parallel(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<CopyStats, std::io::Error> {
+    copy_folder_impl(from.as_ref(), to.as_ref(), options, true)
+}
 
-        let relative = path.strip_prefix(from).expect("walkdir starts with root");
+fn copy_folder_impl(
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+    parallel: bool,
+) -> Result<CopyStats, std::io::Error> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    let mut dirs_created = 0;
+    let walker = WalkDir::new(from).follow_links(options.symlinks == SymlinkBehavior::Follow);
+    for entry in walker
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || options.keeps(relative_to(e.path(), from)))
+    {
+        let entry = entry?;
+        let relative = relative_to(entry.path(), from);
         let out_path = to.join(relative);
+        if options.prune {
+            seen.insert(relative.to_path_buf());
+        }
 
         if entry.file_type().is_dir() {
-            fs::create_dir_all(&out_path)?;
+            if !out_path.is_dir() {
+                fs::create_dir_all(&out_path)?;
+                dirs_created += 1;
+            }
             continue;
         }
 
-        fs::copy(path, out_path)?;
+        files.push((entry, out_path));
     }
 
-    Ok(())
+    let (symlinks, mode, use_hard_links) = (options.symlinks, options.mode, options.use_hard_links);
+    let on_progress = options.on_progress;
+    let total = files.len() as u64;
+    let done = AtomicU64::new(0);
+    let copy = |(entry, out_path): &(walkdir::DirEntry, PathBuf)| {
+        let copied = copy_entry(entry, out_path, symlinks, mode, use_hard_links)?;
+        if let Some(on_progress) = on_progress {
+            on_progress(
+                done.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+                entry.path(),
+            );
+        }
+        Ok::<_, std::io::Error>(copied)
+    };
+    let copied: Vec<Option<u64>> = if parallel && files.len() >= PARALLEL_COPY_THRESHOLD {
+        files.par_iter().map(copy).collect::<Result<_, _>>()?
+    } else {
+        files.iter().map(copy).collect::<Result<_, _>>()?
+    };
+
+    let deleted = if options.prune {
+        prune_folder(to, &seen, options)?
+    } else {
+        0
+    };
+    let sizes: Vec<u64> = copied.iter().flatten().copied().collect();
+
+    Ok(CopyStats {
+        copied: sizes.len(),
+        bytes_copied: sizes.iter().sum(),
+        dirs_created,
+        skipped: copied.len() - sizes.len(),
+        deleted,
+    })
+}
+
+fn relative_to<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).expect("walkdir starts with root")
+}
+
+/// Removes everything in `to` that is not in `seen`, returning how many files were removed
+fn prune_folder(
+    to: &Path,
+    seen: &HashSet<PathBuf>,
+    options: &CopyOptions,
+) -> Result<usize, std::io::Error> {
+    let mut deleted = 0;
+    // Children first so folders are empty by the time we get to them
+    for entry in WalkDir::new(to).min_depth(1).contents_first(true) {
+        let entry = entry?;
+        let relative = relative_to(entry.path(), to);
+        if seen.contains(relative) || !options.keeps(relative) {
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            fs::remove_dir(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+            deleted += 1;
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Whether the file or folder is only there for tooling (VCS, IDE...) and not part of
+/// the package itself
+pub(crate) fn is_tooling_metadata(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| MTIME_IGNORED.iter().any(|ignored| name == *ignored))
+}
+
+/// Copies a single non-folder entry found while walking the source folder.
+/// Returns the number of bytes copied or None if it was skipped as it was already up to date.
+fn copy_entry(
+    entry: &walkdir::DirEntry,
+    out_path: &Path,
+    symlinks: SymlinkBehavior,
+    mode: CopyMode,
+    use_hard_links: bool,
+) -> Result<Option<u64>, std::io::Error> {
+    let path = entry.path();
+    let existing = match mode {
+        CopyMode::Always => None,
+        CopyMode::IfChanged => out_path.symlink_metadata().ok(),
+    };
+
+    // When following symlinks, walkdir gives us the type of the target so symlinks to folders
+    // are walked like folders and symlinks to files are copied below
+    if entry.path_is_symlink() {
+        match symlinks {
+            SymlinkBehavior::Follow => (),
+            SymlinkBehavior::Preserve => {
+                if let Some(existing) = existing {
+                    if existing.is_symlink() && fs::read_link(out_path)? == fs::read_link(path)? {
+                        return Ok(None);
+                    }
+                    remove_existing(out_path, &existing)?;
+                }
+                copy_symlink(path, out_path)?;
+                return Ok(Some(0));
+            }
+            SymlinkBehavior::Error => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Found a symlink at {}", path.display()),
+                ));
+            }
+        }
+    }
+
+    let meta = entry.metadata()?;
+    if let Some(existing) = existing {
+        let src_mtime = FileTime::from_last_modification_time(&meta);
+        let dest_mtime = FileTime::from_last_modification_time(&existing);
+        let up_to_date = match mode {
+            CopyMode::Always => false,
+            CopyMode::IfChanged => existing.len() == meta.len() && dest_mtime == src_mtime,
+        };
+        if existing.is_file() && up_to_date {
+            return Ok(None);
+        }
+        // Don't write through a hardlink or a symlink to some other file
+        remove_existing(out_path, &existing)?;
+    }
+
+    // Hard links don't follow symlinks so we copy the file they point to instead
+    if use_hard_links && !entry.path_is_symlink() && try_hard_link(path, out_path)? {
+        return Ok(Some(meta.len()));
+    }
+
+    fs::copy(path, out_path)?;
+    // fs::copy should already do it but some R packages ship scripts like `configure` that
+    // need to keep their executable bit so we set it explicitly
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = meta.permissions().mode();
+        fs::set_permissions(out_path, std::fs::Permissions::from_mode(mode))?;
+    }
+    // Otherwise every copied file looks new to `mtime_recursive` and the next `IfChanged` copy
+    filetime::set_file_mtime(out_path, FileTime::from_last_modification_time(&meta))?;
+
+    Ok(Some(meta.len()))
+}
+
+/// Returns false if the file couldn't be hard linked and needs to be copied instead
+fn try_hard_link(path: &Path, out_path: &Path) -> Result<bool, std::io::Error> {
+    match fs::hard_link(path, out_path) {
+        Ok(_) => Ok(true),
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::CrossesDevices
+            | std::io::ErrorKind::PermissionDenied
+            | std::io::ErrorKind::TooManyLinks
+            | std::io::ErrorKind::Unsupported => Ok(false),
+            _ => Err(e),
+        },
+    }
+}
+
+fn remove_existing(path: &Path, meta: &Metadata) -> Result<(), std::io::Error> {
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Recreates the symlink at `path` in `out_path`, pointing to the same target
+#[cfg(unix)]
+fn copy_symlink(path: &Path, out_path: &Path) -> Result<(), std::io::Error> {
+    let target = fs::read_link(path)?;
+    std::os::unix::fs::symlink(target, out_path)
+}
+
+/// Windows requires admin rights or developer mode for symlinks so we use a junction for
+/// folders if we can't create one, and copy whatever they point to for files.
+#[cfg(not(unix))]
+fn copy_symlink(path: &Path, out_path: &Path) -> Result<(), std::io::Error> {
+    #[cfg(windows)]
+    {
+        let target = fs::read_link(path)?;
+        let res = if path.is_dir() {
+            std::os::windows::fs::symlink_dir(&target, out_path)
+                .or_else(|_| link_dir(path.parent().unwrap_or(path).join(&target), out_path))
+        } else {
+            std::os::windows::fs::symlink_file(&target, out_path)
+        };
+        if res.is_ok() {
+            return Ok(());
+        }
+    }
+
+    if path.is_dir() {
+        copy_folder(
+            path,
+            out_path,
+            &CopyOptions {
+                symlinks: SymlinkBehavior::Follow,
+                ..Default::default()
+            },
+        )
+        .map(|_| ())
+    } else {
+        fs::copy(path, out_path).map(|_| ())
+    }
+}
+
+/// Makes `dst` point to the `src` folder: a symlink on Unix, a junction on Windows.
+/// The link itself is removed with `remove_dir` on Windows and `remove_file` elsewhere.
+#[cfg(unix)]
+pub(crate) fn link_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, dst)
+}
+
+/// Unlike symlinks, junctions don't require admin rights or developer mode.
+/// std can't create junctions so we set the reparse point on an empty folder ourselves.
+#[cfg(windows)]
+pub(crate) fn link_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
+    // Junctions can only point to absolute paths
+    let src = std::path::absolute(src.as_ref())?;
+    let dst = dst.as_ref();
+    fs::create_dir(dst)?;
+    set_junction_target(&src, dst).map_err(|e| {
+        let _ = fs::remove_dir(dst);
+        std::io::Error::new(
+            e.kind(),
+            format!(
+                "Failed to create a junction from {} to {}: {e}",
+                dst.display(),
+                src.display()
+            ),
+        )
+    })
+}
+
+/// Writes the mount point reparse point making the empty `dst` folder a junction to `src`
+#[cfg(windows)]
+fn set_junction_target(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_WRITE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_REPARSE_POINT;
+    use windows_sys::Win32::System::SystemServices::IO_REPARSE_TAG_MOUNT_POINT;
+
+    // The target is stored as an NT path, `\??\C:\...`, along with the path to display
+    let mut print_name: Vec<u16> = src.as_os_str().encode_wide().collect();
+    let verbatim: Vec<u16> = r"\\?\".encode_utf16().collect();
+    if print_name.starts_with(&verbatim) {
+        print_name.drain(..verbatim.len());
+    }
+    let substitute_name: Vec<u16> = r"\??\".encode_utf16().chain(print_name.clone()).collect();
+
+    // REPARSE_DATA_BUFFER, which windows-sys doesn't have: the header, the offsets and lengths
+    // in bytes of both names and then the names, each followed by a NUL
+    let too_long = || std::io::Error::other("the target path is too long");
+    let substitute_len = u16::try_from(substitute_name.len() * 2).map_err(|_| too_long())?;
+    let print_len = u16::try_from(print_name.len() * 2).map_err(|_| too_long())?;
+    let data_len = substitute_len
+        .checked_add(print_len)
+        .and_then(|len| len.checked_add(8 + 2 * 2))
+        .ok_or_else(too_long)?;
+    let mut buffer = Vec::with_capacity(8 + data_len as usize);
+    buffer.extend_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    buffer.extend_from_slice(&data_len.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&substitute_len.to_le_bytes());
+    buffer.extend_from_slice(&(substitute_len + 2).to_le_bytes());
+    buffer.extend_from_slice(&print_len.to_le_bytes());
+    for c in substitute_name
+        .iter()
+        .chain(&[0])
+        .chain(&print_name)
+        .chain(&[0])
+    {
+        buffer.extend_from_slice(&c.to_le_bytes());
+    }
+
+    let dst: Vec<u16> = dst.as_os_str().encode_wide().chain([0]).collect();
+    // SAFETY: `dst` is NUL terminated and the handle is closed below
+    let handle = unsafe {
+        CreateFileW(
+            dst.as_ptr(),
+            GENERIC_WRITE,
+            0,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut returned = 0;
+    // SAFETY: the handle is valid and the input buffer is as long as we say it is
+    let res = unsafe {
+        DeviceIoControl(
+            handle,
+            FSCTL_SET_REPARSE_POINT,
+            buffer.as_ptr().cast(),
+            buffer.len() as u32,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    let res = if res == 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    // SAFETY: the handle is valid and not used afterwards
+    unsafe { CloseHandle(handle) };
+    res
 }
 
 fn metadata(path: impl AsRef<Path>) -> Result<Metadata, std::io::Error> {
@@ -40,20 +472,89 @@ fn metadata(path: impl AsRef<Path>) -> Result<Metadata, std::io::Error> {
     fs::metadata(path)
 }
 
+/// Files and folders that don't affect the package being built, eg VCS metadata
+const MTIME_IGNORED: &[&str] = &[".git", ".svn", ".Rproj.user"];
+
+/// What `mtime_recursive` found while walking a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DirectoryStats {
+    pub(crate) max_mtime: FileTime,
+    /// Everything that is not a folder once symlinks are followed
+    pub(crate) file_count: u64,
+    /// Not counting the folder itself
+    pub(crate) dir_count: u64,
+    /// Size of everything that is not a folder once symlinks are followed
+    pub(crate) total_size_bytes: u64,
+}
+
 /// Returns the maximum mtime found in the given folder, looking at all subfolders and
-/// following symlinks
+/// following symlinks, along with how many files and folders it saw.
+/// Anything in `MTIME_IGNORED` is skipped.
+/// `max_depth` limits how deep we look, `Some(1)` only looking at the direct children of the
+/// folder, and `None` walking everything.
+/// `exclude_hidden` skips hidden files and folders, eg `.Rprofile`.
 /// Taken from cargo crates/cargo-util/src/paths.rs
-/// We keep it simple for now and just mtime even if it causes more rebuilds than mtime + hashes
-pub(crate) fn mtime_recursive(folder: impl AsRef<Path>) -> Result<FileTime, std::io::Error> {
+/// Only mtimes are compared, which can cause more rebuilds than needed: `fingerprint_recursive`
+/// hashes the content instead.
+pub(crate) fn mtime_recursive(
+    folder: impl AsRef<Path>,
+    max_depth: Option<usize>,
+    exclude_hidden: bool,
+) -> Result<DirectoryStats, std::io::Error> {
+    mtime_recursive_filtered(folder, MTIME_IGNORED, max_depth, exclude_hidden)
+}
+
+/// Dotfiles everywhere and files with the hidden attribute on Windows
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    if entry.file_name().to_string_lossy().starts_with('.') {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if let Ok(meta) = entry.metadata() {
+            return meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+        }
+    }
+    false
+}
+
+/// Same as `mtime_recursive` but skipping the files and folders with one of the given names.
+/// Skipped folders are not walked at all.
+pub(crate) fn mtime_recursive_filtered(
+    folder: impl AsRef<Path>,
+    ignored: &[&str],
+    max_depth: Option<usize>,
+    exclude_hidden: bool,
+) -> Result<DirectoryStats, std::io::Error> {
     let meta = metadata(folder.as_ref())?;
     if !meta.is_dir() {
-        return Ok(FileTime::from_last_modification_time(&meta));
+        return Ok(DirectoryStats {
+            max_mtime: FileTime::from_last_modification_time(&meta),
+            file_count: 1,
+            dir_count: 0,
+            total_size_bytes: meta.len(),
+        });
     }
 
-    // TODO: filter out hidden files/folders?
-    let max_mtime = WalkDir::new(folder)
+    let mut stats = DirectoryStats {
+        // Handles the case where there are no files in the directory.
+        max_mtime: FileTime::from_last_modification_time(&meta),
+        file_count: 0,
+        dir_count: 0,
+        total_size_bytes: 0,
+    };
+    let entries = WalkDir::new(folder)
         .follow_links(true)
+        .max_depth(max_depth.unwrap_or(usize::MAX))
         .into_iter()
+        // Never skip the folder we were given, even if it's named `.git`
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !(ignored.iter().any(|name| e.file_name() == *name)
+                    || exclude_hidden && is_hidden(e))
+        })
         .filter_map(|e| e.ok())
         .filter_map(|e| {
             if e.path_is_symlink() {
@@ -76,7 +577,7 @@ pub(crate) fn mtime_recursive(folder: impl AsRef<Path>) -> Result<FileTime, std:
                 match e.metadata() {
                     Ok(target_meta) => {
                         let target_mtime = FileTime::from_last_modification_time(&target_meta);
-                        Some(sym_mtime.max(target_mtime))
+                        Some((e, sym_mtime.max(target_mtime), target_meta))
                     }
                     Err(err) => {
                         log::debug!(
@@ -84,7 +585,7 @@ pub(crate) fn mtime_recursive(folder: impl AsRef<Path>) -> Result<FileTime, std:
                             e.path().display(),
                             err
                         );
-                        Some(sym_mtime)
+                        Some((e, sym_mtime, sym_meta))
                     }
                 }
             } else {
@@ -99,66 +600,2328 @@ pub(crate) fn mtime_recursive(folder: impl AsRef<Path>) -> Result<FileTime, std:
                         return None;
                     }
                 };
-                Some(FileTime::from_last_modification_time(&meta))
+                Some((e, FileTime::from_last_modification_time(&meta), meta))
+            }
+        });
+
+    let mut max_mtime = None;
+    for (entry, mtime, meta) in entries {
+        max_mtime = max_mtime.max(Some(mtime));
+        if meta.is_dir() {
+            if entry.depth() > 0 {
+                stats.dir_count += 1;
             }
+        } else {
+            stats.file_count += 1;
+            stats.total_size_bytes += meta.len();
+        }
+    }
+    if let Some(max_mtime) = max_mtime {
+        stats.max_mtime = max_mtime;
+    }
+
+    Ok(stats)
+}
+
+/// Which digest `untar_archive` computes for the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+            Self::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    /// The name used to prefix digests, eg `sha512:abc...`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// Splits an optional `sha256:` or `sha512:` prefix from a digest.
+    /// Bare digests are returned with no algorithm.
+    pub(crate) fn split_prefix(digest: &str) -> (Option<Self>, &str) {
+        for algorithm in [Self::Sha256, Self::Sha512] {
+            if let Some(hex) = digest
+                .strip_prefix(algorithm.name())
+                .and_then(|rest| rest.strip_prefix(':'))
+            {
+                return (Some(algorithm), hex);
+            }
+        }
+        (None, digest)
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+        }
+    }
+
+    /// Returns the lowercase hex digest, without prefix
+    fn finalize(self) -> String {
+        match self {
+            Self::Sha256(h) => format!("{:x}", h.finalize()),
+            Self::Sha512(h) => format!("{:x}", h.finalize()),
+        }
+    }
+}
+
+/// The digest of an archive is not the one we expected.
+/// Returned by `untar_archive` wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Debug, thiserror::Error)]
+#[error("Hash mismatch: expected {expected}, got {actual}")]
+pub(crate) struct HashMismatch {
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+/// An extracted file doesn't have the digest listed in the manifest given to `untar_archive`.
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Debug, thiserror::Error)]
+#[error("Hash mismatch for {path}: expected {expected}, got {actual}", path = path.display())]
+pub(crate) struct FileHashMismatch {
+    pub(crate) path: PathBuf,
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+/// Options for `untar_archive`. The default extracts the archive with its permissions,
+/// without hashing it.
+pub(crate) struct UntarOptions<'a> {
+    /// Which digest of the archive to compute, if any
+    pub(crate) hash: Option<HashAlgorithm>,
+    /// The size of the archive if known, used for progress reporting and to avoid reallocations
+    /// when zip archives are read in memory
+    pub(crate) total_size: Option<u64>,
+    /// Called after each extracted entry with how many bytes of the archive have been read
+    /// and the total size of the archive, if known.
+    /// It is called one last time once the whole archive is read, with the total always set.
+    pub(crate) on_progress: Option<&'a dyn Fn(u64, Option<u64>)>,
+    /// Only the entries for which this returns true are extracted, eg to skip `doc/`.
+    /// Paths are relative to the root of the archive.
+    pub(crate) path_filter: Option<&'a dyn Fn(&Path) -> bool>,
+    /// Whether to restore the Unix permissions stored in the archive, eg the executable bit
+    /// of `configure` or `bin/` scripts.
+    /// When false, zip entries get the default permissions. Tarballs still get their rwx bits
+    /// as tar always sets them, only the special bits (setuid, sticky...) are dropped.
+    pub(crate) preserve_permissions: bool,
+    /// If set, the digest of the archive has to match, otherwise a `HashMismatch` error is
+    /// returned and nothing is written to the destination.
+    /// It can be prefixed with the algorithm, eg `sha512:abc...`, which is used if `hash` is not
+    /// set. Bare digests are computed with `hash`, SHA256 if not set.
+    pub(crate) expected_hash: Option<&'a str>,
+    /// How many leading components to drop from each entry path, like `tar --strip-components`.
+    /// Entries with fewer components are skipped.
+    pub(crate) strip_components: u32,
+    /// Digests of files that need to be checked after extraction, by path relative to the
+    /// destination. They are SHA256 unless prefixed like `expected_hash`. A mismatch or a missing
+    /// file is an error and nothing is written to the destination.
+    pub(crate) manifest: Option<&'a HashMap<PathBuf, String>>,
+}
+
+impl UntarOptions<'_> {
+    fn keeps(&self, path: &Path) -> bool {
+        self.path_filter.is_none_or(|filter| filter(path))
+    }
+}
+
+impl Default for UntarOptions<'_> {
+    fn default() -> Self {
+        Self {
+            hash: None,
+            expected_hash: None,
+            strip_components: 0,
+            manifest: None,
+            total_size: None,
+            on_progress: None,
+            path_filter: None,
+            preserve_permissions: true,
+        }
+    }
+}
+
+/// Returns a SHA256 over the relative paths and contents of all the files in the given folder,
+/// skipping anything in `MTIME_IGNORED`.
+/// Files are hashed sorted by their relative path, using `/` as separator, so the same content
+/// gives the same fingerprint on every OS and filesystem.
+/// Slower than `mtime_recursive` but stable when mtimes are not, eg after a fresh checkout.
+pub(crate) fn fingerprint_recursive(folder: impl AsRef<Path>) -> Result<String, std::io::Error> {
+    let folder = folder.as_ref();
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(folder)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0 || !MTIME_IGNORED.iter().any(|name| e.file_name() == *name)
         })
-        .max() // or_else handles the case where there are no files in the directory.
-        .unwrap_or_else(|| FileTime::from_last_modification_time(&meta));
-    Ok(max_mtime)
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = relative_to(entry.path(), folder)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((relative, entry.into_path()));
+    }
+    // Sorting the walk by file name is not enough: `a/b` would come before `a-c`
+    files.sort_unstable();
+
+    let mut hasher = Sha256::new();
+    for (relative, path) in files {
+        let mut file = fs::File::open(path)?;
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        // Prefix the content with its length so moving bytes between files changes the hash
+        hasher.update(file.metadata()?.len().to_le_bytes());
+        std::io::copy(&mut file, &mut hasher)?;
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Checks a file against a `sha256sum` style sidecar file, eg `R-4.3.2.tar.gz.sha256`.
+/// Each line is `<hex> <name>`, with one or two spaces and an optional `*` for binary mode.
+/// If it lists several files, the entry with the same file name as `artifact` is used.
+/// Returns an `InvalidData` error if no entry matches.
+// Not used outside of tests for now
+#[allow(dead_code)]
+pub(crate) fn verify_hash_file(
+    artifact: impl AsRef<Path>,
+    hash_file: impl AsRef<Path>,
+) -> Result<bool, std::io::Error> {
+    let artifact = artifact.as_ref();
+    let hash_file = hash_file.as_ref();
+    let file_name = artifact.file_name().unwrap_or_default().to_string_lossy();
+    let content = fs::read_to_string(hash_file)?;
+
+    let mut bare = None;
+    let mut expected = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((hex, name)) = line.split_once(char::is_whitespace) else {
+            // Only the digest, without file name
+            bare.get_or_insert(line);
+            continue;
+        };
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        // The name can be a path, only compare the file names
+        if Path::new(name).file_name() == artifact.file_name() {
+            expected = Some(hex);
+            break;
+        }
+    }
+
+    let Some(expected) = expected.or(bare) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("No hash for {file_name} found in {}", hash_file.display()),
+        ));
+    };
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut fs::File::open(artifact)?, &mut hasher)?;
+    Ok(expected.eq_ignore_ascii_case(&format!("{:x}", hasher.finalize())))
 }
 
-/// Untars an archive in the given destination folder, returning a path to the first folder in what
-/// was extracted since R tarballs are (always?) a folder
+/// The archive formats we can extract, detected from their magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarBz2,
+    TarZst,
+    TarXz,
+}
+
+impl ArchiveFormat {
+    /// The longest magic number we look for is xz with 6 bytes
+    const MAGIC_LEN: u64 = 6;
+
+    fn detect(magic: &[u8]) -> Result<Self, std::io::Error> {
+        // Can happen if a repository returns an empty body instead of an error status
+        if magic.len() < 4 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "archive too small ({} bytes) to be a recognized format",
+                    magic.len()
+                ),
+            ));
+        }
+
+        match magic {
+            [0x50, 0x4b, 0x03, 0x04, ..] => Ok(Self::Zip),
+            // .tar.gz and .tgz
+            [0x1F, 0x8B, ..] => Ok(Self::TarGz),
+            [0x42, 0x5A, 0x68, ..] => Ok(Self::TarBz2),
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Ok(Self::TarZst),
+            [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, ..] => Ok(Self::TarXz),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "not a .tar.gz, .tgz, .tar.bz2, .tar.xz, .tar.zst or .zip archive",
+            )),
+        }
+    }
+}
+
+/// Reads the magic number of the archive, giving back a reader over the whole archive
+fn sniff_format<R: Read>(mut reader: R) -> Result<(ArchiveFormat, impl Read), std::io::Error> {
+    let mut magic = Vec::with_capacity(ArchiveFormat::MAGIC_LEN as usize);
+    reader
+        .by_ref()
+        .take(ArchiveFormat::MAGIC_LEN)
+        .read_to_end(&mut magic)?;
+    let format = ArchiveFormat::detect(&magic)?;
+    Ok((format, std::io::Cursor::new(magic).chain(reader)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveEntryKind {
+    /// Regular files, and hard links
+    File,
+    Dir,
+    Symlink,
+}
+
+/// An entry of an archive, as listed by `list_archive_entries`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ArchiveEntry {
+    /// Path relative to the root of the archive
+    pub(crate) path: PathBuf,
+    /// Uncompressed size, 0 for folders
+    pub(crate) size: u64,
+    pub(crate) kind: ArchiveEntryKind,
+    /// Not available for zip archives as they store a local time without timezone
+    pub(crate) mtime: Option<FileTime>,
+}
+
+/// Lists what `untar_archive` would extract from the archive without writing anything to disk.
+// Not used outside of tests for now
+#[allow(dead_code)]
+pub(crate) fn list_archive_entries<R: Read>(
+    reader: R,
+) -> Result<Vec<ArchiveEntry>, std::io::Error> {
+    let (format, mut reader) = sniff_format(reader)?;
+    match format {
+        ArchiveFormat::Zip => {
+            // zip lib requires Seek so we need the whole archive in memory
+            let mut buffer = Vec::new();
+            reader.read_to_end(&mut buffer)?;
+            let mut archive = zip::read::ZipArchive::new(std::io::Cursor::new(buffer))?;
+            let mut entries = Vec::with_capacity(archive.len());
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i)?;
+                entries.push(ArchiveEntry {
+                    path: PathBuf::from(file.name()),
+                    size: file.size(),
+                    kind: if file.is_dir() {
+                        ArchiveEntryKind::Dir
+                    } else if file.is_symlink() {
+                        ArchiveEntryKind::Symlink
+                    } else {
+                        ArchiveEntryKind::File
+                    },
+                    mtime: None,
+                });
+            }
+            Ok(entries)
+        }
+        ArchiveFormat::TarGz => list_tar_entries(GzDecoder::new(reader)),
+        ArchiveFormat::TarBz2 => list_tar_entries(BzDecoder::new(reader)),
+        ArchiveFormat::TarZst => list_tar_entries(zstd::Decoder::new(reader)?),
+        ArchiveFormat::TarXz => list_tar_entries(XzDecoder::new(reader)),
+    }
+}
+
+fn list_tar_entries(reader: impl Read) -> Result<Vec<ArchiveEntry>, std::io::Error> {
+    let mut archive = Archive::new(reader);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        entries.push(ArchiveEntry {
+            path: entry.path()?.into_owned(),
+            size: entry.size(),
+            kind: match header.entry_type() {
+                t if t.is_dir() => ArchiveEntryKind::Dir,
+                t if t.is_symlink() => ArchiveEntryKind::Symlink,
+                _ => ArchiveEntryKind::File,
+            },
+            mtime: header
+                .mtime()
+                .ok()
+                .map(|t| FileTime::from_unix_time(t as i64, 0)),
+        });
+    }
+    Ok(entries)
+}
+
+/// Untars an archive in the given destination folder, returning a path to the first folder (by name)
+/// in what was extracted since R tarballs are (always?) a folder.
+/// If `strip_components` is set, that folder is gone and the destination itself is returned.
+/// Use `top_level_dirs` on the destination if the archive can contain several folders.
 /// For windows binaries, they are in .zip archives and will be unzipped
+///
+/// Tarballs are extracted and hashed as they are read, only zip archives are loaded in memory.
+/// The archive is first extracted in a temporary folder next to `dest` and only moved there
+/// once fully extracted, so an interrupted extraction never leaves a partial `dest` behind.
 pub(crate) fn untar_archive<R: Read>(
-    mut reader: R,
+    reader: R,
     dest: impl AsRef<Path>,
-    compute_hash: bool,
+    options: UntarOptions,
 ) -> Result<(Option<PathBuf>, Option<String>), std::io::Error> {
+    let algorithm = archive_hash_algorithm(&options)?;
+    let (dir, mut hashes) = untar_archive_hashes(reader, dest, options, &[])?;
+    Ok((dir, algorithm.and_then(|a| hashes.remove(&a))))
+}
+
+/// Same as `untar_archive` but computes every digest in `algorithms` in the same pass on top
+/// of the one from the options, eg a SHA256 to check against the publisher and a SHA512 to
+/// store. Digests are returned by algorithm, without prefix.
+pub(crate) fn untar_archive_hashes<R: Read>(
+    reader: R,
+    dest: impl AsRef<Path>,
+    options: UntarOptions,
+    algorithms: &[HashAlgorithm],
+) -> Result<(Option<PathBuf>, HashMap<HashAlgorithm, String>), std::io::Error> {
     let dest = dest.as_ref();
-    fs::create_dir_all(dest)?;
+    let parent = match dest.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    // Removed on drop if anything fails before we move it to `dest`
+    let staging = tempfile::Builder::new()
+        .prefix(".rv-extract-")
+        .tempdir_in(parent)?;
+    // tar checks that entries do not escape the destination by comparing canonicalized paths
+    let unpack_dest = fs::canonicalize(staging.path())?;
 
-    let mut hash = None;
-    let mut buffer = Vec::new();
-    reader.read_to_end(&mut buffer)?;
-    if compute_hash {
-        let mut hasher = Sha256::new();
-        hasher.update(&buffer);
-        let hash_out = hasher.finalize();
-        hash = Some(format!("{hash_out:x}"));
+    let (format, reader) = sniff_format(reader)?;
+    let read = Cell::new(0);
+    let report = |read: u64, total: Option<u64>| {
+        if let Some(on_progress) = options.on_progress {
+            on_progress(read, total.or(options.total_size));
+        }
+    };
+    let on_entry = || report(read.get(), None);
+    let expected_hash = options.expected_hash.map(HashAlgorithm::split_prefix);
+    let algorithm = archive_hash_algorithm(&options)?;
+    let mut hashers: Vec<_> = Vec::new();
+    for a in algorithm.into_iter().chain(algorithms.iter().copied()) {
+        if !hashers.iter().any(|(existing, _)| *existing == a) {
+            hashers.push((a, a.hasher()));
+        }
     }
+    let stream = HashingReader {
+        inner: CountingReader {
+            inner: reader,
+            count: &read,
+        },
+        hashers,
+    };
 
-    match buffer[..4] {
-        // zip
-        [0x50, 0x4b, 0x03, 0x04] => {
-            // zip lib requires Seek
-            let cursor = std::io::Cursor::new(buffer);
-            zip::read::ZipArchive::new(cursor)?.extract(dest)?;
+    let mut stream = match format {
+        ArchiveFormat::Zip => {
+            // zip lib requires Seek so we need the whole archive in memory
+            let mut stream = stream;
+            // The size comes from the server so we don't trust it for the allocation
+            let capacity = options.total_size.unwrap_or(0).min(MAX_ZIP_PREALLOCATION);
+            let mut buffer = Vec::with_capacity(capacity as usize);
+            stream.read_to_end(&mut buffer)?;
+            let mut done = 0;
+            extract_zip(&buffer, &unpack_dest, &options, |compressed_size| {
+                done += compressed_size;
+                report(done, Some(buffer.len() as u64));
+            })?;
+            stream
+        }
+        ArchiveFormat::TarGz => {
+            unpack_tar(GzDecoder::new(stream), &unpack_dest, &options, on_entry)?.into_inner()
+        }
+        ArchiveFormat::TarBz2 => {
+            unpack_tar(BzDecoder::new(stream), &unpack_dest, &options, on_entry)?.into_inner()
+        }
+        ArchiveFormat::TarZst => {
+            // bytes left in the decoder buffer have already been hashed
+            unpack_tar(
+                zstd::Decoder::new(stream)?,
+                &unpack_dest,
+                &options,
+                on_entry,
+            )?
+            .finish()
+            .into_inner()
         }
-        // tar.gz, .tgz
-        [0x1F, 0x8B, ..] => {
-            let tar = GzDecoder::new(buffer.as_slice());
-            let mut archive = Archive::new(tar);
-            archive.unpack(dest)?;
+        ArchiveFormat::TarXz => {
+            unpack_tar(XzDecoder::new(stream), &unpack_dest, &options, on_entry)?.into_inner()
         }
-        _ => {
+    };
+
+    // The decompressors can stop before the end of the archive (eg padding after the tar
+    // end-of-archive marker) but the hash needs to cover every byte
+    std::io::copy(&mut stream, &mut std::io::sink())?;
+    report(read.get(), Some(read.get()));
+    let hashes: HashMap<_, _> = stream
+        .hashers
+        .into_iter()
+        .map(|(a, hasher)| (a, hasher.finalize()))
+        .collect();
+    if let (Some((_, expected)), Some(actual)) =
+        (expected_hash, algorithm.and_then(|a| hashes.get(&a)))
+        && !expected.eq_ignore_ascii_case(actual)
+    {
+        // Dropping the staging folder removes everything we extracted
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            HashMismatch {
+                expected: expected.to_string(),
+                actual: actual.clone(),
+            },
+        ));
+    }
+
+    if let Some(manifest) = options.manifest {
+        verify_manifest(&unpack_dest, manifest)?;
+    }
+
+    let dir = if options.strip_components > 0 {
+        Some(dest.to_path_buf())
+    } else {
+        // Only look at what was extracted, `dest` might contain other folders
+        top_level_dirs(staging.path())?
+            .into_iter()
+            .next()
+            .and_then(|d| d.file_name().map(|name| dest.join(name)))
+    };
+    persist_extraction(staging.path(), dest)?;
+
+    Ok((dir, hashes))
+}
+
+/// Which digest of the archive is returned by `untar_archive` and checked against
+/// `expected_hash`
+fn archive_hash_algorithm(options: &UntarOptions) -> Result<Option<HashAlgorithm>, std::io::Error> {
+    let expected = options
+        .expected_hash
+        .map(|digest| HashAlgorithm::split_prefix(digest).0);
+    match (options.hash, expected) {
+        (Some(hash), Some(Some(expected))) if hash != expected => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Expected a {expected} digest but the archive is hashed with {hash}"),
+        )),
+        (Some(hash), _) => Ok(Some(hash)),
+        (None, Some(expected)) => Ok(Some(expected.unwrap_or_default())),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Checks the digests of the files listed in the manifest, relative to `root`
+fn verify_manifest(root: &Path, manifest: &HashMap<PathBuf, String>) -> Result<(), std::io::Error> {
+    for (path, expected) in manifest {
+        let (algorithm, hex) = HashAlgorithm::split_prefix(expected);
+        let algorithm = algorithm.unwrap_or_default();
+        let mut reader = HashingReader {
+            inner: fs::File::open(root.join(path))?,
+            hashers: vec![(algorithm, algorithm.hasher())],
+        };
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        let actual = reader
+            .hashers
+            .pop()
+            .map(|(_, hasher)| hasher.finalize())
+            .unwrap_or_default();
+        if !hex.eq_ignore_ascii_case(&actual) {
             return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "not tar.gz or a .zip archive",
+                std::io::ErrorKind::InvalidData,
+                FileHashMismatch {
+                    path: path.clone(),
+                    expected: expected.clone(),
+                    actual,
+                },
             ));
         }
     }
 
-    let dir: Option<PathBuf> = fs::read_dir(dest)?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            if entry.file_type().ok()?.is_dir() {
-                Some(entry.path())
+    Ok(())
+}
+
+/// Moves a fully extracted archive to `dest`. If `dest` already exists, the extracted entries are
+/// moved inside it, replacing any existing entry with the same name.
+fn persist_extraction(staging: &Path, dest: &Path) -> Result<(), std::io::Error> {
+    if !dest.exists() {
+        return move_path(staging, dest);
+    }
+
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if target.is_dir() && !target.is_symlink() {
+            fs::remove_dir_all(&target)?;
+        } else if target.exists() || target.is_symlink() {
+            fs::remove_file(&target)?;
+        }
+        move_path(&entry.path(), &target)?;
+    }
+
+    Ok(())
+}
+
+/// Renames `from` to `to`, falling back to copying and deleting if they are not on the same
+/// filesystem.
+fn move_path(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if from.is_dir() {
+                copy_folder(from, to, &CopyOptions::default())?;
+                fs::remove_dir_all(from)
             } else {
-                None
+                fs::copy(from, to)?;
+                fs::remove_file(from)
             }
-        })
-        .next();
+        }
+        res => res,
+    }
+}
+
+/// Keeps track of how many bytes were read from the inner reader
+struct CountingReader<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Feeds everything read from the inner reader to each hasher
+struct HashingReader<R> {
+    inner: R,
+    hashers: Vec<(HashAlgorithm, Hasher)>,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Unpacks a decompressed tar stream entry by entry, following the options and
+/// calling `on_entry` after each one.
+/// Gives back the reader so the rest of the archive can be consumed
+fn unpack_tar<R: Read>(
+    reader: R,
+    dest: &Path,
+    options: &UntarOptions,
+    on_entry: impl Fn(),
+) -> Result<R, std::io::Error> {
+    let mut archive = Archive::new(reader);
+    archive.set_preserve_permissions(options.preserve_permissions);
+    // Like `Archive::unpack`, directories are applied last so the permissions of a read-only one
+    // don't prevent writing its files
+    let mut directories = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if options.keeps(&path) {
+            if entry.header().entry_type() == tar::EntryType::Directory {
+                directories.push((path, entry));
+            } else {
+                unpack_tar_entry(&mut entry, &path, dest, options.strip_components)?;
+            }
+        }
+        on_entry();
+    }
+    // Children before their parents
+    directories.sort_by(|(a, _), (b, _)| b.cmp(a));
+    for (path, mut entry) in directories {
+        unpack_tar_entry(&mut entry, &path, dest, options.strip_components)?;
+    }
+
+    Ok(archive.into_inner())
+}
+
+fn unpack_tar_entry<R: Read>(
+    entry: &mut tar::Entry<R>,
+    path: &Path,
+    dest: &Path,
+    strip_components: u32,
+) -> Result<(), std::io::Error> {
+    if strip_components == 0 {
+        // tar skips entries escaping `dest` without telling us
+        if !entry.unpack_in(dest)? {
+            return Err(outside_of_destination(&path.to_string_lossy()));
+        }
+        return Ok(());
+    }
 
-    Ok((dir, hash))
+    // `unpack_in` only works with the path from the archive so we need to do its checks ourselves
+    let Some(stripped) = strip_path(path, strip_components)? else {
+        return Ok(());
+    };
+    let out_path = dest.join(stripped);
+    ensure_within(dest, &out_path, &path.to_string_lossy())?;
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    entry.unpack(&out_path)?;
+    Ok(())
+}
+
+/// Drops the first `n` components of `path`, returning `None` if nothing is left.
+/// Errors if the path is not a plain relative path.
+fn strip_path(path: &Path, n: u32) -> Result<Option<PathBuf>, std::io::Error> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => components.push(c),
+            Component::CurDir => {}
+            _ => return Err(outside_of_destination(&path.to_string_lossy())),
+        }
+    }
+    let stripped: PathBuf = components.into_iter().skip(n as usize).collect();
+    Ok((!stripped.as_os_str().is_empty()).then_some(stripped))
+}
+
+/// Unzips the archive file by file, following the options and
+/// calling `on_entry` with the compressed size of each entry
+fn extract_zip(
+    buffer: &[u8],
+    dest: &Path,
+    options: &UntarOptions,
+    mut on_entry: impl FnMut(u64),
+) -> Result<(), std::io::Error> {
+    // zip lib requires Seek
+    let mut archive = zip::read::ZipArchive::new(std::io::Cursor::new(buffer))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let Some(relative) = file.enclosed_name() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid file path in zip archive: {}", file.name()),
+            ));
+        };
+        if options.keeps(&relative)
+            && let Some(stripped) = strip_path(&relative, options.strip_components)?
+        {
+            let out_path = dest.join(stripped);
+            // `enclosed_name` doesn't know about the symlinks we have already extracted
+            ensure_within(dest, &out_path, file.name())?;
+            extract_zip_file(&mut file, &out_path, options.preserve_permissions)?;
+        }
+        on_entry(file.compressed_size());
+    }
+
+    Ok(())
+}
+
+/// Errors if writing `path` would end up outside of `dest`, which has to be canonicalized,
+/// after following the symlinks already there
+fn ensure_within(dest: &Path, path: &Path, entry_name: &str) -> Result<(), std::io::Error> {
+    let Some(existing) = path.ancestors().find(|p| p.symlink_metadata().is_ok()) else {
+        return Err(outside_of_destination(entry_name));
+    };
+    if fs::canonicalize(existing)?.starts_with(dest) {
+        Ok(())
+    } else {
+        Err(outside_of_destination(entry_name))
+    }
+}
+
+fn outside_of_destination(entry_name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Archive entry `{entry_name}` would be extracted outside of the destination"),
+    )
+}
+
+fn extract_zip_file<R: Read>(
+    file: &mut zip::read::ZipFile<R>,
+    out_path: &Path,
+    preserve_permissions: bool,
+) -> Result<(), std::io::Error> {
+    if file.is_dir() {
+        return fs::create_dir_all(out_path);
+    }
+
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    #[cfg(unix)]
+    if file.is_symlink() {
+        let mut target = String::new();
+        file.read_to_string(&mut target)?;
+        return std::os::unix::fs::symlink(target, out_path);
+    }
+    let mut out = fs::File::create(out_path)?;
+    std::io::copy(file, &mut out)?;
+    #[cfg(unix)]
+    if let Some(mode) = file.unix_mode().filter(|_| preserve_permissions) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(out_path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// Replaces `target` with the `staging` folder, removing any existing `target`.
+/// The old `target` is only deleted once the new one is in place so a crash leaves either the old
+/// or the new folder, never a half-written one.
+/// This relies on `rename`, which is only atomic on the same filesystem: if `staging` is on a
+/// different one, it is first copied to a temporary folder next to `target` and that folder is
+/// then swapped in.
+pub(crate) fn replace_dir_atomically(staging: &Path, target: &Path) -> Result<(), std::io::Error> {
+    let parent = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    // Also removes the old target on drop
+    let scratch = tempfile::Builder::new()
+        .prefix(".rv-replace-")
+        .tempdir_in(parent)?;
+    let new = scratch.path().join("new");
+    let old = scratch.path().join("old");
+
+    // Once in the scratch folder, we know the last rename is on the same filesystem
+    match fs::rename(staging, &new) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_folder(staging, &new, &CopyOptions::default())?;
+            fs::remove_dir_all(staging)?;
+        }
+        res => res?,
+    }
+
+    let had_target = target.symlink_metadata().is_ok();
+    if had_target {
+        fs::rename(target, &old)?;
+    }
+    if let Err(e) = fs::rename(&new, target) {
+        if had_target {
+            fs::rename(&old, target)?;
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Returns all the folders directly in the given directory, sorted by name.
+/// `read_dir` order is platform dependent so we sort to always pick the same folder when an
+/// archive contains more than one top-level folder.
+pub(crate) fn top_level_dirs(dest: impl AsRef<Path>) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dest.as_ref())?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if entry.file_type().ok()?.is_dir() {
+                Some(entry.path())
+            } else {
+                None
+            }
+        })
+        .collect();
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Builds an uncompressed tarball containing the given files
+    fn make_tar_with(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Builds an uncompressed tarball containing a `pkg` folder with a DESCRIPTION file
+    fn make_tar() -> Vec<u8> {
+        make_tar_with(&[("pkg/DESCRIPTION", b"Package: pkg\nVersion: 1.0.0\n")])
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn hashed() -> UntarOptions<'static> {
+        UntarOptions {
+            hash: Some(HashAlgorithm::Sha256),
+            ..Default::default()
+        }
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[test]
+    fn can_untar_bzip2_archive() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(&make_tar()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, hash) = untar_archive(compressed.as_slice(), tempdir.path(), hashed()).unwrap();
+        let dir = dir.unwrap();
+        assert_eq!(dir.file_name().unwrap(), "pkg");
+        assert!(dir.join("DESCRIPTION").is_file());
+        assert_eq!(hash.unwrap(), sha256(&compressed));
+    }
+
+    #[test]
+    fn streaming_hash_matches_buffered_hash() {
+        // tar stops at the end-of-archive marker so whatever comes after is never read by it.
+        // Use data that doesn't compress to make sure it is not all in the decoder buffer
+        let mut tar = make_tar();
+        let mut state = 0x2545F491u32;
+        tar.extend((0..128 * 1024).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }));
+        let compressed = gzip(&tar);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (_, streamed) = untar_archive(compressed.as_slice(), tempdir.path(), hashed()).unwrap();
+        assert_eq!(streamed.unwrap(), sha256(&compressed));
+    }
+
+    #[test]
+    fn untar_errors_on_tiny_archives() {
+        for data in [&[][..], &[0x1F, 0x8B][..]] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let err = untar_archive(data, tempdir.path(), UntarOptions::default()).unwrap_err();
+            assert!(err.to_string().contains("too small"), "{err}");
+            assert!(list_archive_entries(data).is_err());
+        }
+    }
+
+    #[test]
+    fn can_untar_zstd_archive() {
+        let compressed = zstd::encode_all(make_tar().as_slice(), 0).unwrap();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, _) = untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(dir.unwrap(), tempdir.path().join("pkg"));
+    }
+
+    #[test]
+    fn can_untar_xz_archive() {
+        let mut encoder = liblzma::write::XzEncoder::new(Vec::new(), 1);
+        encoder.write_all(&make_tar()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, hash) = untar_archive(compressed.as_slice(), tempdir.path(), hashed()).unwrap();
+        assert_eq!(dir.unwrap(), tempdir.path().join("pkg"));
+        assert_eq!(hash.unwrap(), sha256(&compressed));
+    }
+
+    #[test]
+    fn all_tar_formats_round_trip() {
+        let tar = make_tar_with(&[
+            ("pkg/DESCRIPTION", b"Package: pkg\nVersion: 1.0.0\n"),
+            ("pkg/R/pkg.R", b"f <- function() 1\n"),
+        ]);
+        let mut bz2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bz2.write_all(&tar).unwrap();
+        let mut xz = liblzma::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&tar).unwrap();
+        let archives = [
+            gzip(&tar),
+            bz2.finish().unwrap(),
+            xz.finish().unwrap(),
+            zstd::encode_all(tar.as_slice(), 0).unwrap(),
+        ];
+
+        for compressed in archives {
+            let entries = list_archive_entries(compressed.as_slice()).unwrap();
+            assert_eq!(entries.len(), 2);
+
+            let tempdir = tempfile::tempdir().unwrap();
+            let (dir, hash) =
+                untar_archive(compressed.as_slice(), tempdir.path(), hashed()).unwrap();
+            let dir = dir.unwrap();
+            assert_eq!(dir, tempdir.path().join("pkg"));
+            assert_eq!(hash.unwrap(), sha256(&compressed));
+            assert_eq!(
+                std::fs::read_to_string(dir.join("R").join("pkg.R")).unwrap(),
+                "f <- function() 1\n"
+            );
+        }
+    }
+
+    #[test]
+    fn picks_first_top_level_dir_by_name() {
+        let compressed = gzip(&make_tar_with(&[
+            ("vignettes-data/data.csv", b"a,b\n"),
+            ("pkg/DESCRIPTION", b"Package: pkg\n"),
+        ]));
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, _) = untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(dir.unwrap(), tempdir.path().join("pkg"));
+        assert_eq!(
+            top_level_dirs(tempdir.path()).unwrap(),
+            vec![
+                tempdir.path().join("pkg"),
+                tempdir.path().join("vignettes-data")
+            ]
+        );
+    }
+
+    #[test]
+    fn can_untar_zstd_fixture() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, hash) = untar_archive(
+            std::fs::read("src/tests/archives/zstdpkg.tar.zst")
+                .unwrap()
+                .as_slice(),
+            tempdir.path(),
+            hashed(),
+        )
+        .unwrap();
+        let dir = dir.unwrap();
+        assert_eq!(dir, tempdir.path().join("zstdpkg"));
+        assert!(dir.join("DESCRIPTION").is_file());
+        assert_eq!(
+            hash.unwrap(),
+            "58857b671881cde09fa2554824a39824941d3ce21df9893a01e4661a0752a039"
+        );
+    }
+
+    #[test]
+    fn can_skip_paths_when_extracting() {
+        let tar = make_tar_with(&[
+            ("aaa/doc/index.html", b""),
+            ("pkg/DESCRIPTION", b"Package: pkg\n"),
+            ("pkg/doc/index.html", b""),
+            ("pkg/R/pkg.R", b""),
+        ]);
+        let keep = |path: &Path| !path.components().any(|c| c.as_os_str() == "doc");
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, _) = untar_archive(
+            gzip(&tar).as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                path_filter: Some(&keep),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let dir = dir.unwrap();
+        assert_eq!(dir.file_name().unwrap(), "pkg");
+        assert!(dir.join("DESCRIPTION").is_file());
+        assert!(dir.join("R").join("pkg.R").is_file());
+        assert!(!dir.join("doc").exists());
+        assert!(!tempdir.path().join("aaa").exists());
+    }
+
+    #[test]
+    fn rejects_zip_entries_escaping_destination() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file("../evil.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"evil").unwrap();
+        let compressed = writer.finish().unwrap().into_inner();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("dest");
+        let err = untar_archive(compressed.as_slice(), &dest, UntarOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("../evil.txt"));
+        assert!(!dest.exists());
+        assert!(!tempdir.path().join("evil.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_dir_creates_a_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("DESCRIPTION"), "Package: pkgA").unwrap();
+        let dst = tempdir.path().join("dst");
+
+        link_dir(&src, &dst).unwrap();
+        assert!(dst.is_symlink());
+        assert_eq!(fs::read_link(&dst).unwrap(), src);
+        assert!(dst.join("DESCRIPTION").is_file());
+
+        // Removing the link doesn't touch the target
+        fs::remove_file(&dst).unwrap();
+        assert!(src.join("DESCRIPTION").is_file());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn link_dir_creates_a_junction() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("DESCRIPTION"), "Package: pkgA").unwrap();
+        let dst = tempdir.path().join("dst");
+
+        link_dir(&src, &dst).unwrap();
+        // std reports junctions as symlinks to folders
+        assert!(dst.is_symlink());
+        assert!(dst.is_dir());
+        assert_eq!(
+            std::fs::read_to_string(dst.join("DESCRIPTION")).unwrap(),
+            "Package: pkgA"
+        );
+        // The junction is still there if it's not the same folder
+        assert!(link_dir(&src, &dst).is_err());
+
+        // Removing the junction doesn't touch the target
+        fs::remove_dir(&dst).unwrap();
+        assert!(src.join("DESCRIPTION").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_zip_entries_escaping_through_symlink() {
+        let outside = tempfile::tempdir().unwrap();
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .add_symlink(
+                "pkg/link",
+                outside.path().to_str().unwrap(),
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer
+            .start_file(
+                "pkg/link/evil.txt",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(b"evil").unwrap();
+        let compressed = writer.finish().unwrap().into_inner();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let err = untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("pkg/link/evil.txt"));
+        assert!(!outside.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn rejects_evil_fixtures() {
+        for fixture in ["evil.tar.gz", "evil.zip"] {
+            let archive = std::fs::read(format!("src/tests/archives/{fixture}")).unwrap();
+            let tempdir = tempfile::tempdir().unwrap();
+            let dest = tempdir.path().join("a").join("dest");
+
+            let err =
+                untar_archive(archive.as_slice(), &dest, UntarOptions::default()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{fixture}");
+            assert!(
+                err.to_string().contains("pkg/../../evil.txt"),
+                "{fixture}: {err}"
+            );
+            // Not even the valid entries before the evil one
+            assert!(!dest.exists(), "{fixture}");
+            assert_eq!(
+                std::fs::read_dir(tempdir.path().join("a")).unwrap().count(),
+                0
+            );
+            assert!(!tempdir.path().join("evil.txt").exists(), "{fixture}");
+        }
+    }
+
+    #[test]
+    fn rejects_tar_entries_escaping_destination() {
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..11].copy_from_slice(b"../evil.txt");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, b"evil".as_slice()).unwrap();
+        let compressed = gzip(&builder.into_inner().unwrap());
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("dest");
+        let err = untar_archive(compressed.as_slice(), &dest, UntarOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("../evil.txt"));
+        assert!(!dest.exists());
+        assert!(!tempdir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn mtime_recursive_skips_vcs_folders() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let source = tempdir.path().join("R").join("pkg.R");
+        let git_index = tempdir.path().join(".git").join("index");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(git_index.parent().unwrap()).unwrap();
+        std::fs::write(&source, "").unwrap();
+        std::fs::write(&git_index, "").unwrap();
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        let new = FileTime::from_unix_time(1_700_000_000, 0);
+        for path in [
+            tempdir.path(),
+            source.parent().unwrap(),
+            git_index.parent().unwrap(),
+            &source,
+        ] {
+            filetime::set_file_mtime(path, old).unwrap();
+        }
+        filetime::set_file_mtime(&git_index, new).unwrap();
+
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, false)
+                .unwrap()
+                .max_mtime,
+            old
+        );
+        assert_eq!(
+            mtime_recursive_filtered(tempdir.path(), &[], None, false)
+                .unwrap()
+                .max_mtime,
+            new
+        );
+    }
+
+    #[test]
+    fn mtime_recursive_can_skip_hidden_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let source = tempdir.path().join("R").join("pkg.R");
+        let hidden = tempdir.path().join(".__MACOSX").join("._pkg.R");
+        let rprofile = tempdir.path().join(".Rprofile");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(hidden.parent().unwrap()).unwrap();
+        for path in [&source, &hidden, &rprofile] {
+            std::fs::write(path, "").unwrap();
+        }
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        let new = FileTime::from_unix_time(1_700_000_000, 0);
+        for path in [tempdir.path(), source.parent().unwrap(), &source] {
+            filetime::set_file_mtime(path, old).unwrap();
+        }
+        for path in [hidden.parent().unwrap(), &hidden, &rprofile] {
+            filetime::set_file_mtime(path, new).unwrap();
+        }
+
+        let stats = mtime_recursive(tempdir.path(), None, true).unwrap();
+        assert_eq!((stats.file_count, stats.dir_count), (1, 1));
+        let stats = mtime_recursive(tempdir.path(), None, false).unwrap();
+        assert_eq!((stats.file_count, stats.dir_count), (3, 2));
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, true)
+                .unwrap()
+                .max_mtime,
+            old
+        );
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, false)
+                .unwrap()
+                .max_mtime,
+            new
+        );
+        // Only the hidden folder content changed
+        filetime::set_file_mtime(&rprofile, old).unwrap();
+        filetime::set_file_mtime(hidden.parent().unwrap(), old).unwrap();
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, true)
+                .unwrap()
+                .max_mtime,
+            old
+        );
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, false)
+                .unwrap()
+                .max_mtime,
+            new
+        );
+    }
+
+    #[test]
+    fn mtime_recursive_counts_files_and_folders() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("R").join("sub")).unwrap();
+        std::fs::write(tempdir.path().join("DESCRIPTION"), "Package: a").unwrap();
+        std::fs::write(tempdir.path().join("R").join("a.R"), "f <- 1").unwrap();
+        std::fs::write(tempdir.path().join("R").join("sub").join("b.R"), "").unwrap();
+
+        let stats = mtime_recursive(tempdir.path(), None, false).unwrap();
+        assert_eq!(stats.file_count, 3);
+        assert_eq!(stats.dir_count, 2);
+        assert_eq!(stats.total_size_bytes, 16);
+
+        let stats = mtime_recursive(tempdir.path().join("DESCRIPTION"), None, false).unwrap();
+        assert_eq!((stats.file_count, stats.total_size_bytes), (1, 10));
+    }
+
+    #[test]
+    fn mtime_recursive_respects_max_depth() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let nested = tempdir.path().join("R").join("sub").join("pkg.R");
+        std::fs::create_dir_all(nested.parent().unwrap()).unwrap();
+        std::fs::write(&nested, "").unwrap();
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        let new = FileTime::from_unix_time(1_700_000_000, 0);
+        for path in [
+            tempdir.path(),
+            &tempdir.path().join("R"),
+            nested.parent().unwrap(),
+        ] {
+            filetime::set_file_mtime(path, old).unwrap();
+        }
+        filetime::set_file_mtime(&nested, new).unwrap();
+
+        let stats = mtime_recursive(tempdir.path(), Some(2), false).unwrap();
+        assert_eq!((stats.file_count, stats.dir_count), (0, 2));
+        let stats = mtime_recursive(tempdir.path(), Some(3), false).unwrap();
+        assert_eq!((stats.file_count, stats.dir_count), (1, 2));
+        assert_eq!(
+            mtime_recursive(tempdir.path(), Some(2), false)
+                .unwrap()
+                .max_mtime,
+            old
+        );
+        assert_eq!(
+            mtime_recursive(tempdir.path(), Some(3), false)
+                .unwrap()
+                .max_mtime,
+            new
+        );
+        assert_eq!(
+            mtime_recursive(tempdir.path(), None, false)
+                .unwrap()
+                .max_mtime,
+            new
+        );
+    }
+
+    #[test]
+    fn can_unzip_zip64_archive() {
+        // `large_file` forces the Zip64 extra fields, the same layout as archives over 4GB
+        let options = zip::write::SimpleFileOptions::default().large_file(true);
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer.add_directory("pkg/", options).unwrap();
+        writer.start_file("pkg/DESCRIPTION", options).unwrap();
+        writer.write_all(b"Package: pkg\n").unwrap();
+        let compressed = writer.finish().unwrap().into_inner();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, _) = untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                total_size: Some(compressed.len() as u64),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(dir.unwrap(), tempdir.path().join("pkg"));
+        assert_eq!(
+            std::fs::read_to_string(tempdir.path().join("pkg").join("DESCRIPTION")).unwrap(),
+            "Package: pkg\n"
+        );
+    }
+
+    #[test]
+    fn fingerprint_only_depends_on_content() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let source = tempdir.path().join("R").join("pkg.R");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::write(&source, "f <- function() 1\n").unwrap();
+        let original = fingerprint_recursive(tempdir.path()).unwrap();
+
+        filetime::set_file_mtime(&source, FileTime::from_unix_time(1_700_000_000, 0)).unwrap();
+        std::fs::create_dir_all(tempdir.path().join(".git")).unwrap();
+        std::fs::write(tempdir.path().join(".git").join("index"), "").unwrap();
+        assert_eq!(fingerprint_recursive(tempdir.path()).unwrap(), original);
+
+        std::fs::write(&source, "f <- function() 2\n").unwrap();
+        assert_ne!(fingerprint_recursive(tempdir.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn fingerprint_sorts_by_relative_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("a")).unwrap();
+        std::fs::write(tempdir.path().join("a").join("b"), "1").unwrap();
+        std::fs::write(tempdir.path().join("a-c"), "22").unwrap();
+
+        let mut hasher = Sha256::new();
+        for (path, content) in [("a-c", "22"), ("a/b", "1")] {
+            hasher.update(path.as_bytes());
+            hasher.update([0]);
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(content.as_bytes());
+        }
+        assert_eq!(
+            fingerprint_recursive(tempdir.path()).unwrap(),
+            format!("{:x}", hasher.finalize())
+        );
+    }
+
+    #[test]
+    fn can_verify_hash_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let artifact = tempdir.path().join("R-4.3.2.tar.gz");
+        std::fs::write(&artifact, "content").unwrap();
+        let hex = sha256(b"content");
+        let hash_file = tempdir.path().join("R-4.3.2.tar.gz.sha256");
+
+        for content in [
+            format!("{hex}  R-4.3.2.tar.gz\n"),
+            format!("{hex} R-4.3.2.tar.gz"),
+            format!("{hex} *R-4.3.2.tar.gz"),
+            format!("{hex}\n"),
+            format!(
+                "{}  R-4.3.1.tar.gz\n{hex}  src/base/R-4/R-4.3.2.tar.gz\n",
+                sha256(b"other")
+            ),
+        ] {
+            std::fs::write(&hash_file, content).unwrap();
+            assert!(verify_hash_file(&artifact, &hash_file).unwrap());
+        }
+
+        std::fs::write(&hash_file, format!("{}  R-4.3.2.tar.gz", sha256(b"other"))).unwrap();
+        assert!(!verify_hash_file(&artifact, &hash_file).unwrap());
+
+        std::fs::write(&hash_file, format!("{hex}  R-4.3.1.tar.gz")).unwrap();
+        let err = verify_hash_file(&artifact, &hash_file).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replace_dir_atomically_replaces_existing_target() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let staging = tempdir.path().join("staging").join("pkg");
+        let target = tempdir.path().join("library").join("pkg");
+        std::fs::create_dir_all(staging.join("R")).unwrap();
+        std::fs::write(staging.join("DESCRIPTION"), "Version: 2.0.0").unwrap();
+        std::fs::create_dir_all(target.join("old")).unwrap();
+        std::fs::write(target.join("DESCRIPTION"), "Version: 1.0.0").unwrap();
+
+        replace_dir_atomically(&staging, &target).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(target.join("DESCRIPTION")).unwrap(),
+            "Version: 2.0.0"
+        );
+        assert!(target.join("R").is_dir());
+        assert!(!target.join("old").exists());
+        assert!(!staging.exists());
+        // No leftover scratch folder
+        assert_eq!(
+            std::fs::read_dir(target.parent().unwrap()).unwrap().count(),
+            1
+        );
+    }
+
+    #[test]
+    fn replace_dir_atomically_creates_missing_target() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let staging = tempdir.path().join("staging");
+        let target = tempdir.path().join("library").join("pkg");
+        std::fs::create_dir_all(&staging).unwrap();
+        std::fs::write(staging.join("DESCRIPTION"), "").unwrap();
+
+        replace_dir_atomically(&staging, &target).unwrap();
+
+        assert!(target.join("DESCRIPTION").is_file());
+        assert!(!staging.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn untar_keeps_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/configure", std::io::empty())
+            .unwrap();
+        let tarball = gzip(&builder.into_inner().unwrap());
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "pkg/configure",
+                zip::write::SimpleFileOptions::default().unix_permissions(0o755),
+            )
+            .unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        for (archive, preserve_permissions, executable) in [
+            (&tarball, true, true),
+            (&zip, true, true),
+            (&zip, false, false),
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            untar_archive(
+                archive.as_slice(),
+                tempdir.path(),
+                UntarOptions {
+                    preserve_permissions,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let mode = std::fs::metadata(tempdir.path().join("pkg").join("configure"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o111 != 0, executable, "{mode:o}");
+        }
+    }
+
+    #[test]
+    fn untar_checks_expected_hash() {
+        let compressed = gzip(&make_tar());
+        let expected = sha256(&compressed);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("dest");
+        let err = untar_archive(
+            compressed.as_slice(),
+            &dest,
+            UntarOptions {
+                expected_hash: Some("abc"),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        let mismatch = err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<HashMismatch>()
+            .unwrap();
+        assert_eq!(mismatch.expected, "abc");
+        assert_eq!(mismatch.actual, expected);
+        assert!(!dest.exists());
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+
+        let (dir, hash) = untar_archive(
+            compressed.as_slice(),
+            &dest,
+            UntarOptions {
+                expected_hash: Some(&expected.to_uppercase()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(dir.unwrap().join("DESCRIPTION").is_file());
+        assert_eq!(hash.unwrap(), expected);
+    }
+
+    #[test]
+    fn untar_accepts_prefixed_expected_hash() {
+        let compressed = gzip(&make_tar());
+        let sha512 = format!("{:x}", Sha512::digest(&compressed));
+        let tempdir = tempfile::tempdir().unwrap();
+        let extract = |options: UntarOptions| {
+            untar_archive(compressed.as_slice(), tempdir.path().join("dest"), options)
+        };
+
+        // The prefix picks the algorithm
+        let (_, hash) = extract(UntarOptions {
+            expected_hash: Some(&format!("sha512:{sha512}")),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(hash.unwrap(), sha512);
+        let (_, hash) = extract(UntarOptions {
+            expected_hash: Some(&format!("sha256:{}", sha256(&compressed))),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(hash.unwrap(), sha256(&compressed));
+
+        // But it can't contradict the one asked for
+        let err = extract(UntarOptions {
+            hash: Some(HashAlgorithm::Sha256),
+            expected_hash: Some(&format!("sha512:{sha512}")),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn can_split_hash_prefix() {
+        assert_eq!(
+            HashAlgorithm::split_prefix("sha512:abc"),
+            (Some(HashAlgorithm::Sha512), "abc")
+        );
+        assert_eq!(
+            HashAlgorithm::split_prefix("sha256:abc"),
+            (Some(HashAlgorithm::Sha256), "abc")
+        );
+        assert_eq!(HashAlgorithm::split_prefix("abc"), (None, "abc"));
+        assert_eq!(HashAlgorithm::split_prefix("md5:abc"), (None, "md5:abc"));
+    }
+
+    #[test]
+    fn can_strip_components() {
+        let tarball = gzip(&make_tar_with(&[
+            ("R-4.3.2/bin/R", b"#!/bin/sh\n"),
+            ("R-4.3.2/VERSION", b"4.3.2\n"),
+        ]));
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in ["R-4.3.2/bin/R", "R-4.3.2/VERSION"] {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+        }
+        let zip = writer.finish().unwrap().into_inner();
+
+        for archive in [tarball, zip] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let dest = tempdir.path().join("R");
+            let (dir, _) = untar_archive(
+                archive.as_slice(),
+                &dest,
+                UntarOptions {
+                    strip_components: 1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(dir.unwrap(), dest);
+            assert!(dest.join("bin").join("R").is_file());
+            assert!(dest.join("VERSION").is_file());
+            assert!(!dest.join("R-4.3.2").exists());
+        }
+    }
+
+    #[test]
+    fn untar_checks_manifest() {
+        let content = b"Package: pkg\n";
+        let compressed = gzip(&make_tar_with(&[("pkg/DESCRIPTION", content)]));
+        let path = PathBuf::from("pkg").join("DESCRIPTION");
+        let extract = |manifest: &HashMap<PathBuf, String>| {
+            let tempdir = tempfile::tempdir().unwrap();
+            let dest = tempdir.path().join("dest");
+            let res = untar_archive(
+                compressed.as_slice(),
+                &dest,
+                UntarOptions {
+                    manifest: Some(manifest),
+                    ..Default::default()
+                },
+            );
+            (res, dest.exists())
+        };
+
+        let (res, extracted) = extract(&HashMap::from([(path.clone(), sha256(content))]));
+        assert!(res.is_ok());
+        assert!(extracted);
+        let sha512 = format!("sha512:{:x}", Sha512::digest(content));
+        let (res, _) = extract(&HashMap::from([(path.clone(), sha512)]));
+        assert!(res.is_ok());
+
+        let (res, extracted) = extract(&HashMap::from([(path.clone(), "abc".to_string())]));
+        let err = res.unwrap_err();
+        let mismatch = err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<FileHashMismatch>()
+            .unwrap();
+        assert_eq!(mismatch.path, path);
+        assert_eq!(mismatch.expected, "abc");
+        assert_eq!(mismatch.actual, sha256(content));
+        assert!(!extracted);
+
+        let (res, extracted) = extract(&HashMap::from([(
+            PathBuf::from("pkg").join("NAMESPACE"),
+            sha256(content),
+        )]));
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert!(!extracted);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_untar_read_only_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o555);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/", std::io::empty())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(13);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/DESCRIPTION", b"Package: pkg\n".as_slice())
+            .unwrap();
+        let compressed = gzip(&builder.into_inner().unwrap());
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("out");
+        untar_archive(compressed.as_slice(), &dest, UntarOptions::default()).unwrap();
+        let dir = dest.join("pkg");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("DESCRIPTION")).unwrap(),
+            "Package: pkg\n"
+        );
+        let metadata = std::fs::metadata(&dir).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o555);
+        // So the tempdir can be removed
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn can_list_tar_entries() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/", std::io::empty())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(13);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/DESCRIPTION", b"Package: pkg\n".as_slice())
+            .unwrap();
+        let compressed = gzip(&builder.into_inner().unwrap());
+
+        let entries = list_archive_entries(compressed.as_slice()).unwrap();
+        let mtime = Some(FileTime::from_unix_time(1_700_000_000, 0));
+        assert_eq!(
+            entries,
+            vec![
+                ArchiveEntry {
+                    path: PathBuf::from("pkg/"),
+                    size: 0,
+                    kind: ArchiveEntryKind::Dir,
+                    mtime,
+                },
+                ArchiveEntry {
+                    path: PathBuf::from("pkg/DESCRIPTION"),
+                    size: 13,
+                    kind: ArchiveEntryKind::File,
+                    mtime,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn can_list_zip_entries() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .add_directory("pkg/", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("pkg/DESCRIPTION", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"Package: pkg\n").unwrap();
+        let compressed = writer.finish().unwrap().into_inner();
+
+        let entries = list_archive_entries(compressed.as_slice()).unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.size, e.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("pkg/", 0, ArchiveEntryKind::Dir),
+                ("pkg/DESCRIPTION", 13, ArchiveEntryKind::File)
+            ]
+        );
+    }
+
+    #[test]
+    fn can_list_fixture_entries() {
+        let archive = std::fs::read("src/tests/archives/zstdpkg.tar.zst").unwrap();
+        let entries = list_archive_entries(archive.as_slice()).unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.size, e.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("zstdpkg/", 0, ArchiveEntryKind::Dir),
+                ("zstdpkg/DESCRIPTION", 32, ArchiveEntryKind::File),
+            ]
+        );
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.mtime == Some(FileTime::from_unix_time(1_704_067_200, 0)))
+        );
+    }
+
+    #[test]
+    fn can_list_tar_symlinks() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder
+            .append_link(&mut header, "pkg/libs/pkg.so", "pkg.so.1")
+            .unwrap();
+        let compressed = gzip(&builder.into_inner().unwrap());
+
+        let entries = list_archive_entries(compressed.as_slice()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, ArchiveEntryKind::Symlink);
+    }
+
+    #[test]
+    fn failed_extraction_leaves_nothing_behind() {
+        // Truncated tar.gz, as if the download was interrupted
+        let mut compressed = gzip(&make_tar());
+        compressed.truncate(compressed.len() / 2);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("pkg");
+        assert!(untar_archive(compressed.as_slice(), &dest, UntarOptions::default()).is_err());
+        assert!(!dest.exists());
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn can_untar_in_existing_folder() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("pkg")).unwrap();
+        std::fs::write(tempdir.path().join("pkg").join("stale"), "").unwrap();
+        std::fs::write(tempdir.path().join("other"), "").unwrap();
+
+        let (dir, _) = untar_archive(
+            gzip(&make_tar()).as_slice(),
+            tempdir.path(),
+            UntarOptions::default(),
+        )
+        .unwrap();
+        let dir = dir.unwrap();
+        assert!(dir.join("DESCRIPTION").is_file());
+        assert!(!dir.join("stale").exists());
+        assert!(tempdir.path().join("other").is_file());
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn computes_several_hashes_in_one_pass() {
+        let archive = std::fs::read("src/tests/archives/zstdpkg.tar.zst").unwrap();
+        let sha512 = format!("{:x}", Sha512::digest(&archive));
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, hashes) = untar_archive_hashes(
+            archive.as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                expected_hash: Some(&sha256(&archive)),
+                ..Default::default()
+            },
+            &[HashAlgorithm::Sha512, HashAlgorithm::Sha256],
+        )
+        .unwrap();
+        assert!(dir.is_some());
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[&HashAlgorithm::Sha256], sha256(&archive));
+        assert_eq!(hashes[&HashAlgorithm::Sha512], sha512);
+
+        let (_, hashes) = untar_archive_hashes(
+            archive.as_slice(),
+            tempfile::tempdir().unwrap().path(),
+            UntarOptions::default(),
+            &[],
+        )
+        .unwrap();
+        assert!(hashes.is_empty());
+    }
+
+    #[test]
+    fn hashes_archive_with_requested_algorithm() {
+        let archive = std::fs::read("src/tests/archives/zstdpkg.tar.zst").unwrap();
+        let expected = [
+            (
+                Some(HashAlgorithm::Sha256),
+                Some("58857b671881cde09fa2554824a39824941d3ce21df9893a01e4661a0752a039"),
+            ),
+            (
+                Some(HashAlgorithm::Sha512),
+                Some(
+                    "65af8ceb26ab3fbae0ac005ca9eabcaeb87433b0b5e970190f933265addde3f76b946487fd943b7baedf681ff60d6145512e47b0cacc8c6ac5a5ff06cc816480",
+                ),
+            ),
+            (None, None),
+        ];
+
+        for (algorithm, digest) in expected {
+            let tempdir = tempfile::tempdir().unwrap();
+            let (_, hash) = untar_archive(
+                archive.as_slice(),
+                tempdir.path(),
+                UntarOptions {
+                    hash: algorithm,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(hash.as_deref(), digest, "{algorithm:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_folder_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let from = tempfile::tempdir().unwrap();
+        let configure = from.path().join("configure");
+        std::fs::write(&configure, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&configure, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("configure", from.path().join("configure.link")).unwrap();
+
+        let to = tempfile::tempdir().unwrap();
+        copy_folder(from.path(), to.path(), &CopyOptions::default()).unwrap();
+
+        let mode = std::fs::metadata(to.path().join("configure"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        let link = to.path().join("configure.link");
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_link(&link).unwrap(), Path::new("configure"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_folder_symlink_behaviors() {
+        let from = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(from.path().join("lib")).unwrap();
+        std::fs::write(from.path().join("lib").join("libR.so.0.0.1"), "elf").unwrap();
+        std::os::unix::fs::symlink("libR.so.0.0.1", from.path().join("lib").join("libR.so"))
+            .unwrap();
+        std::os::unix::fs::symlink("lib", from.path().join("lib64")).unwrap();
+
+        let preserved = tempfile::tempdir().unwrap();
+        copy_folder(from.path(), preserved.path(), &CopyOptions::default()).unwrap();
+        let link = preserved.path().join("lib").join("libR.so");
+        assert!(link.is_symlink());
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            PathBuf::from("libR.so.0.0.1")
+        );
+        assert!(preserved.path().join("lib64").is_symlink());
+
+        let followed = tempfile::tempdir().unwrap();
+        copy_folder(
+            from.path(),
+            followed.path(),
+            &CopyOptions {
+                symlinks: SymlinkBehavior::Follow,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let link = followed.path().join("lib").join("libR.so");
+        assert!(!link.is_symlink());
+        assert_eq!(std::fs::read_to_string(link).unwrap(), "elf");
+        let lib64 = followed.path().join("lib64");
+        assert!(!lib64.is_symlink());
+        assert!(lib64.join("libR.so.0.0.1").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_folder_can_fail_on_symlinks() {
+        let from = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(from.path().join("lib")).unwrap();
+        std::fs::write(from.path().join("lib").join("libR.so.0.0.1"), "elf").unwrap();
+        let link = from.path().join("lib").join("libR.so");
+        std::os::unix::fs::symlink("libR.so.0.0.1", &link).unwrap();
+
+        let to = tempfile::tempdir().unwrap();
+        let options = CopyOptions {
+            symlinks: SymlinkBehavior::Error,
+            ..Default::default()
+        };
+        let err = copy_folder(from.path(), to.path(), &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(
+            err.to_string().contains(&link.display().to_string()),
+            "{err}"
+        );
+        assert!(!to.path().join("lib").join("libR.so").exists());
+
+        // Without symlinks it's a normal copy
+        std::fs::remove_file(&link).unwrap();
+        copy_folder(from.path(), to.path(), &options).unwrap();
+        assert!(to.path().join("lib").join("libR.so.0.0.1").is_file());
+    }
+
+    #[test]
+    fn copy_folder_skips_filtered_entries() {
+        let from = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(from.path().join(".git").join("objects")).unwrap();
+        std::fs::write(from.path().join(".git").join("objects").join("ab"), "").unwrap();
+        std::fs::create_dir_all(from.path().join("R")).unwrap();
+        std::fs::write(from.path().join("R").join("pkg.R"), "").unwrap();
+        std::fs::write(from.path().join(".Rproj.user"), "").unwrap();
+        std::fs::write(from.path().join("DESCRIPTION"), "").unwrap();
+
+        let to = tempfile::tempdir().unwrap();
+        copy_folder(
+            from.path(),
+            to.path(),
+            &CopyOptions {
+                filter: Some(&|path| !is_tooling_metadata(path)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let parallel = tempfile::tempdir().unwrap();
+        copy_folder_parallel(
+            from.path(),
+            parallel.path(),
+            &CopyOptions {
+                filter: Some(&|path| !is_tooling_metadata(path)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        for dest in [to.path(), parallel.path()] {
+            assert!(dest.join("DESCRIPTION").is_file());
+            assert!(dest.join("R").join("pkg.R").is_file());
+            assert!(!dest.join(".git").exists());
+            assert!(!dest.join(".Rproj.user").exists());
+        }
+    }
+
+    #[test]
+    fn copy_folder_only_copies_changed_files() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let options = CopyOptions {
+            mode: CopyMode::IfChanged,
+            prune: true,
+            ..Default::default()
+        };
+        std::fs::create_dir_all(from.path().join("R")).unwrap();
+        std::fs::write(from.path().join("DESCRIPTION"), "Version: 1.0.0").unwrap();
+        std::fs::write(from.path().join("R").join("pkg.R"), "f <- 1").unwrap();
+        assert_eq!(
+            copy_folder(from.path(), to.path(), &options).unwrap(),
+            CopyStats {
+                copied: 2,
+                bytes_copied: 20,
+                dirs_created: 1,
+                skipped: 0,
+                deleted: 0
+            }
+        );
+
+        // newer in the source, identical in both, only in the destination
+        std::fs::write(from.path().join("DESCRIPTION"), "Version: 1.0.1").unwrap();
+        filetime::set_file_mtime(
+            from.path().join("DESCRIPTION"),
+            FileTime::from_unix_time(2_000_000_000, 0),
+        )
+        .unwrap();
+        std::fs::create_dir_all(to.path().join("old")).unwrap();
+        std::fs::write(to.path().join("old").join("removed.R"), "").unwrap();
+
+        assert_eq!(
+            copy_folder(from.path(), to.path(), &options).unwrap(),
+            CopyStats {
+                copied: 1,
+                bytes_copied: 14,
+                dirs_created: 0,
+                skipped: 1,
+                deleted: 1
+            }
+        );
+        // And nothing to do the next time
+        assert_eq!(
+            copy_folder_parallel(from.path(), to.path(), &options).unwrap(),
+            CopyStats {
+                skipped: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            std::fs::read_to_string(to.path().join("DESCRIPTION")).unwrap(),
+            "Version: 1.0.1"
+        );
+        assert!(!to.path().join("old").exists());
+    }
+
+    #[test]
+    fn copy_folder_preserves_mtimes() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let src = from.path().join("DESCRIPTION");
+        std::fs::write(&src, "Version: 1.0.0").unwrap();
+        let mtime = FileTime::from_unix_time(1_500_000_000, 0);
+        filetime::set_file_mtime(&src, mtime).unwrap();
+
+        copy_folder(from.path(), to.path(), &CopyOptions::default()).unwrap();
+        let meta = std::fs::metadata(to.path().join("DESCRIPTION")).unwrap();
+        // Whole seconds are fine with every filesystem granularity
+        assert_eq!(FileTime::from_last_modification_time(&meta), mtime);
+    }
+
+    #[test]
+    fn copy_folder_reports_progress() {
+        let from = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(from.path().join("R")).unwrap();
+        for i in 0..100 {
+            std::fs::write(from.path().join("R").join(format!("f{i}.R")), "").unwrap();
+        }
+
+        for parallel in [false, true] {
+            let to = tempfile::tempdir().unwrap();
+            let calls = std::sync::Mutex::new(Vec::new());
+            let on_progress = |done, total, path: &Path| {
+                assert!(path.starts_with(from.path()));
+                calls.lock().unwrap().push((done, total));
+            };
+            let options = CopyOptions {
+                on_progress: Some(&on_progress),
+                ..Default::default()
+            };
+            if parallel {
+                copy_folder_parallel(from.path(), to.path(), &options).unwrap();
+            } else {
+                copy_folder(from.path(), to.path(), &options).unwrap();
+            }
+
+            let mut calls = calls.into_inner().unwrap();
+            calls.sort();
+            assert_eq!(calls, (1..=100).map(|i| (i, 100)).collect::<Vec<_>>());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_folder_can_hard_link_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        std::fs::write(from.path().join("DESCRIPTION"), "Version: 1.0.0").unwrap();
+        let options = CopyOptions {
+            use_hard_links: true,
+            ..Default::default()
+        };
+        copy_folder(from.path(), to.path(), &options).unwrap();
+
+        // Both tempdirs are on the same filesystem
+        let src = std::fs::metadata(from.path().join("DESCRIPTION")).unwrap();
+        let dest = std::fs::metadata(to.path().join("DESCRIPTION")).unwrap();
+        assert_eq!(src.ino(), dest.ino());
+        assert_eq!(src.nlink(), 2);
+
+        // And not by default
+        let to = tempfile::tempdir().unwrap();
+        copy_folder(from.path(), to.path(), &CopyOptions::default()).unwrap();
+        let dest = std::fs::metadata(to.path().join("DESCRIPTION")).unwrap();
+        assert_ne!(src.ino(), dest.ino());
+    }
+
+    #[test]
+    fn copy_folder_parallel_matches_serial_copy() {
+        let from = tempfile::tempdir().unwrap();
+        for i in 0..5000 {
+            let folder = from.path().join(format!("dir{}", i % 50));
+            std::fs::create_dir_all(&folder).unwrap();
+            std::fs::write(folder.join(format!("file{i}")), format!("content {i}")).unwrap();
+        }
+
+        let serial = tempfile::tempdir().unwrap();
+        copy_folder(from.path(), serial.path(), &CopyOptions::default()).unwrap();
+        let parallel = tempfile::tempdir().unwrap();
+        copy_folder_parallel(from.path(), parallel.path(), &CopyOptions::default()).unwrap();
+
+        let mut count = 0;
+        for entry in WalkDir::new(serial.path()) {
+            let entry = entry.unwrap();
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(serial.path()).unwrap();
+            assert_eq!(
+                std::fs::read(entry.path()).unwrap(),
+                std::fs::read(parallel.path().join(relative)).unwrap()
+            );
+            count += 1;
+        }
+        assert_eq!(count, 5000);
+    }
+
+    #[test]
+    fn untar_reports_progress() {
+        let compressed = gzip(&make_tar_with(&[
+            ("pkg/DESCRIPTION", b"Package: pkg\n"),
+            ("pkg/R/pkg.R", b"f <- function() 1\n"),
+        ]));
+        let calls = std::cell::RefCell::new(Vec::new());
+        let on_progress = |read, total| calls.borrow_mut().push((read, total));
+
+        let tempdir = tempfile::tempdir().unwrap();
+        untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                total_size: Some(compressed.len() as u64),
+                on_progress: Some(&on_progress),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let calls = calls.into_inner();
+        // One per entry and the final one
+        assert_eq!(calls.len(), 3);
+        let total = compressed.len() as u64;
+        assert!(
+            calls
+                .iter()
+                .all(|(read, t)| *t == Some(total) && *read <= total)
+        );
+    }
+
+    #[test]
+    fn untar_reports_zip_progress() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file("pkg/DESCRIPTION", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"Package: pkg\n").unwrap();
+        let compressed = writer.finish().unwrap().into_inner();
+        let calls = std::cell::RefCell::new(Vec::new());
+        let on_progress = |read, total| calls.borrow_mut().push((read, total));
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let (dir, _) = untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                on_progress: Some(&on_progress),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(dir.unwrap().join("DESCRIPTION").is_file());
+        assert_eq!(calls.into_inner().len(), 2);
+    }
+
+    #[test]
+    fn untar_reports_progress_without_total() {
+        let compressed = gzip(&make_tar_with(&[
+            ("pkg/DESCRIPTION", b"Package: pkg\n"),
+            ("pkg/R/a.R", b"a <- 1\n"),
+            ("pkg/R/b.R", b"b <- 2\n"),
+        ]));
+        let calls = std::cell::RefCell::new(Vec::new());
+        let on_progress = |read, total| calls.borrow_mut().push((read, total));
+
+        let tempdir = tempfile::tempdir().unwrap();
+        untar_archive(
+            compressed.as_slice(),
+            tempdir.path(),
+            UntarOptions {
+                on_progress: Some(&on_progress),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let calls = calls.into_inner();
+        let total = compressed.len() as u64;
+        let (last, entries) = calls.split_last().unwrap();
+        assert_eq!(*last, (total, Some(total)));
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|(_, t)| t.is_none()));
+        assert!(calls.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
 }
@@ -103,6 +103,27 @@ impl GitUrl {
             Self::Ssh(url) => url.as_str(),
         }
     }
+
+    /// The URL of the tarball GitHub generates for the given tag, branch or commit, which is a lot
+    /// faster to download than cloning the repository.
+    /// Returns None if it's not a GitHub repository.
+    pub(crate) fn github_archive_url(&self, reference: &str) -> Option<Url> {
+        let path = match self {
+            Self::Http(url) if url.host_str() == Some("github.com") => url.path(),
+            Self::Ssh(url) => url.strip_prefix("git@github.com:")?,
+            _ => return None,
+        };
+        let mut parts = path.trim_matches('/').split('/');
+        let (Some(owner), Some(repo)) = (parts.next(), parts.next()) else {
+            return None;
+        };
+        let repo = repo.trim_end_matches(".git");
+
+        Url::parse(&format!(
+            "https://github.com/{owner}/{repo}/archive/{reference}.tar.gz"
+        ))
+        .ok()
+    }
 }
 
 impl fmt::Display for GitUrl {
@@ -116,3 +137,43 @@ impl fmt::Debug for GitUrl {
         write!(f, "\"{}\"", self.url())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_get_github_archive_url() {
+        for (url, reference, expected) in [
+            (
+                "https://github.com/r-lib/httr",
+                "v1.4.7",
+                "https://github.com/r-lib/httr/archive/v1.4.7.tar.gz",
+            ),
+            (
+                "https://github.com/r-lib/httr.git",
+                "main",
+                "https://github.com/r-lib/httr/archive/main.tar.gz",
+            ),
+            (
+                "git@github.com:r-lib/httr.git",
+                "c67018fa4970",
+                "https://github.com/r-lib/httr/archive/c67018fa4970.tar.gz",
+            ),
+        ] {
+            let git_url = GitUrl::try_from(url).unwrap();
+            assert_eq!(
+                git_url.github_archive_url(reference).unwrap().as_str(),
+                expected
+            );
+        }
+
+        for url in [
+            "https://gitlab.com/jimhester/covr",
+            "https://github.com/r-lib",
+        ] {
+            let git_url = GitUrl::try_from(url).unwrap();
+            assert_eq!(git_url.github_archive_url("main"), None);
+        }
+    }
+}
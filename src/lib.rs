@@ -1,5 +1,6 @@
 mod activate;
 mod add;
+mod auth;
 mod cache;
 mod cancellation;
 mod config;
@@ -8,9 +9,13 @@ mod git;
 mod http;
 mod library;
 mod lockfile;
+mod mirrors;
 mod package;
 mod project_summary;
 mod r_cmd;
+mod r_installations;
+mod r_version_file;
+mod r_versions;
 mod renv;
 mod repository;
 mod repository_urls;
@@ -26,20 +31,50 @@ pub mod cli;
 pub mod consts;
 
 pub use activate::{activate, deactivate};
-pub use add::{add_packages, read_and_verify_config};
-pub use cache::{CacheInfo, DiskCache, PackagePaths, utils::hash_string};
+pub use add::{
+    AddPackagesError, NotADependencyError, PackageToAdd, add_packages, read_and_verify_config,
+    remove_packages,
+};
+pub use auth::{Credentials, Secret};
+pub use cache::{
+    CacheInfo, CleanReport, DiskCache, PackagePaths, clean_cache, format_size, utils::hash_string,
+};
 pub use cancellation::Cancellation;
 pub use config::{Config, ConfigDependency, Repository};
+pub use fs::HashAlgorithm;
 pub use git::{CommandExecutor, GitExecutor, GitRepository};
-pub use http::{Http, HttpDownload};
-pub use library::Library;
-pub use lockfile::Lockfile;
-pub use package::{Version, VersionRequirement, is_binary_package};
+pub use http::{
+    BytesPerSecond, Http, HttpClient, HttpDownload, RetryPolicy, set_http_client, set_offline,
+    set_retry_policy,
+};
+pub use library::{InstallMetadata, Library};
+pub use lockfile::{FrozenLockfileError, Lockfile, check_frozen, needs_resolve};
+pub use mirrors::{CranMirror, get_cran_mirrors, parse_cran_mirrors, ping, rank_mirrors};
+pub use package::{
+    BuildPreference, PackageType, Version, VersionRequirement, is_binary_package,
+};
 pub use project_summary::ProjectSummary;
 pub use r_cmd::{RCmd, RCommandLine, find_r_version_command};
-pub use renv::RenvLock;
+pub use r_installations::{
+    InstallationCheck, InstallationIssue, RInstallation, RInstallationError,
+    find_r_installations, matching_installations, remove_r_installation, verify_r_installation,
+    write_manifest,
+};
+pub use r_version_file::{RVersionFile, RVersionFileError};
+pub use r_versions::{
+    Comparator, ManifestError, PreRelease, R_VERSION_ALIASES, R_VERSIONS_URL, RRelease, RVersion,
+    RVersionManifest, VersionConstraint, get_r_versions_manifest, is_alias, resolve_alias,
+};
+pub use renv::{
+    PackratLockError, RenvLock, RenvRVersionError, UnresolvedRenv, read_renv_lock_r_version,
+};
 pub use repository::RepositoryDatabase;
-pub use repository_urls::{get_package_file_urls, get_tarball_urls};
-pub use resolver::{Resolution, ResolvedDependency, Resolver, UnresolvedDependency};
-pub use sync::{BuildPlan, BuildStep, SyncChange, SyncHandler};
+pub use repository_urls::{BiocRepository, get_package_file_urls, get_tarball_urls};
+pub use resolver::{
+    DependencyCycle, Resolution, ResolvedDependency, Resolver, UnresolvedDependency,
+};
+pub use sync::{
+    BuildPlan, BuildStep, PlanAction, PlannedChange, SyncChange, SyncHandler, plan_changes,
+    set_force_copy,
+};
 pub use system_info::{OsType, SystemInfo};
@@ -5,12 +5,15 @@ use std::path::{Path, PathBuf};
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
 
+use url::Url;
+
 use crate::consts::{
-    DESCRIPTION_FILENAME, LIBRARY_METADATA_FILENAME, LIBRARY_ROOT_DIR_NAME, RV_DIR_NAME,
+    DESCRIPTION_FILENAME, INSTALL_METADATA_FILENAME, LIBRARY_METADATA_FILENAME,
+    LIBRARY_ROOT_DIR_NAME, RV_DIR_NAME,
 };
-use crate::fs::mtime_recursive;
+use crate::fs::{fingerprint_recursive, mtime_recursive};
 use crate::lockfile::Source;
-use crate::package::parse_version;
+use crate::package::{PackageType, parse_version};
 use crate::{ResolvedDependency, SystemInfo, Version};
 
 /// Builds the path for binary in the cache and the library based on system info and R version
@@ -33,6 +36,9 @@ fn get_current_system_path(system_info: &SystemInfo, r_version: [u32; 2]) -> Pat
 pub enum LocalMetadata {
     /// For local folders. The mtime of the source folder at the time of building
     Mtime(i64),
+    /// For local folders instead of the mtime when `RV_LOCAL_FINGERPRINT` is set.
+    /// The hash of the content of the source folder at the time of building
+    Fingerprint(String),
     /// For git repositories, URL sources and local tarballs
     Sha(String),
 }
@@ -56,7 +62,7 @@ impl LocalMetadata {
 
     pub fn sha(&self) -> Option<&str> {
         match self {
-            LocalMetadata::Mtime(_) => None,
+            LocalMetadata::Mtime(_) | LocalMetadata::Fingerprint(_) => None,
             LocalMetadata::Sha(s) => Some(s.as_str()),
         }
     }
@@ -64,11 +70,55 @@ impl LocalMetadata {
     pub fn mtime(&self) -> Option<i64> {
         match self {
             LocalMetadata::Mtime(i) => Some(*i),
-            LocalMetadata::Sha(_) => None,
+            LocalMetadata::Sha(_) | LocalMetadata::Fingerprint(_) => None,
         }
     }
 }
 
+/// How a package version got in the cache, written in the package folder so it can be looked
+/// at without network access, from the cache or from any library it's linked in.
+/// It's written again every time the package is installed in the cache and deleted with it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InstallMetadata {
+    pub name: String,
+    pub version: String,
+    /// Unix timestamp, in seconds
+    pub installed_at: i64,
+    /// Where the archive was downloaded from
+    pub source_url: Option<Url>,
+    /// The SHA256 of the downloaded archive
+    pub archive_sha256: Option<String>,
+    /// Whether we downloaded a binary or built the package from source
+    pub kind: PackageType,
+    /// `{R version}/{arch?}/{codename?}` the package was installed for
+    pub platform: String,
+    /// The folder of this package version in the cache
+    pub cache_path: PathBuf,
+}
+
+impl InstallMetadata {
+    pub fn load(folder: impl AsRef<Path>) -> Result<Option<Self>, std::io::Error> {
+        let path = folder.as_ref().join(INSTALL_METADATA_FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes it in a temporary file renamed once complete so readers never see half of it
+    pub fn write(&self, folder: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let folder = folder.as_ref();
+        let mut f = tempfile::NamedTempFile::new_in(folder)?;
+        f.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())?;
+        f.persist(folder.join(INSTALL_METADATA_FILENAME))
+            .map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Library {
     /// This is the path where the packages are installed so
@@ -188,13 +238,20 @@ impl Library {
                 if let Some(metadata) = self.non_repo_packages.get(pkg.name.as_ref()) {
                     match metadata {
                         LocalMetadata::Mtime(local_mtime) => {
-                            let current_mtime =
-                                match mtime_recursive(pkg.local_resolved_path.clone().unwrap()) {
-                                    Ok(m) => m,
-                                    Err(_) => return false,
-                                };
+                            let current_mtime = match mtime_recursive(
+                                pkg.local_resolved_path.clone().unwrap(),
+                                None,
+                                false,
+                            ) {
+                                Ok(stats) => stats.max_mtime,
+                                Err(_) => return false,
+                            };
                             current_mtime.unix_seconds() == *local_mtime
                         }
+                        LocalMetadata::Fingerprint(fingerprint) => {
+                            fingerprint_recursive(pkg.local_resolved_path.clone().unwrap())
+                                .is_ok_and(|current| &current == fingerprint)
+                        }
                         LocalMetadata::Sha(local_sha) => {
                             if let Some(s) = sha {
                                 s == local_sha
@@ -212,3 +269,54 @@ impl Library {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_metadata_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        assert_eq!(InstallMetadata::load(tempdir.path()).unwrap(), None);
+
+        let mut metadata = InstallMetadata {
+            name: "R6".to_string(),
+            version: "2.5.1".to_string(),
+            installed_at: 1_700_000_000,
+            source_url: Some(
+                Url::parse("https://cran.r-project.org/src/contrib/R6_2.5.1.tar.gz").unwrap(),
+            ),
+            archive_sha256: Some("abc".to_string()),
+            kind: PackageType::Source,
+            platform: "4.4/x86_64/jammy".to_string(),
+            cache_path: tempdir.path().to_path_buf(),
+        };
+        metadata.write(tempdir.path()).unwrap();
+        assert_eq!(
+            InstallMetadata::load(tempdir.path()).unwrap().as_ref(),
+            Some(&metadata)
+        );
+
+        // Installing it again replaces it and leaves no temporary file around
+        metadata.kind = PackageType::Binary;
+        metadata.write(tempdir.path()).unwrap();
+        assert_eq!(
+            InstallMetadata::load(tempdir.path()).unwrap(),
+            Some(metadata)
+        );
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn local_metadata_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for metadata in [
+            LocalMetadata::Mtime(1_700_000_000),
+            LocalMetadata::Sha("abc".to_string()),
+            LocalMetadata::Fingerprint("def".to_string()),
+        ] {
+            metadata.write(tempdir.path()).unwrap();
+            assert_eq!(LocalMetadata::load(tempdir.path()).unwrap(), Some(metadata));
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use filetime::FileTime;
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
 use toml_edit::{Array, ArrayOfTables, InlineTable, Item, Table, Value};
@@ -383,6 +384,12 @@ impl LockedPackage {
             }
         }
 
+        if let Some(req) = dep.version_requirement()
+            && !Version::from_str(&self.version).is_ok_and(|v| req.is_satisfied(&v))
+        {
+            return false;
+        }
+
         true
     }
 }
@@ -435,6 +442,16 @@ impl Lockfile {
         Ok(())
     }
 
+    /// For lockfiles converted from other tools
+    pub(crate) fn from_packages(r_version: &str, mut packages: Vec<LockedPackage>) -> Self {
+        packages.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            version: CURRENT_LOCKFILE_VERSION,
+            r_version: r_version.to_string(),
+            packages,
+        }
+    }
+
     pub fn from_resolved(r_version: &[u32; 2], deps: Vec<ResolvedDependency>) -> Self {
         let mut packages: Vec<_> = deps
             .into_iter()
@@ -454,8 +471,12 @@ impl Lockfile {
         doc.insert("version", Item::Value(Value::from(self.version)));
         doc.insert("r_version", Item::Value(Value::from(&self.r_version)));
 
+        // Packages are already sorted when coming from a resolution but not necessarily if the
+        // lockfile was edited by hand. Sorting keeps the output stable either way.
+        let mut sorted: Vec<_> = self.packages.iter().collect();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
         let mut packages = ArrayOfTables::new();
-        for p in self.packages.iter() {
+        for p in sorted {
             packages.push(p.as_toml_table());
         }
         doc.insert("packages", Item::ArrayOfTables(packages));
@@ -569,6 +590,44 @@ impl Lockfile {
         })
     }
 
+    /// Removes the given packages so they get resolved again, eg to upgrade them.
+    /// Packages depending on them are kept so the lockfile might not be complete anymore.
+    pub fn remove_packages(&mut self, names: &[String]) {
+        self.packages.retain(|p| !names.contains(&p.name));
+    }
+
+    /// Removes the packages that are not required anymore by the given top level dependencies,
+    /// directly or not. A package still needed by another dependency is kept.
+    /// Returns the names of the removed packages, sorted.
+    pub fn prune(&mut self, top_level: &[&str]) -> Vec<String> {
+        let mut required = HashSet::new();
+        let mut queue: Vec<_> = top_level.to_vec();
+        while let Some(name) = queue.pop() {
+            if !required.insert(name.to_string()) {
+                continue;
+            }
+            if let Some(p) = self.get_package(name, None) {
+                let suggests = p.suggests.iter().filter(|_| p.install_suggests());
+                queue.extend(p.dependencies.iter().chain(suggests).map(|d| d.name()));
+            }
+        }
+
+        let mut removed: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|p| !required.contains(&p.name))
+            .map(|p| p.name.clone())
+            .collect();
+        removed.sort();
+        self.packages.retain(|p| required.contains(&p.name));
+        removed
+    }
+
+    /// All the packages listed in the lockfile, in the order they are listed
+    pub fn packages(&self) -> &[LockedPackage] {
+        &self.packages
+    }
+
     /// Gets a set of all the package names listed in the lockfile
     pub fn package_names(&self) -> HashSet<&str> {
         let mut out = HashSet::new();
@@ -586,6 +645,80 @@ impl Lockfile {
     pub fn version(&self) -> i64 {
         self.version
     }
+
+    /// What changes from that lockfile to the other one, one line per change in alphabetical
+    /// order: `+ name version` for added packages, `- name version` for removed ones and
+    /// `~ name ...` for the ones with a different version, source or dependencies.
+    pub fn diff(&self, other: &Lockfile) -> Vec<String> {
+        let mut out = Vec::new();
+        if self.r_version != other.r_version {
+            out.push(format!("~ R {} → {}", self.r_version, other.r_version));
+        }
+
+        let mut names: Vec<_> = self
+            .package_names()
+            .union(&other.package_names())
+            .copied()
+            .collect();
+        names.sort_unstable();
+        for name in names {
+            let before = self.packages.iter().find(|p| p.name == name);
+            let after = other.packages.iter().find(|p| p.name == name);
+            match (before, after) {
+                (None, Some(p)) => out.push(format!("+ {name} {} ({})", p.version, p.source)),
+                (Some(p), None) => out.push(format!("- {name} {}", p.version)),
+                (Some(b), Some(a)) if b != a => {
+                    let change = if b.version != a.version {
+                        format!("{} → {}", b.version, a.version)
+                    } else if b.source != a.source {
+                        format!("{} → {}", b.source, a.source)
+                    } else {
+                        "dependencies changed".to_string()
+                    };
+                    out.push(format!("~ {name} {change}"));
+                }
+                _ => (),
+            }
+        }
+        out
+    }
+}
+
+/// Whether the config file was modified after the lockfile was written, which means the
+/// lockfile might not match it anymore. If either file is missing, we need to resolve.
+/// The config doesn't include other files so its own mtime is all we need to look at.
+/// This only compares mtimes, which is much cheaper than checking the dependencies against
+/// the lockfile but can be wrong if the mtimes are not reliable, eg after a `git checkout`.
+pub fn needs_resolve(config: impl AsRef<Path>, lockfile: impl AsRef<Path>) -> bool {
+    let mtime = |path: &Path| {
+        fs::metadata(path)
+            .map(|m| FileTime::from_last_modification_time(&m))
+            .ok()
+    };
+    match (mtime(config.as_ref()), mtime(lockfile.as_ref())) {
+        (Some(config), Some(lockfile)) => config >= lockfile,
+        _ => true,
+    }
+}
+
+/// For `--frozen`: errors if the lockfile we would write after resolving is not the one we have.
+/// Not having a lockfile is only fine if there would be nothing in it.
+pub fn check_frozen(
+    existing: Option<&Lockfile>,
+    resolved: &Lockfile,
+) -> Result<(), FrozenLockfileError> {
+    match existing {
+        None if resolved.packages.is_empty() => Ok(()),
+        None => Err(FrozenLockfileError::Missing),
+        Some(existing) => {
+            let diff = existing.diff(resolved);
+            if diff.is_empty() {
+                Ok(())
+            } else {
+                Err(FrozenLockfileError::Outdated(diff))
+            }
+        }
+    }
 }
 
 impl FromStr for Lockfile {
@@ -608,6 +741,17 @@ pub struct LockfileError {
     pub source: LockfileErrorKind,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum FrozenLockfileError {
+    #[error("The lockfile is missing and `--frozen` forbids creating it")]
+    Missing,
+    #[error(
+        "The lockfile is not up to date and `--frozen` forbids updating it:\n{}",
+        .0.join("\n")
+    )]
+    Outdated(Vec<String>),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum LockfileErrorKind {
     #[error(transparent)]
@@ -617,3 +761,105 @@ pub enum LockfileErrorKind {
     #[error("Invalid lockfile: {0}")]
     Invalid(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockfile_round_trips() {
+        let path = "src/tests/lockfiles/rv.lock";
+        let lockfile = Lockfile::load(path).unwrap().unwrap();
+        assert_eq!(lockfile.packages.len(), 6);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let out = tempdir.path().join("rv.lock");
+        lockfile.save(&out).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), fs::read(path).unwrap());
+        assert_eq!(Lockfile::load(&out).unwrap().unwrap(), lockfile);
+    }
+
+    #[test]
+    fn prune_keeps_shared_dependencies() {
+        let mut lockfile = Lockfile::load("src/tests/lockfiles/remove.lock")
+            .unwrap()
+            .unwrap();
+        // A depends on B and C, D depends on C
+        let removed = lockfile.prune(&["D"]);
+        assert_eq!(removed, vec!["A", "B"]);
+        let mut remaining: Vec<_> = lockfile.package_names().into_iter().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["C", "D"]);
+        assert!(lockfile.prune(&["D"]).is_empty());
+    }
+
+    #[test]
+    fn needs_resolve_if_config_is_newer() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = tempdir.path().join("rproject.toml");
+        let lockfile = tempdir.path().join("rv.lock");
+        assert!(needs_resolve(&config, &lockfile));
+        std::fs::write(&config, "").unwrap();
+        std::fs::write(&lockfile, "").unwrap();
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        let new = FileTime::from_unix_time(1_700_000_000, 0);
+
+        // Lockfile written after the config was last edited
+        filetime::set_file_mtime(&config, old).unwrap();
+        filetime::set_file_mtime(&lockfile, new).unwrap();
+        assert!(!needs_resolve(&config, &lockfile));
+
+        // Config edited after the lockfile was written
+        filetime::set_file_mtime(&config, new).unwrap();
+        filetime::set_file_mtime(&lockfile, old).unwrap();
+        assert!(needs_resolve(&config, &lockfile));
+
+        // Same mtime, we can't tell which one came first
+        filetime::set_file_mtime(&lockfile, new).unwrap();
+        assert!(needs_resolve(&config, &lockfile));
+
+        std::fs::remove_file(&lockfile).unwrap();
+        assert!(needs_resolve(&config, &lockfile));
+    }
+
+    #[test]
+    fn frozen_accepts_up_to_date_lockfile() {
+        let lockfile = Lockfile::load("src/tests/lockfiles/tree.lock")
+            .unwrap()
+            .unwrap();
+        assert!(check_frozen(Some(&lockfile), &lockfile.clone()).is_ok());
+        assert!(check_frozen(None, &Lockfile::new("4.4")).is_ok());
+    }
+
+    #[test]
+    fn frozen_rejects_stale_lockfile() {
+        let existing = Lockfile::load("src/tests/lockfiles/rv.lock")
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            check_frozen(None, &existing),
+            Err(FrozenLockfileError::Missing)
+        ));
+
+        let mut resolved = existing.clone();
+        let removed = resolved.packages.remove(0);
+        resolved.packages[0].version = "99.0.0".to_string();
+        resolved.packages[1].dependencies.clear();
+        let mut added = removed.clone();
+        added.name = "newpkg".to_string();
+        resolved.packages.push(added);
+
+        let err = check_frozen(Some(&existing), &resolved).unwrap_err();
+        insta::assert_snapshot!(err.to_string());
+    }
+
+    #[test]
+    fn lockfile_packages_are_sorted_by_name() {
+        let mut lockfile = Lockfile::load("src/tests/lockfiles/rv.lock")
+            .unwrap()
+            .unwrap();
+        let expected = lockfile.as_toml_string();
+        lockfile.packages.reverse();
+        assert_eq!(lockfile.as_toml_string(), expected);
+    }
+}
@@ -1,21 +1,30 @@
 use clap::{Parser, Subcommand};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use fs_err::{self as fs, read_to_string, write};
 use serde::Serialize;
 use serde_json::json;
+use url::Url;
 
 use rv::cli::utils::timeit;
 use rv::cli::{
-    CliContext, RCommandLookup, find_r_repositories, init, init_structure, migrate_renv, tree,
+    CliContext, OUTDATED_HEADER, RCommandLookup, check_pinned_r_version, export_renv,
+    find_r_repositories, import_packrat, import_renv, init, init_structure, migrate_renv, outdated,
+    project_r_version, set_mirror, tree, verify, why,
 };
+use rv::consts::R_VERSION_FILENAME;
 use rv::system_req::{SysDep, SysInstallationStatus};
 use rv::{
-    CacheInfo, Config, GitExecutor, Http, Lockfile, ProjectSummary, RCmd, RCommandLine, Resolution,
-    Resolver, SyncChange, SyncHandler, Version, activate, add_packages, deactivate,
-    read_and_verify_config, system_req,
+    CacheInfo, Config, DiskCache, GitExecutor, Http, InstallMetadata, Lockfile, PackageToAdd,
+    PackageType, PlannedChange, ProjectSummary, R_VERSION_ALIASES, RCmd, RCommandLine,
+    RInstallationError, RVersion, RVersionFile, Resolution, Resolver, SyncChange, SyncHandler,
+    SystemInfo, UnresolvedRenv, Version, activate, add_packages, check_frozen, clean_cache,
+    deactivate, find_r_installations, find_r_version_command, format_size, get_cran_mirrors,
+    get_r_versions_manifest, is_alias, matching_installations, plan_changes, rank_mirrors,
+    read_and_verify_config, read_renv_lock_r_version, remove_packages, remove_r_installation,
+    resolve_alias, system_req, verify_r_installation, write_manifest,
 };
 
 #[derive(Parser)]
@@ -32,6 +41,16 @@ pub struct Cli {
     #[clap(short = 'c', long, default_value = "rproject.toml", global = true)]
     pub config_file: PathBuf,
 
+    /// Only install from what is already in the cache, without any network access.
+    /// Fails if something needs to be downloaded. Can also be set with `RV_OFFLINE=1`.
+    #[clap(long, global = true)]
+    offline: bool,
+
+    /// Copy the packages from the cache into the library instead of linking them,
+    /// eg on filesystems without symlinks. Overrides `RV_LINK_MODE`.
+    #[clap(long, global = true)]
+    copy: bool,
+
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -69,22 +88,45 @@ pub enum Command {
         /// The command will not error even if this R version is not found
         #[clap(long)]
         r_version: Option<Version>,
+        /// Fail if the lockfile is missing or not up to date instead of updating it
+        #[clap(long)]
+        frozen: bool,
     },
     /// Replaces the library with exactly what is in the lock file
     Sync {
         #[clap(long)]
         save_install_logs_in: Option<PathBuf>,
+        /// Fail if the lockfile is missing or not up to date instead of updating it, eg in CI
+        #[clap(long)]
+        frozen: bool,
     },
     /// Add simple packages to the project and sync
     Add {
+        /// The packages to add, as `name` or `name@version`.
+        /// A version without an operator, eg `dplyr@1.1.4`, means that exact version
         #[clap(value_parser, required = true)]
-        packages: Vec<String>,
+        packages: Vec<PackageToAdd>,
         #[clap(long)]
         /// Do not make any changes, only report what would happen if those packages were added         
         dry_run: bool,
         #[clap(long)]
         /// Add packages to config file, but do not sync. No effect if --dry-run is used
         no_sync: bool,
+        #[clap(long)]
+        /// Allow packages already in the config: their version is replaced by the one given, if any,
+        /// and they are resolved again instead of using the lockfile
+        upgrade: bool,
+    },
+    /// Remove packages from the project, as well as the packages only they depended on
+    Remove {
+        #[clap(value_parser, required = true)]
+        packages: Vec<String>,
+        #[clap(long)]
+        /// Do not make any changes, only report what would happen if those packages were removed
+        dry_run: bool,
+        #[clap(long)]
+        /// Remove packages from the config file and lockfile, but do not sync. No effect if --dry-run is used
+        no_sync: bool,
     },
     /// Provide a summary about the project status
     Summary {
@@ -107,7 +149,10 @@ pub enum Command {
         repositories: bool,
     },
     /// Gives information about where the cache is for that project
-    Cache,
+    Cache {
+        #[clap(subcommand)]
+        subcommand: Option<CacheSubcommand>,
+    },
     /// Upgrade packages to the latest versions available
     Upgrade {
         #[clap(long)]
@@ -118,6 +163,21 @@ pub enum Command {
         #[clap(subcommand)]
         subcommand: MigrateSubcommand,
     },
+    /// Import a project from another tool, keeping the exact versions it recorded
+    Import {
+        #[clap(subcommand)]
+        subcommand: ImportSubcommand,
+    },
+    /// Export the project for another tool
+    Export {
+        #[clap(subcommand)]
+        subcommand: ExportSubcommand,
+    },
+    /// Manage the mirrors tried when a repository can't be reached
+    Mirror {
+        #[clap(subcommand)]
+        subcommand: MirrorSubcommand,
+    },
     /// Activate a previously initialized rv project
     Activate {
         #[clap(long)]
@@ -145,6 +205,11 @@ pub enum Command {
     },
     /// Shows the project packages in tree format
     Tree {
+        /// Only show the tree of that package, which can be a transitive dependency
+        package: Option<String>,
+        #[clap(long, requires = "package")]
+        /// Show the packages depending on the given package instead of its dependencies
+        invert: bool,
         #[clap(long)]
         /// How deep are we going in the tree: 1 == only root deps, 2 == root deps + their direct dep etc
         /// Defaults to showing everything
@@ -159,6 +224,116 @@ pub enum Command {
         /// The command will not error even if this R version is not found
         r_version: Option<Version>,
     },
+    /// Shows the shortest dependency paths from the project dependencies to a package,
+    /// using the lockfile
+    Why {
+        /// The package to explain
+        package: String,
+    },
+    /// Lists the locked packages that have a newer version in their repository.
+    /// This does not change anything in the project
+    Outdated,
+    /// Checks that the packages installed in the library are the ones from the lockfile,
+    /// exiting with an error if there is any difference
+    Verify,
+    /// Shows how an installed package got there: when it was added to the cache, whether it's
+    /// a binary or was built from source, where it was downloaded from and the SHA256 of the
+    /// archive. This only reads the library so it works offline
+    Metadata {
+        /// The package to show
+        package: String,
+    },
+    /// Pins the R version to use in the project directory and its subdirectories by writing a
+    /// `.R-version` file, taking precedence over the R version of the config.
+    /// Without a version, shows the pinned version and which `.R-version` file it comes from
+    Local {
+        /// A version like `4.4.1` or an alias like `release`
+        version: Option<String>,
+        /// Pin the R version declared in the renv.lock of the project directory
+        #[clap(long, conflicts_with = "version")]
+        from_renv: bool,
+    },
+    /// Manage the R installations rv can use: the R on the PATH and the ones in /opt/R, where
+    /// rig installs them
+    R {
+        #[clap(subcommand)]
+        subcommand: RSubcommand,
+    },
+    /// Manage the cached list of R versions
+    Manifest {
+        #[clap(subcommand)]
+        subcommand: ManifestSubcommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheSubcommand {
+    /// Removes the cached packages that are not in the lockfile of any project synced on this
+    /// machine, showing the size of the cache and how much was freed
+    Clean {
+        /// Remove all the cached packages instead
+        #[clap(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ManifestSubcommand {
+    /// Download the list of R versions again, even if the cached one is recent enough
+    Refresh,
+    /// Shows which R version an alias refers to: `release` (or `latest`), `oldrel`,
+    /// `oldrel-<n>` for the nth minor release before the current one, `devel` or
+    /// `devel-<yyyy-mm-dd>` for the devel snapshot of that day
+    Resolve { alias: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RSubcommand {
+    /// List the R installations, marking with `*` the one the project uses, or the R on the
+    /// PATH outside of a project
+    List,
+    /// Pins an installed R version for the project directory and its subdirectories, writing a
+    /// `.R-version` file like `rv local` but checking the version is installed first
+    Use { version: String },
+    /// Checks that an R installation works: R runs and reports its version, the key files are
+    /// there and, if a manifest was written, no file changed since.
+    /// Without a version, checks the one the project uses. Exits with an error on any problem
+    Verify {
+        version: Option<String>,
+        /// Check all the installations
+        #[clap(long, conflicts_with = "version")]
+        all: bool,
+        /// Record the SHA256 of all the files of the installations in /opt/R instead of checking
+        /// them, for later verifications
+        #[clap(long)]
+        write_manifest: bool,
+    },
+    /// Deletes an R installation from /opt/R. The R on the PATH can't be removed by rv
+    Remove {
+        version: String,
+        /// Don't ask for confirmation when removing the R version the project uses
+        #[clap(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MirrorSubcommand {
+    /// List the CRAN mirrors
+    List,
+    /// Measure the latency to each CRAN mirror and rank them, fastest first
+    Ping {
+        /// Only show that many mirrors
+        #[clap(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Add a mirror to a repository in the config, it will be the first mirror tried
+    Set {
+        url: Url,
+        /// The alias of the repository, defaults to the first one in the config
+        #[clap(long)]
+        repository: Option<String>,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -174,6 +349,41 @@ pub enum MigrateSubcommand {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum ImportSubcommand {
+    /// Converts a renv.lock into a config and a rv.lock without touching the network
+    Renv {
+        #[clap(value_parser, default_value = "renv.lock")]
+        renv_file: PathBuf,
+        #[clap(long)]
+        /// Include the patch in the R version
+        strict_r_version: bool,
+        #[clap(long)]
+        /// Turn off rv access through .rv R environment
+        no_r_environment: bool,
+    },
+    /// Converts a packrat.lock into a config and a rv.lock without touching the network
+    Packrat {
+        #[clap(value_parser, default_value = "packrat/packrat.lock")]
+        packrat_file: PathBuf,
+        #[clap(long)]
+        /// Include the patch in the R version
+        strict_r_version: bool,
+        #[clap(long)]
+        /// Turn off rv access through .rv R environment
+        no_r_environment: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportSubcommand {
+    /// Writes a renv.lock with the packages of the rv.lock
+    Renv {
+        #[clap(value_parser, default_value = "renv.lock")]
+        path: PathBuf,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum ResolveMode {
     Default,
@@ -223,6 +433,7 @@ fn resolve_dependencies<'a>(
     if context.show_progress_bar {
         resolver.show_progress_bar();
     }
+    resolver.set_offline(context.offline);
 
     let mut resolution = resolver.resolve(
         context.config.dependencies(),
@@ -244,6 +455,10 @@ fn resolve_dependencies<'a>(
             eprintln!("{}", req_error_messages.join("\n"));
         }
 
+        for cycle in resolution.cycles {
+            eprintln!("    {cycle}");
+        }
+
         ::std::process::exit(1)
     }
 
@@ -268,23 +483,22 @@ fn resolve_dependencies<'a>(
 }
 
 #[derive(Debug, Default, Serialize)]
-struct SyncChanges {
-    installed: Vec<SyncChange>,
-    removed: Vec<SyncChange>,
+struct SyncChanges<'a> {
+    installed: Vec<&'a SyncChange>,
+    removed: Vec<&'a SyncChange>,
+    /// Only for dry runs: the changes compared to the library
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<Vec<PlannedChange<'a>>>,
 }
 
-impl SyncChanges {
-    fn from_changes(changes: Vec<SyncChange>) -> Self {
-        let mut installed = vec![];
-        let mut removed = vec![];
-        for change in changes {
-            if change.installed {
-                installed.push(change);
-            } else {
-                removed.push(change);
-            }
+impl<'a> SyncChanges<'a> {
+    fn from_changes(changes: &'a [SyncChange], plan: Option<Vec<PlannedChange<'a>>>) -> Self {
+        let (installed, removed) = changes.iter().partition(|c| c.installed);
+        Self {
+            installed,
+            removed,
+            plan,
         }
-        Self { installed, removed }
     }
 }
 
@@ -295,7 +509,7 @@ fn _sync(
     resolve_mode: ResolveMode,
     output_format: OutputFormat,
     save_install_logs_in: Option<PathBuf>,
-) -> Result<()> {
+) -> Result<Vec<String>> {
     if !has_logs_enabled {
         context.show_progress_bar();
     }
@@ -309,6 +523,11 @@ fn _sync(
     context.load_system_requirements()?;
 
     let resolved = resolve_dependencies(&context, &resolve_mode, true).found;
+    if context.frozen {
+        let lockfile = Lockfile::from_resolved(&context.r_version.major_minor(), resolved.clone());
+        check_frozen(context.lockfile.as_ref(), &lockfile)?;
+    }
+    let resolved_names: Vec<_> = resolved.iter().map(|d| d.name.to_string()).collect();
 
     match timeit!(
         if dry_run {
@@ -331,11 +550,13 @@ fn _sync(
                 handler.show_progress_bar();
             }
             handler.set_uses_lockfile(context.config.use_lockfile());
+            handler.set_mirrors(context.config.mirrors());
+            handler.set_cache_max_size(context.config.cache_max_size());
             handler.handle(&resolved, &context.r_cmd)
         }
     ) {
         Ok(mut changes) => {
-            if !dry_run && context.config.use_lockfile() {
+            if !dry_run && !context.frozen && context.config.use_lockfile() {
                 if resolved.is_empty() {
                     // delete the lockfiles if there are no dependencies
                     let lockfile_path = context.lockfile_path();
@@ -353,6 +574,8 @@ fn _sync(
                     } else {
                         lockfile.save(context.lockfile_path())?;
                     }
+                    // So `rv cache clean` knows this project uses those packages
+                    context.cache.register_lockfile(context.lockfile_path());
                 }
             }
             let all_sys_deps: HashSet<_> = changes
@@ -376,21 +599,26 @@ fn _sync(
                 }
             }
 
+            let plan = dry_run.then(|| plan_changes(&changes, &context.library.packages));
             if output_format.is_json() {
                 println!(
                     "{}",
-                    serde_json::to_string_pretty(&SyncChanges::from_changes(changes,))
+                    serde_json::to_string_pretty(&SyncChanges::from_changes(&changes, plan))
                         .expect("valid json")
                 );
             } else if changes.is_empty() {
                 println!("Nothing to do");
+            } else if let Some(plan) = plan {
+                for c in plan {
+                    println!("{}", c.print(!sysdeps_status.is_empty()));
+                }
             } else {
                 for c in changes {
                     println!("{}", c.print(!dry_run, !sysdeps_status.is_empty()));
                 }
             }
 
-            Ok(())
+            Ok(resolved_names)
         }
         Err(e) => {
             if context.staging_path().is_dir() {
@@ -401,6 +629,81 @@ fn _sync(
     }
 }
 
+/// The cache holding the R versions manifest, which is shared by all projects so the manifest
+/// commands work outside of one
+fn manifest_cache() -> Result<DiskCache> {
+    // Only the manifest is needed so the R version doesn't matter here
+    let r_version = "4.0".parse::<Version>().unwrap();
+    DiskCache::new(&r_version, SystemInfo::from_os_info()).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// Where the config file is, the `.R-version` file of the project is looked for from there
+fn project_dir(config_file: &Path) -> PathBuf {
+    match config_file.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Sets up the project around the config written by `rv migrate renv` or `rv import`
+fn finish_renv_conversion(
+    config_file: &std::path::Path,
+    renv_file: &std::path::Path,
+    no_r_environment: bool,
+    unresolved: &[UnresolvedRenv],
+    verb: &str,
+    output_format: &OutputFormat,
+) -> Result<()> {
+    // the config file was just created, so parent directory is confirmed to exist
+    let project_dir = &config_file.canonicalize()?.parent().unwrap().to_path_buf();
+    init_structure(project_dir)?;
+    activate(project_dir, no_r_environment)?;
+    let content = read_to_string(project_dir.join(".Rprofile"))?
+        .replace(
+            "source(\"renv/activate.R\")",
+            "# source(\"renv/activate.R\")",
+        )
+        .replace("source(\"packrat/init.R\")", "# source(\"packrat/init.R\")");
+    write(project_dir.join(".Rprofile"), content)?;
+
+    if unresolved.is_empty() {
+        if output_format.is_json() {
+            println!(
+                "{}",
+                json!({
+                    "success": true,
+                    "unresolved": [],
+                })
+            );
+        } else {
+            println!(
+                "{} was successfully {verb} to {}",
+                renv_file.display(),
+                config_file.display()
+            );
+        }
+    } else if output_format.is_json() {
+        println!(
+            "{}",
+            json!({
+                "success": false,
+                "unresolved": unresolved.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            })
+        );
+    } else {
+        println!(
+            "{} was {verb} to {} with {} unresolved packages: ",
+            renv_file.display(),
+            config_file.display(),
+            unresolved.len()
+        );
+        for u in unresolved {
+            eprintln!("    {u}");
+        }
+    }
+    Ok(())
+}
+
 fn try_main() -> Result<()> {
     let cli = Cli::parse();
     let output_format = if cli.json {
@@ -420,6 +723,13 @@ fn try_main() -> Result<()> {
         .filter(Some("os_info"), log::LevelFilter::Off)
         .init();
 
+    if cli.offline {
+        rv::set_offline(true);
+    }
+    if cli.copy {
+        rv::set_force_copy(true);
+    }
+
     match cli.command {
         Command::Init {
             project_directory,
@@ -494,19 +804,26 @@ fn try_main() -> Result<()> {
                 println!("{path_out}");
             }
         }
-        Command::Plan { upgrade, r_version } => {
+        Command::Plan {
+            upgrade,
+            r_version,
+            frozen,
+        } => {
             let upgrade = if upgrade || r_version.is_some() {
                 ResolveMode::FullUpgrade
             } else {
                 ResolveMode::Default
             };
-            let context = CliContext::new(&cli.config_file, r_version.into())?;
+            let mut context = CliContext::new(&cli.config_file, r_version.into())?;
+            context.frozen = frozen;
             _sync(context, true, log_enabled, upgrade, output_format, None)?;
         }
         Command::Sync {
             save_install_logs_in,
+            frozen,
         } => {
-            let context = CliContext::new(&cli.config_file, RCommandLookup::Strict)?;
+            let mut context = CliContext::new(&cli.config_file, RCommandLookup::Strict)?;
+            context.frozen = frozen;
             _sync(
                 context,
                 false,
@@ -520,10 +837,12 @@ fn try_main() -> Result<()> {
             packages,
             dry_run,
             no_sync,
+            upgrade,
         } => {
+            let names: Vec<_> = packages.iter().map(|p| p.name().to_string()).collect();
             // load config to verify structure is valid
             let mut doc = read_and_verify_config(&cli.config_file)?;
-            add_packages(&mut doc, packages)?;
+            add_packages(&mut doc, packages, upgrade)?;
             // write the update if not dry run
             if !dry_run {
                 write(&cli.config_file, doc.to_string())?;
@@ -543,6 +862,99 @@ fn try_main() -> Result<()> {
             if dry_run {
                 context.config = doc.to_string().parse::<Config>()?;
             }
+            if upgrade && let Some(lockfile) = context.lockfile.as_mut() {
+                lockfile.remove_packages(&names);
+            }
+            let previous: HashSet<_> = context
+                .lockfile
+                .as_ref()
+                .map(|l| l.package_names().into_iter().map(String::from).collect())
+                .unwrap_or_default();
+            let resolved = _sync(
+                context,
+                dry_run,
+                log_enabled,
+                ResolveMode::Default,
+                output_format.clone(),
+                None,
+            )?;
+            if !output_format.is_json() {
+                let mut added: Vec<_> = resolved
+                    .iter()
+                    .filter(|p| !previous.contains(*p) && !names.contains(p))
+                    .collect();
+                if !added.is_empty() {
+                    added.sort();
+                    println!(
+                        "\n{} new transitive dependencies: {}",
+                        added.len(),
+                        added
+                            .iter()
+                            .map(|p| p.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+            }
+        }
+        Command::Remove {
+            packages,
+            dry_run,
+            no_sync,
+        } => {
+            // load config to verify structure is valid
+            let mut doc = read_and_verify_config(&cli.config_file)?;
+            remove_packages(&mut doc, &packages)?;
+            let config = doc.to_string().parse::<Config>()?;
+
+            // Prune the lockfile so the packages only needed by the removed ones are not kept
+            let lockfile_path = cli
+                .config_file
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(config.lockfile_name());
+            let mut lockfile = if config.use_lockfile() && lockfile_path.exists() {
+                Lockfile::load(&lockfile_path)?
+            } else {
+                None
+            };
+            let removed = if let Some(lockfile) = lockfile.as_mut() {
+                let top_level: Vec<_> = config.dependencies().iter().map(|d| d.name()).collect();
+                lockfile.prune(&top_level)
+            } else {
+                packages
+            };
+
+            if !dry_run {
+                write(&cli.config_file, doc.to_string())?;
+                if let Some(lockfile) = &lockfile {
+                    if config.dependencies().is_empty() {
+                        fs::remove_file(&lockfile_path)?;
+                    } else {
+                        lockfile.save(&lockfile_path)?;
+                    }
+                }
+            }
+
+            if no_sync && !dry_run {
+                if output_format.is_json() {
+                    println!("{}", json!({"removed": removed}));
+                } else {
+                    println!("Removed {}", removed.join(", "));
+                }
+                return Ok(());
+            }
+            if !output_format.is_json() {
+                println!("Removed {}\n", removed.join(", "));
+            }
+            let mut context = CliContext::new(&cli.config_file, RCommandLookup::Strict)?;
+            // if dry run, the config and lockfile on disk won't have been edited
+            if dry_run {
+                context.config = config;
+                if context.lockfile.is_some() {
+                    context.lockfile = lockfile;
+                }
+            }
             _sync(
                 context,
                 dry_run,
@@ -603,7 +1015,36 @@ fn try_main() -> Result<()> {
                 }
             }
         }
-        Command::Cache => {
+        Command::Cache {
+            subcommand: Some(CacheSubcommand::Clean { all }),
+        } => {
+            let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            let mut lockfiles = Vec::new();
+            let mut paths = context.cache.get_known_lockfiles();
+            paths.push(context.lockfile_path());
+            for path in paths {
+                match Lockfile::load(&path) {
+                    Ok(Some(lockfile)) => lockfiles.push(lockfile),
+                    Ok(None) => (),
+                    // Better to keep too much than delete packages a project needs
+                    Err(e) if !all => {
+                        anyhow::bail!("Failed to read {}: {e}", path.display())
+                    }
+                    Err(_) => (),
+                }
+            }
+            let report = clean_cache(&context.cache, &lockfiles, all)?;
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("valid json")
+                );
+            } else {
+                println!("{report}");
+            }
+        }
+        Command::Cache { subcommand: None } => {
             let mut context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
             context.load_databases()?;
             if !log_enabled {
@@ -623,64 +1064,432 @@ fn try_main() -> Result<()> {
                 println!("{info}");
             }
         }
-        Command::Migrate {
-            subcommand:
-                MigrateSubcommand::Renv {
-                    renv_file,
-                    strict_r_version,
-                    no_r_environment,
-                },
-        } => {
-            let unresolved = migrate_renv(&renv_file, &cli.config_file, strict_r_version)?;
-            // migrate renv will create the config file, so parent directory is confirmed to exist
-            let project_dir = &cli
-                .config_file
-                .canonicalize()?
-                .parent()
-                .unwrap()
-                .to_path_buf();
-            init_structure(project_dir)?;
-            activate(project_dir, no_r_environment)?;
-            let content = read_to_string(project_dir.join(".Rprofile"))?.replace(
-                "source(\"renv/activate.R\")",
-                "# source(\"renv/activate.R\")",
-            );
-            write(project_dir.join(".Rprofile"), content)?;
-
-            if unresolved.is_empty() {
+        Command::Mirror { subcommand } => match subcommand {
+            MirrorSubcommand::List => {
+                let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+                let mirrors = get_cran_mirrors(&Http {}, &context.cache)?;
                 if output_format.is_json() {
                     println!(
                         "{}",
-                        json!({
-                            "success": true,
-                            "unresolved": [],
-                        })
+                        serde_json::to_string_pretty(&mirrors).expect("valid json")
                     );
                 } else {
+                    for m in mirrors {
+                        println!("{} ({}, {}): {}", m.name, m.city, m.country, m.url);
+                    }
+                }
+            }
+            MirrorSubcommand::Ping { limit } => {
+                let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+                let mirrors = get_cran_mirrors(&Http {}, &context.cache)?;
+                let ranked = rank_mirrors(mirrors, |m| &m.url);
+                if output_format.is_json() {
+                    let out: Vec<_> = ranked
+                        .iter()
+                        .take(limit)
+                        .map(|(m, latency)| json!({"mirror": m, "latency_ms": latency.as_millis()}))
+                        .collect();
                     println!(
-                        "{} was successfully migrated to {}",
-                        renv_file.display(),
-                        cli.config_file.display()
+                        "{}",
+                        serde_json::to_string_pretty(&out).expect("valid json")
                     );
+                } else if ranked.is_empty() {
+                    println!("No mirror could be reached");
+                } else {
+                    for (m, latency) in ranked.iter().take(limit) {
+                        println!("{:>6}ms  {} ({})", latency.as_millis(), m.url, m.name);
+                    }
+                }
+            }
+            MirrorSubcommand::Set { url, repository } => {
+                set_mirror(&cli.config_file, &url, repository.as_deref())?;
+                if output_format.is_json() {
+                    println!("{}", json!({"mirror": url}));
+                } else {
+                    println!("Mirror {url} added");
                 }
-            } else if output_format.is_json() {
+            }
+        },
+        Command::Manifest {
+            subcommand: ManifestSubcommand::Resolve { alias },
+        } => {
+            let manifest = get_r_versions_manifest(&Http {}, &manifest_cache()?, false)?;
+            let Some(version) = resolve_alias(&alias, &manifest) else {
+                if !is_alias(&alias) {
+                    anyhow::bail!(
+                        "Unknown R version alias `{alias}`, expected one of: {}",
+                        R_VERSION_ALIASES.join(", ")
+                    );
+                }
+                anyhow::bail!("The R versions manifest has no version for `{alias}`");
+            };
+
+            if output_format.is_json() {
+                println!("{}", json!({"alias": alias, "version": version}));
+            } else {
+                println!("Resolving {alias} → {version}");
+            }
+        }
+        Command::Local { version, from_renv } => {
+            let project_dir = project_dir(&cli.config_file);
+            let version = if from_renv {
+                Some(read_renv_lock_r_version(project_dir.join("renv.lock"))?.to_string())
+            } else {
+                version
+            };
+            if let Some(v) = &version
+                && !is_alias(v)
+                && cli.config_file.exists()
+                && let Ok(pinned) = v.parse::<Version>()
+            {
+                check_pinned_r_version(
+                    &pinned,
+                    Config::from_file(&cli.config_file)?.r_version(),
+                    &project_dir.join(R_VERSION_FILENAME),
+                )?;
+            }
+            let file = match version {
+                Some(v) => Some(RVersionFile::write(&project_dir, &v)?),
+                None => RVersionFile::find(&project_dir)?,
+            };
+            if output_format.is_json() {
                 println!(
                     "{}",
                     json!({
-                        "success": false,
-                        "unresolved": unresolved.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                        "version": file.as_ref().map(|f| &f.value),
+                        "path": file.as_ref().map(|f| &f.path),
                     })
                 );
+            } else if let Some(file) = file {
+                println!("R {} ({})", file.value, file.path.display());
+            } else {
+                println!("No .R-version file found, the R version of the config is used");
+            }
+        }
+        Command::R { subcommand } => {
+            let installations = find_r_installations();
+            let installed = if installations.is_empty() {
+                "none".to_string()
+            } else {
+                installations
+                    .iter()
+                    .map(|i| i.version.original.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            // Same lookup as when syncing: the first installation matching what the project wants
+            let active = if cli.config_file.exists() {
+                let r_version = project_r_version(
+                    &Config::from_file(&cli.config_file)?,
+                    &project_dir(&cli.config_file),
+                )?;
+                matching_installations(&installations, &r_version)
+                    .first()
+                    .copied()
             } else {
+                installations.iter().find(|i| i.r.is_none())
+            };
+            let parse_version = |version: &str| {
+                version
+                    .parse::<Version>()
+                    .map_err(|_| anyhow::anyhow!("`{version}` is not an R version like `4.4.1`"))
+            };
+
+            match subcommand {
+                RSubcommand::List => {
+                    if output_format.is_json() {
+                        let out: Vec<_> = installations
+                            .iter()
+                            .map(|i| {
+                                json!({
+                                    "version": i.version,
+                                    "path": i.r,
+                                    "installed_at": i.installed_at,
+                                    "size": i.size,
+                                    "active": Some(i) == active,
+                                })
+                            })
+                            .collect();
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&out).expect("valid json")
+                        );
+                    } else if installations.is_empty() {
+                        println!("No R installation found");
+                    } else {
+                        for i in &installations {
+                            let path =
+                                i.r.as_ref()
+                                    .map(|r| r.display().to_string())
+                                    .unwrap_or_else(|| "R on the PATH".to_string());
+                            let installed_at = i.installed_at.map(|t| {
+                                jiff::Timestamp::from_second(t)
+                                    .map(|t| t.strftime("%Y-%m-%d").to_string())
+                                    .unwrap_or_else(|_| t.to_string())
+                            });
+                            let details = [installed_at, i.size.map(format_size)]
+                                .into_iter()
+                                .flatten()
+                                .collect::<Vec<_>>();
+                            println!(
+                                "{} {} ({path}){}",
+                                if Some(i) == active { "*" } else { " " },
+                                i.version,
+                                if details.is_empty() {
+                                    String::new()
+                                } else {
+                                    format!(", {}", details.join(", "))
+                                }
+                            );
+                        }
+                    }
+                }
+                RSubcommand::Use { version } => {
+                    let matching =
+                        matching_installations(&installations, &parse_version(&version)?);
+                    let Some(installation) = matching.first() else {
+                        return Err(RInstallationError::NotInstalled { version, installed }.into());
+                    };
+                    let project_dir = project_dir(&cli.config_file);
+                    if cli.config_file.exists() {
+                        check_pinned_r_version(
+                            &installation.version,
+                            Config::from_file(&cli.config_file)?.r_version(),
+                            &project_dir.join(R_VERSION_FILENAME),
+                        )?;
+                    }
+                    let file = RVersionFile::write(&project_dir, &version)?;
+                    if output_format.is_json() {
+                        println!(
+                            "{}",
+                            json!({
+                                "version": installation.version,
+                                "r": installation.r,
+                                "path": file.path,
+                            })
+                        );
+                    } else {
+                        println!(
+                            "Using R {} in {} ({})",
+                            installation.version,
+                            file.path.parent().unwrap_or(&file.path).display(),
+                            file.path.display()
+                        );
+                    }
+                }
+                RSubcommand::Verify {
+                    version,
+                    all,
+                    write_manifest: write,
+                } => {
+                    let selected: Vec<_> = if all {
+                        installations.iter().collect()
+                    } else if let Some(version) = version {
+                        let matching =
+                            matching_installations(&installations, &parse_version(&version)?);
+                        if matching.is_empty() {
+                            return Err(
+                                RInstallationError::NotInstalled { version, installed }.into()
+                            );
+                        }
+                        matching
+                    } else {
+                        let Some(active) = active else {
+                            anyhow::bail!(
+                                "No R installation found for the project, installed versions: {installed}"
+                            );
+                        };
+                        vec![active]
+                    };
+
+                    if write {
+                        let mut out = Vec::new();
+                        for installation in selected.iter().filter(|i| i.root.is_some()) {
+                            let files = write_manifest(installation)?;
+                            if !output_format.is_json() {
+                                println!("Recorded {files} files for R {}", installation.version);
+                            }
+                            out.push(json!({"version": installation.version, "files": files}));
+                        }
+                        if output_format.is_json() {
+                            println!(
+                                "{}",
+                                serde_json::to_string_pretty(&out).expect("valid json")
+                            );
+                        } else if out.is_empty() {
+                            println!(
+                                "Manifests can only be written for the installations in /opt/R"
+                            );
+                        }
+                        return Ok(());
+                    }
+
+                    let checks: Vec<_> =
+                        selected.iter().map(|i| verify_r_installation(i)).collect();
+                    let failed = checks.iter().filter(|c| !c.is_ok()).count();
+                    if output_format.is_json() {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&checks).expect("valid json")
+                        );
+                    } else {
+                        for check in &checks {
+                            println!("{check}");
+                        }
+                        if checks.len() > 1 {
+                            println!(
+                                "\n{} of {} installations OK",
+                                checks.len() - failed,
+                                checks.len()
+                            );
+                        }
+                    }
+                    if failed > 0 {
+                        ::std::process::exit(1)
+                    }
+                }
+                RSubcommand::Remove { version, yes } => {
+                    let matching =
+                        matching_installations(&installations, &parse_version(&version)?);
+                    let installation = match matching.as_slice() {
+                        [] => {
+                            return Err(
+                                RInstallationError::NotInstalled { version, installed }.into()
+                            );
+                        }
+                        [installation] => *installation,
+                        _ => {
+                            let found = matching
+                                .iter()
+                                .map(|i| i.version.original.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            return Err(RInstallationError::Ambiguous { version, found }.into());
+                        }
+                    };
+                    if Some(installation) == active && !yes {
+                        if output_format.is_json()
+                            || !std::io::IsTerminal::is_terminal(&std::io::stdin())
+                        {
+                            anyhow::bail!(
+                                "R {} is the one the project uses, pass --yes to remove it",
+                                installation.version
+                            );
+                        }
+                        eprint!(
+                            "R {} is the one the project uses, remove it anyway? [y/N] ",
+                            installation.version
+                        );
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                            anyhow::bail!("Aborted");
+                        }
+                    }
+                    remove_r_installation(installation)?;
+                    if output_format.is_json() {
+                        println!("{}", json!({"removed": installation.version}));
+                    } else {
+                        println!("Removed R {}", installation.version);
+                    }
+                }
+            }
+        }
+        Command::Manifest {
+            subcommand: ManifestSubcommand::Refresh,
+        } => {
+            let manifest = get_r_versions_manifest(&Http {}, &manifest_cache()?, true)?;
+            let (release, oldrel, devel) =
+                (manifest.release(), manifest.oldrel(), manifest.devel());
+            if output_format.is_json() {
                 println!(
-                    "{} was migrated to {} with {} unresolved packages: ",
-                    renv_file.display(),
-                    cli.config_file.display(),
-                    unresolved.len()
+                    "{}",
+                    json!({"release": release, "oldrel": oldrel, "devel": devel})
                 );
-                for u in &unresolved {
-                    eprintln!("    {u}");
-                }
+            } else {
+                let show =
+                    |v: Option<RVersion>| v.map(|v| v.to_string()).unwrap_or("-".to_string());
+                println!(
+                    "R versions manifest refreshed: release {}, oldrel {}, devel {}",
+                    show(release),
+                    show(oldrel),
+                    show(devel)
+                );
+            }
+        }
+        Command::Migrate {
+            subcommand:
+                MigrateSubcommand::Renv {
+                    renv_file,
+                    strict_r_version,
+                    no_r_environment,
+                },
+        } => {
+            let unresolved = migrate_renv(&renv_file, &cli.config_file, strict_r_version)?;
+            finish_renv_conversion(
+                &cli.config_file,
+                &renv_file,
+                no_r_environment,
+                &unresolved,
+                "migrated",
+                &output_format,
+            )?;
+        }
+        Command::Import {
+            subcommand:
+                ImportSubcommand::Renv {
+                    renv_file,
+                    strict_r_version,
+                    no_r_environment,
+                },
+        } => {
+            let unresolved = import_renv(&renv_file, &cli.config_file, strict_r_version)?;
+            finish_renv_conversion(
+                &cli.config_file,
+                &renv_file,
+                no_r_environment,
+                &unresolved,
+                "imported",
+                &output_format,
+            )?;
+        }
+        Command::Import {
+            subcommand:
+                ImportSubcommand::Packrat {
+                    packrat_file,
+                    strict_r_version,
+                    no_r_environment,
+                },
+        } => {
+            let unresolved = import_packrat(&packrat_file, &cli.config_file, strict_r_version)?;
+            finish_renv_conversion(
+                &cli.config_file,
+                &packrat_file,
+                no_r_environment,
+                &unresolved,
+                "imported",
+                &output_format,
+            )?;
+        }
+        Command::Export {
+            subcommand: ExportSubcommand::Renv { path },
+        } => {
+            let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            let Some(lockfile) = &context.lockfile else {
+                anyhow::bail!(
+                    "No lockfile matching the R version {} found, run `rv sync` first",
+                    context.r_version
+                );
+            };
+            // renv records the full R version, the config usually only has the minor one
+            let r_version = find_r_version_command(&context.r_version)
+                .and_then(|r| r.version())
+                .unwrap_or_else(|_| context.r_version.clone());
+            export_renv(lockfile, context.config.repositories(), &r_version, &path)?;
+            if output_format.is_json() {
+                println!("{}", json!({"path": path, "r_version": r_version}));
+            } else {
+                println!("Exported the lockfile to {}", path.display());
             }
         }
         Command::Summary { r_version } => {
@@ -706,7 +1515,7 @@ fn try_main() -> Result<()> {
             .map(|(name, status)| SysDep { name, status })
             .collect();
 
-            let summary = ProjectSummary::new(
+            let mut summary = ProjectSummary::new(
                 &context.library,
                 &resolved,
                 context.config.repositories(),
@@ -716,6 +1525,13 @@ fn try_main() -> Result<()> {
                 context.lockfile.as_ref(),
                 sys_deps,
             );
+            let renv_lock = context.project_dir.join("renv.lock");
+            if renv_lock.is_file() {
+                match read_renv_lock_r_version(&renv_lock) {
+                    Ok(v) => summary = summary.with_renv_r_version(&v),
+                    Err(e) => log::warn!("{e}"),
+                }
+            }
             if output_format.is_json() {
                 println!(
                     "{}",
@@ -794,6 +1610,8 @@ fn try_main() -> Result<()> {
         }
 
         Command::Tree {
+            package,
+            invert,
             depth,
             hide_system_deps,
             r_version,
@@ -807,7 +1625,19 @@ fn try_main() -> Result<()> {
                 context.show_progress_bar();
             }
             let resolution = resolve_dependencies(&context, &ResolveMode::Default, false);
-            let tree = tree(&context, &resolution.found, &resolution.failed);
+            if let Some(name) = &package
+                && !resolution.found.iter().any(|d| &d.name == name)
+                && !resolution.failed.iter().any(|d| &d.name == name)
+            {
+                anyhow::bail!("Package `{name}` is not a dependency of the project");
+            }
+            let tree = tree(
+                &context,
+                &resolution.found,
+                &resolution.failed,
+                package.as_deref(),
+                invert,
+            );
 
             if output_format.is_json() {
                 println!(
@@ -818,6 +1648,135 @@ fn try_main() -> Result<()> {
                 tree.print(depth, !hide_system_deps);
             }
         }
+
+        Command::Outdated => {
+            let mut context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            if context.lockfile.is_none() {
+                anyhow::bail!("No lockfile found, run `rv sync` first");
+            }
+            if !log_enabled {
+                context.show_progress_bar();
+            }
+            context.load_databases()?;
+            let lockfile = context.lockfile.as_ref().unwrap();
+            let packages = outdated(
+                lockfile,
+                context.config.dependencies(),
+                &context.databases,
+                &context.r_version,
+            );
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&packages).expect("valid json")
+                );
+            } else if packages.is_empty() {
+                println!("All packages are up to date");
+            } else {
+                println!("{OUTDATED_HEADER}");
+                for package in packages {
+                    println!("{package}");
+                }
+            }
+        }
+
+        Command::Verify => {
+            let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            let Some(lockfile) = &context.lockfile else {
+                anyhow::bail!("No lockfile found, run `rv sync` first");
+            };
+            // Custom libraries are not scanned since rv doesn't manage them, but checking them
+            // is the whole point here
+            let mut library = context.library.clone();
+            if library.custom {
+                library.custom = false;
+                library.find_content();
+            }
+            let res = verify(lockfile, &library);
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&res).expect("valid json")
+                );
+            } else {
+                println!("{res}");
+            }
+            if !res.is_ok() {
+                ::std::process::exit(1)
+            }
+        }
+
+        Command::Metadata { package } => {
+            let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            let folder = context.library.path().join(&package);
+            if !folder.is_dir() {
+                anyhow::bail!("Package `{package}` is not installed");
+            }
+            let Some(metadata) = InstallMetadata::load(&folder)? else {
+                anyhow::bail!(
+                    "No metadata found for `{package}`, only packages installed from a repository \
+                     have one. Reinstalling it with `rv sync` will write it"
+                );
+            };
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&metadata).expect("valid json")
+                );
+            } else {
+                let installed_at = jiff::Timestamp::from_second(metadata.installed_at)
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|_| metadata.installed_at.to_string());
+                let kind = match metadata.kind {
+                    PackageType::Binary => "binary",
+                    PackageType::Source => "built from source",
+                };
+                println!("{} {}", metadata.name, metadata.version);
+                println!("Installed at: {installed_at}");
+                println!("Kind: {kind}");
+                println!("Platform: {}", metadata.platform);
+                if let Some(url) = &metadata.source_url {
+                    println!("Source URL: {url}");
+                }
+                if let Some(sha) = &metadata.archive_sha256 {
+                    println!("Archive SHA256: {sha}");
+                }
+                println!("Cache path: {}", metadata.cache_path.display());
+            }
+        }
+
+        Command::Why { package } => {
+            let context = CliContext::new(&cli.config_file, RCommandLookup::Skip)?;
+            let Some(lockfile) = &context.lockfile else {
+                anyhow::bail!("No lockfile found, run `rv sync` first");
+            };
+            if lockfile.get_package(&package, None).is_none() {
+                anyhow::bail!("Package `{package}` is not installed");
+            }
+            let top_level: Vec<_> = context
+                .config
+                .dependencies()
+                .iter()
+                .map(|d| d.name())
+                .collect();
+            let paths = why(lockfile, &top_level, &package);
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&paths).expect("valid json")
+                );
+            } else if paths.is_empty() {
+                println!("{package} is not required by any project dependency");
+            } else {
+                for path in paths {
+                    println!("{path}");
+                }
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,359 @@
+//! Mirrors of a repository, tried in order when downloading packages from it.
+//! CRAN publishes its list of mirrors which we can parse to let users pick one.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use url::Url;
+
+use crate::http::{HttpError, check_online, get_agent_with_timeout, is_offline};
+use crate::{DiskCache, HttpDownload};
+
+pub const CRAN_MIRRORS_URL: &str = "https://cran.r-project.org/CRAN_mirrors.csv";
+
+/// How long we wait for a mirror to answer a ping before giving up on it
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A mirror from the CRAN list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CranMirror {
+    pub name: String,
+    pub country: String,
+    pub city: String,
+    pub url: Url,
+}
+
+/// Splits a CSV line, handling quoted fields with commas and `""` escapes
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+/// Parses the content of <https://cran.r-project.org/CRAN_mirrors.csv>.
+/// Mirrors not marked as OK or with an invalid URL are skipped.
+pub fn parse_cran_mirrors(content: &str) -> Vec<CranMirror> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let header = parse_csv_line(header);
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(name), Some(country), Some(city), Some(url)) = (
+        column("Name"),
+        column("Country"),
+        column("City"),
+        column("URL"),
+    ) else {
+        return Vec::new();
+    };
+    let ok = column("OK");
+
+    lines
+        .map(parse_csv_line)
+        .filter(|fields| ok.is_none_or(|i| fields.get(i).is_some_and(|f| f == "1")))
+        .filter_map(|fields| {
+            Some(CranMirror {
+                name: fields.get(name)?.clone(),
+                country: fields.get(country)?.clone(),
+                city: fields.get(city)?.clone(),
+                url: Url::parse(fields.get(url)?).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Downloads the CRAN mirror list, reusing the one in the cache if it's recent enough
+pub fn get_cran_mirrors(
+    http: &impl HttpDownload,
+    cache: &DiskCache,
+) -> Result<Vec<CranMirror>, HttpError> {
+    let (path, is_fresh) = cache.get_cran_mirrors_entry();
+    // When offline, an outdated list is better than nothing
+    if (is_fresh || is_offline())
+        && let Ok(content) = fs_err::read_to_string(&path)
+    {
+        return Ok(parse_cran_mirrors(&content));
+    }
+
+    // The list rarely changes so the server will most likely tell us to keep the one we have
+    let url = Url::parse(CRAN_MIRRORS_URL).unwrap();
+    http.download_if_modified(&url, &path)?;
+    let content = fs_err::read_to_string(&path).map_err(|e| HttpError::from_io(url.as_str(), e))?;
+    Ok(parse_cran_mirrors(&content))
+}
+
+/// How long it takes for the mirror to answer a HEAD request
+pub fn ping(url: &Url) -> Result<Duration, HttpError> {
+    check_online(url)?;
+    let agent = get_agent_with_timeout(url, PING_TIMEOUT);
+    let start = Instant::now();
+    agent
+        .head(url.as_str())
+        .call()
+        .map_err(|e| HttpError::from_ureq(url.as_str(), e))?;
+    Ok(start.elapsed())
+}
+
+/// Pings all the mirrors in parallel and returns the ones that answered, fastest first
+pub fn rank_mirrors<T: Send>(
+    mirrors: Vec<T>,
+    url: impl Fn(&T) -> &Url + Sync,
+) -> Vec<(T, Duration)> {
+    let mut ranked: Vec<_> = mirrors
+        .into_par_iter()
+        .filter_map(|m| match ping(url(&m)) {
+            Ok(latency) => Some((m, latency)),
+            Err(e) => {
+                log::debug!("Mirror {} did not answer: {e}", url(&m));
+                None
+            }
+        })
+        .collect();
+    ranked.sort_by_key(|(_, latency)| *latency);
+    ranked
+}
+
+/// Replaces the `repository` prefix of the URL by the mirror one.
+/// Returns `None` if the URL is not from that repository.
+fn rebase(url: &Url, repository: &str, mirror: &Url) -> Option<Url> {
+    let rest = url
+        .as_str()
+        .strip_prefix(repository.trim_end_matches('/'))?;
+    Url::parse(&format!("{}{rest}", mirror.as_str().trim_end_matches('/'))).ok()
+}
+
+/// The base URLs to try for a repository, in order: the last one that worked if we know it,
+/// then the repository itself and its mirrors in the order of the config.
+fn ordered_bases(repository: &str, mirrors: &[Url], last_working: Option<Url>) -> Vec<Url> {
+    let mut bases: Vec<_> = Url::parse(repository)
+        .into_iter()
+        .chain(mirrors.iter().cloned())
+        .collect();
+    if let Some(last) = last_working
+        && let Some(idx) = bases.iter().position(|b| same_base(b, &last))
+    {
+        let last = bases.remove(idx);
+        bases.insert(0, last);
+    }
+    bases
+}
+
+fn same_base(a: &Url, b: &Url) -> bool {
+    a.as_str().trim_end_matches('/') == b.as_str().trim_end_matches('/')
+}
+
+/// Downloads and extracts a tarball from a repository, moving on to the next mirror if one can't
+/// be reached. Other errors, like a 404, are returned immediately since they would most likely
+/// happen on all mirrors.
+/// The mirror that worked is remembered in the cache so it's tried first next time.
+pub(crate) fn download_and_untar_from_mirrors(
+    http: &impl HttpDownload,
+    cache: &DiskCache,
+    url: &Url,
+    repository: &str,
+    mirrors: &[Url],
+    destination: impl AsRef<Path>,
+    use_sha_in_path: bool,
+) -> Result<(Option<PathBuf>, String), HttpError> {
+    if mirrors.is_empty() {
+        return http.download_and_untar(url, destination, use_sha_in_path);
+    }
+
+    let bases = ordered_bases(
+        repository,
+        mirrors,
+        cache.get_last_working_mirror(repository),
+    );
+    let urls: Vec<_> = bases
+        .iter()
+        .filter_map(|b| rebase(url, repository, b).map(|u| (b, u)))
+        .collect();
+    let Some(((_, last_url), others)) = urls.split_last() else {
+        return http.download_and_untar(url, destination, use_sha_in_path);
+    };
+
+    for (base, url) in others {
+        match http.download_and_untar(url, destination.as_ref(), use_sha_in_path) {
+            Ok(res) => {
+                cache.set_last_working_mirror(repository, base);
+                return Ok(res);
+            }
+            Err(e) if e.is_connection_error() => {
+                log::warn!("Could not reach {base}: {e}, trying the next mirror");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let res = http.download_and_untar(last_url, destination, use_sha_in_path)?;
+    cache.set_last_working_mirror(repository, urls.last().unwrap().0);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_cran_mirrors() {
+        let content = std::fs::read_to_string("src/tests/cran_mirrors.csv").unwrap();
+        let mirrors = parse_cran_mirrors(&content);
+        assert_eq!(
+            mirrors.iter().map(|m| m.url.as_str()).collect::<Vec<_>>(),
+            vec![
+                "https://cloud.r-project.org/",
+                "https://cran.csiro.au/",
+                "https://mirror.cedia.org.ec/CRAN/",
+            ]
+        );
+        assert_eq!(mirrors[1].name, "Australia [Canberra]");
+        assert_eq!(mirrors[1].city, "Canberra");
+        assert_eq!(mirrors[2].country, "Ecuador");
+    }
+
+    #[test]
+    fn can_rebase_url_on_mirror() {
+        let url = Url::parse("https://cran.r-project.org/src/contrib/R6_2.5.1.tar.gz").unwrap();
+        let mirror = Url::parse("https://cran.csiro.au/").unwrap();
+        assert_eq!(
+            rebase(&url, "https://cran.r-project.org", &mirror)
+                .unwrap()
+                .as_str(),
+            "https://cran.csiro.au/src/contrib/R6_2.5.1.tar.gz"
+        );
+        assert!(rebase(&url, "https://packagemanager.posit.co/cran/latest", &mirror).is_none());
+    }
+
+    /// Only the given host can be reached
+    struct OnlyHost(&'static str);
+
+    impl HttpDownload for OnlyHost {
+        fn download<W: std::io::Write>(
+            &self,
+            _: &Url,
+            _: &mut W,
+            _: Vec<(&str, String)>,
+        ) -> Result<u64, HttpError> {
+            unreachable!()
+        }
+
+        fn download_and_untar(
+            &self,
+            url: &Url,
+            _: impl AsRef<Path>,
+            _: bool,
+        ) -> Result<(Option<PathBuf>, String), HttpError> {
+            if url.host_str() == Some(self.0) {
+                Ok((None, url.to_string()))
+            } else {
+                Err(HttpError {
+                    url: url.to_string(),
+                    source: crate::http::HttpErrorKind::Ureq(Box::new(
+                        ureq::Error::ConnectionFailed,
+                    )),
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn falls_over_to_next_mirror_and_remembers_it() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4.1".parse().unwrap(),
+            crate::SystemInfo::from_os_info(),
+            tempdir.path(),
+        )
+        .unwrap();
+        let repository = "https://cran.r-project.org";
+        let url = Url::parse("https://cran.r-project.org/src/contrib/R6_2.5.1.tar.gz").unwrap();
+        let mirrors = vec![
+            Url::parse("https://cran.csiro.au/").unwrap(),
+            Url::parse("https://mirror.cedia.org.ec/CRAN/").unwrap(),
+        ];
+
+        let (_, downloaded) = download_and_untar_from_mirrors(
+            &OnlyHost("mirror.cedia.org.ec"),
+            &cache,
+            &url,
+            repository,
+            &mirrors,
+            tempdir.path(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            downloaded,
+            "https://mirror.cedia.org.ec/CRAN/src/contrib/R6_2.5.1.tar.gz"
+        );
+        assert_eq!(
+            cache.get_last_working_mirror(repository),
+            Some(mirrors[1].clone())
+        );
+
+        // Nothing can be reached
+        let err = download_and_untar_from_mirrors(
+            &OnlyHost("example.com"),
+            &cache,
+            &url,
+            repository,
+            &mirrors,
+            tempdir.path(),
+            false,
+        )
+        .unwrap_err();
+        assert!(err.is_connection_error());
+    }
+
+    #[test]
+    fn tries_last_working_mirror_first() {
+        let mirrors = vec![
+            Url::parse("https://cran.csiro.au/").unwrap(),
+            Url::parse("https://mirror.cedia.org.ec/CRAN/").unwrap(),
+        ];
+        let bases = |last: Option<&str>| {
+            ordered_bases(
+                "https://cran.r-project.org",
+                &mirrors,
+                last.map(|l| Url::parse(l).unwrap()),
+            )
+            .into_iter()
+            .map(|u| u.to_string())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            bases(None),
+            vec![
+                "https://cran.r-project.org/",
+                "https://cran.csiro.au/",
+                "https://mirror.cedia.org.ec/CRAN/"
+            ]
+        );
+        assert_eq!(
+            bases(Some("https://mirror.cedia.org.ec/CRAN")),
+            vec![
+                "https://mirror.cedia.org.ec/CRAN/",
+                "https://cran.r-project.org/",
+                "https://cran.csiro.au/"
+            ]
+        );
+    }
+}
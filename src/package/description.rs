@@ -57,6 +57,7 @@ pub fn parse_version(file_path: impl AsRef<Path>) -> Result<Version, Box<dyn std
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::package::Dependency;
     use crate::package::remotes::PackageRemote;
 
     #[test]
@@ -78,6 +79,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_parse_description_dependencies() {
+        let content = r#"Package: mypkg
+Version: 0.1.0
+Depends: R (>=4.1.0),
+    methods
+Imports: dplyr (>= 1.0.0), rlang (>
+    1.1.0), vctrs (==
+    0.6.5), cli
+LinkingTo: cpp11 (<= 0.5.0)
+Suggests: testthat (< 4.0.0)
+"#;
+        let package = parse_description_file(content).unwrap();
+        // R is not a real dependency
+        assert_eq!(package.r_requirement.unwrap().to_string(), "(>= 4.1.0)");
+        assert_eq!(
+            package.depends,
+            vec![Dependency::Simple("methods".to_string())]
+        );
+        let requirements: Vec<_> = package
+            .imports
+            .iter()
+            .map(|d| (d.name(), d.version_requirement().map(|r| r.to_string())))
+            .collect();
+        assert_eq!(
+            requirements,
+            vec![
+                ("dplyr", Some("(>= 1.0.0)".to_string())),
+                ("rlang", Some("(> 1.1.0)".to_string())),
+                ("vctrs", Some("(== 0.6.5)".to_string())),
+                ("cli", None),
+            ]
+        );
+        assert_eq!(
+            package.linking_to[0]
+                .version_requirement()
+                .unwrap()
+                .to_string(),
+            "(<= 0.5.0)"
+        );
+        assert_eq!(
+            package.suggests[0]
+                .version_requirement()
+                .unwrap()
+                .to_string(),
+            "(< 4.0.0)"
+        );
+    }
+
     #[test]
     fn can_read_version() {
         let version = parse_version("src/tests/descriptions/gsm.app.DESCRIPTION").unwrap();
@@ -19,15 +19,39 @@ pub use parser::parse_package_file;
 pub use remotes::PackageRemote;
 pub use version::{Operator, Version, VersionRequirement, deserialize_version};
 
+pub(crate) use parser::parse_dcf_records;
 pub(crate) use remotes::parse_remote;
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, Encode, Decode, Serialize)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, Encode, Decode, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PackageType {
     Source,
     Binary,
 }
 
+/// Which kind of build to pick when a repository has both a binary and a source package
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum BuildPreference {
+    /// Only use a binary matching the current platform and R version
+    Binary,
+    /// Always build from source
+    Source,
+    /// Use a binary if there is a compatible one, fall back to source otherwise
+    #[default]
+    PreferBinary,
+}
+
+impl From<bool> for BuildPreference {
+    /// Maps the `force_source` flag used in configs and lockfiles
+    fn from(force_source: bool) -> Self {
+        if force_source {
+            Self::Source
+        } else {
+            Self::PreferBinary
+        }
+    }
+}
+
 impl fmt::Display for PackageType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -11,6 +11,8 @@ use std::sync::LazyLock;
 static PACKAGE_KEY_VAL_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?m)^(?P<key>\w+):(?P<value>.*(?:\n\s+.*)*)").unwrap());
 static ANY_SPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+// Some repositories leave spaces on the empty line between packages
+static RECORD_SEPARATOR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n[ \t]*\n").unwrap());
 
 fn parse_dependencies(content: &str) -> Vec<Dependency> {
     let mut res = Vec::new();
@@ -40,6 +42,31 @@ fn parse_dependencies(content: &str) -> Vec<Dependency> {
     res
 }
 
+/// The fields of a record, with the multi-lines values joined by a single space
+fn record_fields(record: &str) -> impl Iterator<Item = (&str, String)> {
+    PACKAGE_KEY_VAL_RE.captures_iter(record).map(|captures| {
+        let key = captures.name("key").unwrap().as_str();
+        let value = captures.name("value").unwrap().as_str();
+        let value = ANY_SPACE_RE.replace_all(value, " ");
+        (key, value.trim().to_string())
+    })
+}
+
+/// Splits a file in the Debian control format used by PACKAGES files into its records, eg
+/// for the packrat.lock files.
+/// Each record is a list of fields in the order they appear in.
+pub(crate) fn parse_dcf_records(content: &str) -> Vec<Vec<(String, String)>> {
+    RECORD_SEPARATOR_RE
+        .split(&content.replace("\r\n", "\n"))
+        .map(|record| {
+            record_fields(record)
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<Vec<_>>()
+        })
+        .filter(|fields| !fields.is_empty())
+        .collect()
+}
+
 /// Parse a PACKAGE file into something usable to resolve dependencies.
 /// A package may be present multiple times in the file. If that's the case
 /// we do the following:
@@ -53,12 +80,8 @@ pub fn parse_package_file(content: &str) -> HashMap<String, Vec<Package>> {
     let parse_pkg = |content: &str| -> Package {
         let mut package = Package::default();
 
-        for captures in PACKAGE_KEY_VAL_RE.captures_iter(content) {
-            let key = captures.name("key").unwrap().as_str();
-            let value = captures.name("value").unwrap().as_str();
-            let value = ANY_SPACE_RE.replace_all(value, " ");
-            let value = value.trim();
-
+        for (key, value) in record_fields(content) {
+            let value = value.as_str();
             match key {
                 "Package" => package.name = value.to_string(),
                 "Version" => {
@@ -105,7 +128,7 @@ pub fn parse_package_file(content: &str) -> HashMap<String, Vec<Package>> {
     };
 
     // packages are split by an empty line
-    for pkg_data in content.replace("\r\n", "\n").split("\n\n") {
+    for pkg_data in RECORD_SEPARATOR_RE.split(&content.replace("\r\n", "\n")) {
         let pkg = parse_pkg(pkg_data);
         if !pkg.name.is_empty() {
             if let Some(p) = packages.get_mut(&pkg.name) {
@@ -214,6 +237,62 @@ NeedsCompilation: no
         assert_eq!(packages.len(), 1);
     }
 
+    #[test]
+    fn can_parse_multiple_records_with_wrapped_fields() {
+        // The line between the 2 first packages only has spaces
+        let content = "Package: A3
+Version: 1.0.0
+Depends: R (>= 2.15.0), xtable,
+        pbapply
+Suggests: randomForest, e1071
+License: GPL (>= 2)
+MD5sum: 027ebdd8affce8f0effaecfcd5f5ade2
+NeedsCompilation: no
+   
+Package: abc
+Version: 2.2.1
+Depends: R (>= 2.10), abc.data, nnet, quantreg, MASS,
+        locfit
+Imports: grDevices, graphics, stats
+LinkingTo: Rcpp
+License: GPL (>= 3)
+MD5sum: c9fffe4334c178917f762735aba59653
+NeedsCompilation: yes
+
+Package: abc.data
+Version: 1.1
+Depends: R (>= 2.10)
+License: GPL (>= 3)
+MD5sum: 799079dbbdd0cfc9d9c61c3e35241806
+NeedsCompilation: no
+";
+        let packages = parse_package_file(content);
+        assert_eq!(packages.len(), 3);
+
+        let a3 = &packages["A3"][0];
+        assert_eq!(a3.version.to_string(), "1.0.0");
+        assert_eq!(
+            a3.depends,
+            vec![
+                Dependency::Simple("xtable".to_string()),
+                Dependency::Simple("pbapply".to_string()),
+            ]
+        );
+        assert_eq!(a3.md5_sum, "027ebdd8affce8f0effaecfcd5f5ade2");
+
+        let abc = &packages["abc"][0];
+        assert_eq!(abc.r_requirement.clone().unwrap().to_string(), "(>= 2.10)");
+        assert_eq!(abc.depends.len(), 5);
+        assert_eq!(abc.depends[4], Dependency::Simple("locfit".to_string()));
+        assert_eq!(abc.imports.len(), 3);
+        assert_eq!(abc.linking_to, vec![Dependency::Simple("Rcpp".to_string())]);
+        assert!(abc.needs_compilation);
+        assert_eq!(
+            packages["abc.data"][0].md5_sum,
+            "799079dbbdd0cfc9d9c61c3e35241806"
+        );
+    }
+
     #[test]
     fn works_on_gsm() {
         let mut content =
@@ -235,4 +314,25 @@ NeedsCompilation: no
             vec![Dependency::Simple("cpp11".to_string())]
         );
     }
+
+    #[test]
+    fn can_parse_dcf_records() {
+        let content = "RVersion: 4.3.2\nRepos: CRAN=https://cloud.r-project.org,\n    RSPM=https://packagemanager.posit.co/cran/latest\n\nPackage: R6\nSource: CRAN\n  \n\n";
+        assert_eq!(
+            parse_dcf_records(content),
+            vec![
+                vec![
+                    ("RVersion".to_string(), "4.3.2".to_string()),
+                    (
+                        "Repos".to_string(),
+                        "CRAN=https://cloud.r-project.org, RSPM=https://packagemanager.posit.co/cran/latest".to_string()
+                    ),
+                ],
+                vec![
+                    ("Package".to_string(), "R6".to_string()),
+                    ("Source".to_string(), "CRAN".to_string()),
+                ],
+            ]
+        );
+    }
 }
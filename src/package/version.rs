@@ -38,11 +38,14 @@ impl FromStr for Operator {
             "<" => Ok(Self::Lower),
             ">=" => Ok(Self::GreaterOrEqual),
             "<=" => Ok(Self::LowerOrEqual),
-            _ => todo!("Handle error: {s}"),
+            _ => Err(()),
         }
     }
 }
 
+/// An R version, eg `4.3.1` or `1.0-12`.
+/// Both `.` and `-` separate numeric components, which are compared one by one.
+/// Missing components count as 0 so `1.0` is equal to `1.0.0`.
 #[derive(Debug, Default, Clone, Encode, Decode, Deserialize)]
 pub struct Version {
     // TODO: pack versions in a u64 for faster comparison if needed
@@ -171,38 +174,23 @@ impl VersionRequirement {
 impl FromStr for VersionRequirement {
     type Err = String;
 
-    // s is for format `(>= 4.5)`
+    // s is for format `(>= 4.5)`, some packages omit the space after the operator
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut current = String::new();
-        let mut version = None;
-        let mut op = None;
-
-        for c in s.trim().chars() {
-            if c == '(' {
-                continue;
-            }
-            if c == ' ' {
-                // we should have the op in current
-                // however formatting across lines can sometimes cause multiple whitespaces
-                // after the op like "(>=   1.2.0)"
-                // so if we hit more whitespace after setting the op we can just continue
-                if op.is_none() {
-                    op = Some(Operator::from_str(&current).expect("TODO"));
-                    current = String::new();
-                }
-                continue;
-            }
-            if c == ')' {
-                version = Some(Version::from_str(&current).expect("TODO"));
-                continue;
-            }
-            current.push(c);
-        }
-
-        Ok(Self {
-            version: version.unwrap(),
-            op: op.unwrap(),
-        })
+        let inner = s
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .trim();
+        let op_end = inner
+            .find(|c| !matches!(c, '<' | '>' | '='))
+            .unwrap_or(inner.len());
+        let op = Operator::from_str(&inner[..op_end])
+            .map_err(|_| format!("{s} does not have a valid operator"))?;
+        // formatting across lines can sometimes cause multiple whitespaces after the op
+        // like "(>=   1.2.0)"
+        let version = Version::from_str(inner[op_end..].trim())?;
+
+        Ok(Self { version, op })
     }
 }
 
@@ -275,12 +263,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_parse_version_requirements_without_space() {
+        for (input, expected) in [
+            ("(>=1.0.0)", "(>= 1.0.0)"),
+            ("(<1.0-10)", "(< 1.0-10)"),
+            ("( ==  2.1 )", "(== 2.1)"),
+        ] {
+            assert_eq!(
+                VersionRequirement::from_str(input).unwrap().to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn errors_on_invalid_version_requirements() {
+        for input in ["(~= 1.0.0)", "(1.0.0)", "(>= abc)"] {
+            assert!(VersionRequirement::from_str(input).is_err(), "{input}");
+        }
+    }
+
     #[test]
     fn can_compare_versions() {
         assert!(Version::from_str("1.0").unwrap() == Version::from_str("1.0.0").unwrap());
         assert!(Version::from_str("1.1").unwrap() > Version::from_str("1.0.0").unwrap());
     }
 
+    #[test]
+    fn compares_versions_by_component() {
+        let v = |s| Version::from_str(s).unwrap();
+        // Not a string comparison, 12 > 2
+        assert!(v("1.0-12") > v("1.0.2"));
+        assert!(v("1.0-12") > v("1.0-2"));
+        assert!(v("1.0-12") == v("1.0.12"));
+        assert!(v("1.10") > v("1.9.9"));
+        assert!(v("1.0.0.1") > v("1.0"));
+        assert!(v("0.99") < v("1"));
+        assert!(
+            VersionRequirement::from_str("(>= 4.1.0)")
+                .unwrap()
+                .is_satisfied(&v("4.1"))
+        );
+    }
+
     #[test]
     fn can_get_minor_major() {
         assert_eq!(Version::from_str("1.0").unwrap().major_minor(), [1, 0]);
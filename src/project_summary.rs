@@ -9,8 +9,8 @@ use serde::Serialize;
 use crate::system_req::{SysDep, SysInstallationStatus};
 use crate::utils::get_max_workers;
 use crate::{
-    DiskCache, Library, Lockfile, Repository, RepositoryDatabase, ResolvedDependency, SystemInfo,
-    Version, VersionRequirement,
+    DiskCache, Library, Lockfile, RVersion, Repository, RepositoryDatabase, ResolvedDependency,
+    SystemInfo, Version, VersionRequirement,
     cache::InstallationStatus,
     lockfile::Source,
     package::{Operator, PackageType},
@@ -25,6 +25,15 @@ pub struct ProjectSummary<'a> {
     remote_info: RemoteInfo<'a>,
     sys_deps: Vec<SysDep>,
     max_workers: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renv: Option<RenvRVersion>,
+}
+
+/// The R version declared in a renv.lock of the project, if there is one
+#[derive(Debug, Clone, Serialize)]
+struct RenvRVersion {
+    r_version: String,
+    matches: bool,
 }
 
 impl<'a> ProjectSummary<'a> {
@@ -55,8 +64,26 @@ impl<'a> ProjectSummary<'a> {
             cache_root: &cache.root,
             remote_info: RemoteInfo::new(repositories, repo_dbs, &r_version.major_minor()),
             max_workers: get_max_workers(),
+            renv: None,
         }
     }
+
+    /// Reports the R version declared in the renv.lock of the project and whether it's the one
+    /// used by rv
+    pub fn with_renv_r_version(mut self, renv_r_version: &RVersion) -> Self {
+        self.renv = Some(RenvRVersion {
+            r_version: renv_r_version.to_string(),
+            matches: r_version_matches(self.r_version, renv_r_version),
+        });
+        self
+    }
+}
+
+/// A R version like `4.4` in the config matches any 4.4.x
+fn r_version_matches(r_version: &Version, other: &RVersion) -> bool {
+    let parts: Vec<_> = r_version.original.split('.').collect();
+    let other = [other.major, other.minor, other.patch].map(|x| x.to_string());
+    parts.len() <= other.len() && parts.iter().zip(&other).all(|(a, b)| a == b)
 }
 
 impl fmt::Display for ProjectSummary<'_> {
@@ -75,6 +102,18 @@ impl fmt::Display for ProjectSummary<'_> {
             num_cpus::get(),
             self.cache_root.as_path().to_string_lossy(),
         )?;
+        if let Some(renv) = &self.renv {
+            write!(
+                f,
+                "renv.lock R Version: {} ({})\n\n",
+                renv.r_version,
+                if renv.matches {
+                    "matches the R version used"
+                } else {
+                    "does not match the R version used"
+                }
+            )?;
+        }
 
         write!(f, "== Dependencies == \n{}\n", self.dependency_info)?;
         if !self.sys_deps.is_empty() {
@@ -522,3 +561,26 @@ fn get_repository_alias(r: &str, repos: &[Repository]) -> String {
         .map(|repo| repo.alias.to_string())
         .unwrap_or(r.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_match_renv_r_version() {
+        let renv_version = RVersion::new(4, 3, 2);
+        for (r_version, expected) in [
+            ("4.3", true),
+            ("4.3.2", true),
+            ("4.3.1", false),
+            ("4.4", false),
+        ] {
+            let r_version: Version = r_version.parse().unwrap();
+            assert_eq!(
+                r_version_matches(&r_version, &renv_version),
+                expected,
+                "{r_version}"
+            );
+        }
+    }
+}
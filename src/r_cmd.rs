@@ -21,7 +21,7 @@ static R_VERSION_RE: LazyLock<Regex> =
 pub static ACTIVE_R_PROCESS_IDS: LazyLock<Arc<Mutex<HashSet<u32>>>> =
     LazyLock::new(|| Arc::new(Mutex::new(HashSet::new())));
 
-fn find_r_version(output: &str) -> Option<Version> {
+pub(crate) fn find_r_version(output: &str) -> Option<Version> {
     R_VERSION_RE
         .captures(output)
         .and_then(|c| c.get(0))
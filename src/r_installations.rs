@@ -0,0 +1,465 @@
+//! The R installations rv can use, the ones `find_r_version_command` looks at: the R on the
+//! PATH and the ones installed by rig, or by hand, in `/opt/R/{version}`.
+//! rv doesn't install R itself, only the `/opt/R` ones can be removed.
+//!
+//! Installations can also be verified: R has to run and its key files have to be there. rv can
+//! record the SHA256 of all the files of an installation in a manifest so later verifications
+//! find the files that changed since.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::cache::dir_size;
+use crate::r_cmd::find_r_version;
+use crate::{RCmd, RCommandLine, Version};
+
+const OPT_R: &str = "/opt/R";
+/// The symlink rig creates in `/opt/R` to the default version
+const CURRENT_LINK: &str = "current";
+/// Where `write_manifest` records the SHA256 of the files, at the root of the installation
+const MANIFEST_FILENAME: &str = ".rv-manifest.json";
+
+/// The files every installation has, relative to its root
+#[cfg(target_os = "macos")]
+const KEY_FILES: [&str; 5] = [
+    "bin/R",
+    "lib/R/bin/exec/R",
+    "lib/R/etc/Renviron",
+    "lib/R/library/base/DESCRIPTION",
+    "lib/R/lib/libR.dylib",
+];
+#[cfg(not(target_os = "macos"))]
+const KEY_FILES: [&str; 5] = [
+    "bin/R",
+    "lib/R/bin/exec/R",
+    "lib/R/etc/Renviron",
+    "lib/R/library/base/DESCRIPTION",
+    "lib/R/lib/libR.so",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RInstallation {
+    pub version: Version,
+    /// The R executable, `None` for the one on the PATH
+    pub r: Option<PathBuf>,
+    /// The folder of the installation when rv can remove it
+    pub root: Option<PathBuf>,
+    /// When the installation folder was last modified, as a unix timestamp
+    pub installed_at: Option<i64>,
+    /// Size of the installation folder, in bytes
+    pub size: Option<u64>,
+}
+
+impl RInstallation {
+    pub fn r_command(&self) -> RCommandLine {
+        RCommandLine { r: self.r.clone() }
+    }
+
+    fn removable_root(&self) -> Result<&PathBuf, RInstallationError> {
+        self.root
+            .as_ref()
+            .ok_or_else(|| RInstallationError::NotRemovable {
+                version: self.version.original.clone(),
+            })
+    }
+}
+
+/// The installations in the order `find_r_version_command` tries them: the R on the PATH first
+pub fn find_r_installations() -> Vec<RInstallation> {
+    let mut installations = Vec::new();
+    if let Ok(version) = (RCommandLine { r: None }).version() {
+        installations.push(RInstallation {
+            version,
+            r: None,
+            root: None,
+            installed_at: None,
+            size: None,
+        });
+    }
+    installations.extend(installations_in(Path::new(OPT_R), |r| {
+        RCommandLine {
+            r: Some(r.to_path_buf()),
+        }
+        .version()
+        .ok()
+    }));
+    installations
+}
+
+/// The installations in the subfolders of `dir`, sorted by version.
+/// The `current` symlink of rig is skipped since it points to one of them.
+fn installations_in(
+    dir: &Path,
+    version_of: impl Fn(&Path) -> Option<Version>,
+) -> Vec<RInstallation> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut installations: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| {
+            let root = e.path();
+            let r = root.join("bin").join("R");
+            if !r.exists() {
+                return None;
+            }
+            let version = version_of(&r)?;
+            let installed_at = fs::metadata(&root)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64);
+            Some(RInstallation {
+                version,
+                r: Some(r),
+                size: Some(dir_size(&root)),
+                root: Some(root),
+                installed_at,
+            })
+        })
+        .collect();
+    installations.sort_by(|a, b| a.version.cmp(&b.version));
+    installations
+}
+
+/// The installations matching that version, eg `4.4` matches all the 4.4.x
+pub fn matching_installations<'a>(
+    installations: &'a [RInstallation],
+    version: &Version,
+) -> Vec<&'a RInstallation> {
+    installations
+        .iter()
+        .filter(|i| version.hazy_match(&i.version))
+        .collect()
+}
+
+/// Deletes the installation folder, and the `current` symlink of rig if it pointed to it
+pub fn remove_r_installation(installation: &RInstallation) -> Result<(), RInstallationError> {
+    let root = installation.removable_root()?;
+    if let Some(parent) = root.parent() {
+        let current = parent.join(CURRENT_LINK);
+        if fs::read_link(&current).is_ok_and(|target| parent.join(target) == *root) {
+            fs::remove_file(&current)?;
+        }
+    }
+    fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+/// A file of an installation that is missing or changed, or R not running
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstallationIssue {
+    pub path: PathBuf,
+    pub problem: String,
+}
+
+/// What `verify_r_installation` found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstallationCheck {
+    pub version: Version,
+    pub r: Option<PathBuf>,
+    /// How many files were checked against the manifest, `None` without manifest
+    pub manifest_files: Option<usize>,
+    pub issues: Vec<InstallationIssue>,
+}
+
+impl InstallationCheck {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for InstallationCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = self
+            .r
+            .as_ref()
+            .map(|r| r.display().to_string())
+            .unwrap_or_else(|| "R on the PATH".to_string());
+        if self.is_ok() {
+            write!(f, "R {} ({location}) is OK", self.version)?;
+        } else {
+            let n = self.issues.len();
+            write!(
+                f,
+                "R {} ({location}) has {n} problem{}:",
+                self.version,
+                if n == 1 { "" } else { "s" }
+            )?;
+            for issue in &self.issues {
+                write!(f, "\n  {}: {}", issue.path.display(), issue.problem)?;
+            }
+        }
+        match self.manifest_files {
+            Some(n) => write!(f, "\n  {n} files checked against the manifest"),
+            None if self.r.is_some() => write!(f, "\n  No manifest to check the files against"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// SHA256 by path relative to the root of the installation, using `/` as separator
+    files: BTreeMap<String, String>,
+}
+
+fn sha256_file(path: &Path) -> Result<String, std::io::Error> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Records the SHA256 of all the files of the installation, returning how many there are.
+/// Symlinks are not followed, R installations link to system libraries.
+pub fn write_manifest(installation: &RInstallation) -> Result<usize, RInstallationError> {
+    let root = installation.removable_root()?;
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        if !entry.file_type().is_file()
+            || entry.depth() == 1 && entry.file_name() == MANIFEST_FILENAME
+        {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(root)
+            .expect("walking the root")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.insert(relative, sha256_file(entry.path())?);
+    }
+
+    let count = files.len();
+    let mut f = tempfile::NamedTempFile::new_in(root)?;
+    f.write_all(
+        serde_json::to_string_pretty(&Manifest { files })
+            .expect("valid json")
+            .as_bytes(),
+    )?;
+    f.persist(root.join(MANIFEST_FILENAME))
+        .map_err(|e| e.error)?;
+    Ok(count)
+}
+
+/// Checks that R runs and reports the expected version, that the key files are there and, if
+/// `write_manifest` was called before, that no file changed.
+/// Only running R can be checked for the R on the PATH.
+pub fn verify_r_installation(installation: &RInstallation) -> InstallationCheck {
+    let mut issues = Vec::new();
+    let r = installation
+        .r
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(if cfg!(windows) { "R.bat" } else { "R" }));
+    match Command::new(&r).arg("--version").output() {
+        Ok(output) if !output.status.success() => issues.push(InstallationIssue {
+            path: r.clone(),
+            problem: format!("`R --version` failed with {}", output.status),
+        }),
+        Ok(output) => {
+            // R.bat on Windows writes the version to stderr
+            let out = String::from_utf8_lossy(if cfg!(windows) {
+                &output.stderr
+            } else {
+                &output.stdout
+            });
+            match find_r_version(&out) {
+                Some(v) if v == installation.version => (),
+                Some(v) => issues.push(InstallationIssue {
+                    path: r.clone(),
+                    problem: format!("reports R {v} instead of {}", installation.version),
+                }),
+                None => issues.push(InstallationIssue {
+                    path: r.clone(),
+                    problem: "`R --version` doesn't show an R version".to_string(),
+                }),
+            }
+        }
+        Err(e) => issues.push(InstallationIssue {
+            path: r.clone(),
+            problem: format!("can't be run: {e}"),
+        }),
+    }
+
+    let mut manifest_files = None;
+    if let Some(root) = &installation.root {
+        let (file_issues, checked) = check_files(root);
+        issues.extend(file_issues);
+        manifest_files = checked;
+    }
+
+    InstallationCheck {
+        version: installation.version.clone(),
+        r: installation.r.clone(),
+        manifest_files,
+        issues,
+    }
+}
+
+/// The key files and the manifest checks, returning the issues and how many files the manifest
+/// has if there is one
+fn check_files(root: &Path) -> (Vec<InstallationIssue>, Option<usize>) {
+    let mut issues: Vec<_> = KEY_FILES
+        .iter()
+        .map(|f| root.join(f))
+        .filter(|p| !p.is_file())
+        .map(|path| InstallationIssue {
+            path,
+            problem: "missing".to_string(),
+        })
+        .collect();
+
+    let manifest_path = root.join(MANIFEST_FILENAME);
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(content) => match serde_json::from_str::<Manifest>(&content) {
+            Ok(m) => m,
+            Err(e) => {
+                issues.push(InstallationIssue {
+                    path: manifest_path,
+                    problem: format!("invalid manifest: {e}"),
+                });
+                return (issues, None);
+            }
+        },
+        Err(_) => return (issues, None),
+    };
+
+    for (relative, expected) in &manifest.files {
+        let path = root.join(relative);
+        // The key files are already reported
+        if issues.iter().any(|i| i.path == path) {
+            continue;
+        }
+        let problem = match sha256_file(&path) {
+            Ok(actual) if &actual == expected => continue,
+            Ok(actual) => format!("SHA256 mismatch: expected {expected}, got {actual}"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => "missing".to_string(),
+            Err(e) => format!("can't be read: {e}"),
+        };
+        issues.push(InstallationIssue { path, problem });
+    }
+    (issues, Some(manifest.files.len()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RInstallationError {
+    #[error("R {version} is not installed, installed versions: {installed}")]
+    NotInstalled { version: String, installed: String },
+    #[error("R {version} matches several installations ({found}), use the full version")]
+    Ambiguous { version: String, found: String },
+    #[error(
+        "R {version} is the one on the PATH, only the installations in {OPT_R} can be managed by rv"
+    )]
+    NotRemovable { version: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_installation(dir: &Path, version: &str) {
+        for f in KEY_FILES {
+            let path = dir.join(version).join(f);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, version).unwrap();
+        }
+    }
+
+    /// rig names the folders after the version
+    fn version_from_folder(r: &Path) -> Option<Version> {
+        let folder = r.parent()?.parent()?.file_name()?.to_str()?;
+        folder.parse().ok()
+    }
+
+    #[test]
+    fn can_find_and_remove_installations() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for version in ["4.4.1", "4.3.3", "4.4.2"] {
+            make_installation(tempdir.path(), version);
+        }
+        std::fs::create_dir(tempdir.path().join("not-r")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("4.4.2", tempdir.path().join(CURRENT_LINK)).unwrap();
+
+        let installations = installations_in(tempdir.path(), version_from_folder);
+        assert_eq!(
+            installations
+                .iter()
+                .map(|i| i.version.original.as_str())
+                .collect::<Vec<_>>(),
+            vec!["4.3.3", "4.4.1", "4.4.2"]
+        );
+        assert!(installations.iter().all(|i| i.installed_at.is_some()));
+
+        let version: Version = "4.4".parse().unwrap();
+        assert_eq!(matching_installations(&installations, &version).len(), 2);
+
+        remove_r_installation(&installations[2]).unwrap();
+        assert!(!tempdir.path().join("4.4.2").exists());
+        assert!(
+            std::fs::symlink_metadata(tempdir.path().join(CURRENT_LINK)).is_err(),
+            "the current symlink pointed to the removed version"
+        );
+        let installations = installations_in(tempdir.path(), version_from_folder);
+        assert_eq!(installations.len(), 2);
+
+        let on_path = RInstallation {
+            r: None,
+            root: None,
+            ..installations[0].clone()
+        };
+        assert!(matches!(
+            remove_r_installation(&on_path),
+            Err(RInstallationError::NotRemovable { .. })
+        ));
+    }
+
+    #[test]
+    fn can_check_installation_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        make_installation(tempdir.path(), "4.4.1");
+        let root = tempdir.path().join("4.4.1");
+        std::fs::write(root.join("lib/R/library/base/NAMESPACE"), "export()").unwrap();
+        assert_eq!(check_files(&root), (Vec::new(), None));
+
+        let installation = installations_in(tempdir.path(), version_from_folder).remove(0);
+        assert_eq!(write_manifest(&installation).unwrap(), KEY_FILES.len() + 1);
+        assert_eq!(check_files(&root), (Vec::new(), Some(KEY_FILES.len() + 1)));
+
+        std::fs::write(root.join("lib/R/library/base/NAMESPACE"), "changed").unwrap();
+        std::fs::remove_file(root.join(KEY_FILES[4])).unwrap();
+        let (issues, checked) = check_files(&root);
+        assert_eq!(checked, Some(KEY_FILES.len() + 1));
+        assert_eq!(
+            issues
+                .iter()
+                .map(|i| (
+                    i.path.strip_prefix(&root).unwrap().to_path_buf(),
+                    i.problem.split(':').next().unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from(KEY_FILES[4]), "missing"),
+                (
+                    PathBuf::from("lib/R/library/base/NAMESPACE"),
+                    "SHA256 mismatch"
+                ),
+            ]
+        );
+    }
+}
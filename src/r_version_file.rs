@@ -0,0 +1,225 @@
+//! `.R-version` files pin the R version to use in a folder and all its subfolders, like
+//! `.python-version` files for pyenv. They contain a version, eg `4.4` or `4.4.1`, or one of the
+//! aliases of `resolve_alias`, eg `release`.
+//! Without one, the R version from the config is used.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use fs_err as fs;
+
+use crate::consts::R_VERSION_FILENAME;
+use crate::r_versions::{R_VERSION_ALIASES, is_alias, resolve_alias};
+use crate::{RVersion, RVersionManifest, Version};
+
+/// A `.R-version` file and what it contains
+#[derive(Debug, Clone, PartialEq)]
+pub struct RVersionFile {
+    pub path: PathBuf,
+    /// A version or an alias, already validated
+    pub value: String,
+}
+
+impl RVersionFile {
+    fn from_file(path: PathBuf) -> Result<Self, RVersionFileError> {
+        let content = fs::read_to_string(&path).map_err(|source| RVersionFileError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        // Only the first line is used, other tools sometimes add comments after it
+        let value = content
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        validate(&value).map_err(|message| RVersionFileError::Invalid {
+            path: path.clone(),
+            message,
+        })?;
+        Ok(Self { path, value })
+    }
+
+    /// Looks for a `.R-version` file in that folder and then in its parents, stopping at the
+    /// first one found.
+    /// A file that doesn't contain a valid version or alias is an error, not skipped.
+    pub fn find(start: impl AsRef<Path>) -> Result<Option<Self>, RVersionFileError> {
+        for dir in start.as_ref().ancestors() {
+            let path = dir.join(R_VERSION_FILENAME);
+            if path.is_file() {
+                return Self::from_file(path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes a `.R-version` file in that folder, atomically replacing the existing one
+    pub fn write(dir: impl AsRef<Path>, value: &str) -> Result<Self, RVersionFileError> {
+        let path = dir.as_ref().join(R_VERSION_FILENAME);
+        let value = value.trim().to_string();
+        validate(&value).map_err(|message| RVersionFileError::Invalid {
+            path: path.clone(),
+            message,
+        })?;
+        // Written next to it and renamed so a running rv never sees a partial file
+        let io_err = |source| RVersionFileError::Io {
+            path: path.clone(),
+            source,
+        };
+        let mut f = tempfile::NamedTempFile::new_in(dir.as_ref()).map_err(io_err)?;
+        f.write_all(format!("{value}\n").as_bytes())
+            .map_err(io_err)?;
+        f.persist(&path).map_err(|e| io_err(e.error))?;
+        Ok(Self { path, value })
+    }
+
+    pub fn is_alias(&self) -> bool {
+        is_alias(&self.value)
+    }
+
+    /// The R version to look for. The manifest is only needed, and only called, for aliases.
+    pub fn resolve<E>(
+        &self,
+        manifest: impl FnOnce() -> Result<RVersionManifest, E>,
+    ) -> Result<Version, RVersionFileError>
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        if !self.is_alias() {
+            let version = RVersion::from_str(&self.value).expect("validated when reading");
+            // Keeping what was written so `4.4` matches any 4.4.x
+            return Ok(if version.is_release() {
+                Version::from_str(&self.value).expect("valid R version")
+            } else {
+                to_version(&version)
+            });
+        }
+
+        let manifest = manifest().map_err(|e| RVersionFileError::Manifest {
+            path: self.path.clone(),
+            source: e.into(),
+        })?;
+        resolve_alias(&self.value, &manifest)
+            .map(|v| to_version(&v))
+            .ok_or_else(|| RVersionFileError::UnknownAlias {
+                path: self.path.clone(),
+                alias: self.value.clone(),
+            })
+    }
+}
+
+/// Pre-releases can't be installed side by side with their release, we only look for the
+/// numeric part
+fn to_version(version: &RVersion) -> Version {
+    Version::from_str(&format!(
+        "{}.{}.{}",
+        version.major, version.minor, version.patch
+    ))
+    .expect("valid version")
+}
+
+fn validate(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("the file is empty".to_string());
+    }
+    if is_alias(value) || RVersion::from_str(value).is_ok() {
+        return Ok(());
+    }
+    Err(format!(
+        "`{value}` is not an R version like `4.4.1`, or one of: {}",
+        R_VERSION_ALIASES.join(", ")
+    ))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RVersionFileError {
+    #[error("Failed to read `{path}`: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid R version in `{path}`: {message}")]
+    Invalid { path: PathBuf, message: String },
+    #[error("Failed to get the R versions manifest to resolve `{path}`: {source}")]
+    Manifest {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("The R versions manifest has no version for `{alias}`, from `{path}`")]
+    UnknownAlias { path: PathBuf, alias: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManifestError;
+
+    fn no_manifest() -> Result<RVersionManifest, ManifestError> {
+        panic!("the manifest is only needed for aliases")
+    }
+
+    #[test]
+    fn finds_closest_r_version_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let nested = tempdir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(RVersionFile::find(&nested).unwrap(), None);
+
+        RVersionFile::write(tempdir.path(), "4.3").unwrap();
+        let found = RVersionFile::find(&nested).unwrap().unwrap();
+        assert_eq!(found.path, tempdir.path().join(R_VERSION_FILENAME));
+        assert_eq!(found.resolve(no_manifest).unwrap().original, "4.3");
+
+        // The closest one wins
+        RVersionFile::write(tempdir.path().join("a"), "4.4.1\n").unwrap();
+        let found = RVersionFile::find(&nested).unwrap().unwrap();
+        assert_eq!(
+            found.path,
+            tempdir.path().join("a").join(R_VERSION_FILENAME)
+        );
+        assert_eq!(found.value, "4.4.1");
+        assert!(!found.is_alias());
+    }
+
+    #[test]
+    fn can_resolve_aliases_in_r_version_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest = || {
+            RVersionManifest::from_json(
+                &std::fs::read_to_string("src/tests/r_versions/r-versions.json").unwrap(),
+            )
+        };
+        for (alias, expected) in [
+            ("release", "4.5.1"),
+            ("oldrel", "4.4.3"),
+            ("devel", "4.6.0"),
+        ] {
+            let file = RVersionFile::write(tempdir.path(), alias).unwrap();
+            assert!(file.is_alias());
+            assert_eq!(file.resolve(manifest).unwrap().original, expected);
+        }
+
+        let file = RVersionFile::write(tempdir.path(), "oldrel-10").unwrap();
+        assert!(matches!(
+            file.resolve(manifest),
+            Err(RVersionFileError::UnknownAlias { .. })
+        ));
+    }
+
+    #[test]
+    fn errors_on_invalid_r_version_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(R_VERSION_FILENAME);
+        for content in ["", "four", "4", "stable", "R 4.4"] {
+            std::fs::write(&path, content).unwrap();
+            let err = RVersionFile::find(tempdir.path()).unwrap_err();
+            assert!(
+                matches!(err, RVersionFileError::Invalid { .. }),
+                "{content}: {err}"
+            );
+            assert!(err.to_string().contains(&path.display().to_string()));
+        }
+        assert!(RVersionFile::write(tempdir.path(), "nope").is_err());
+    }
+}
@@ -0,0 +1,931 @@
+//! Versions of R itself and the ones released so far, from the index maintained by r-hub.
+//! The index is cached like the package databases, see `DiskCache::get_r_versions_manifest_entry`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::BitAnd;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize, Serializer};
+use url::Url;
+
+use crate::http::{HttpError, is_offline};
+use crate::{DiskCache, HttpDownload};
+
+pub const R_VERSIONS_URL: &str = "https://api.r-hub.io/rversions/r-versions";
+
+/// The stage of an unreleased R version, in the order they happen before the release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PreRelease {
+    /// R-devel, optionally the snapshot of a given day as `[year, month, day]`.
+    /// A snapshot without a date is older than all the dated ones.
+    Devel(Option<[u32; 3]>),
+    Alpha,
+    Beta,
+    Rc,
+}
+
+impl fmt::Display for PreRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Devel(None) => write!(f, "devel"),
+            Self::Devel(Some([y, m, d])) => write!(f, "{y:04}-{m:02}-{d:02}"),
+            Self::Alpha => write!(f, "alpha"),
+            Self::Beta => write!(f, "beta"),
+            Self::Rc => write!(f, "rc"),
+        }
+    }
+}
+
+impl FromStr for PreRelease {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "devel" => return Ok(Self::Devel(None)),
+            "alpha" => return Ok(Self::Alpha),
+            "beta" => return Ok(Self::Beta),
+            "rc" => return Ok(Self::Rc),
+            _ => (),
+        }
+        let date: Vec<_> = s.split('-').map(|x| x.parse::<u32>()).collect();
+        match date.as_slice() {
+            [Ok(y), Ok(m), Ok(d)] if (1..=12).contains(m) && (1..=31).contains(d) => {
+                Ok(Self::Devel(Some([*y, *m, *d])))
+            }
+            _ => Err(format!("{s} is not a valid R pre-release")),
+        }
+    }
+}
+
+/// A version of R itself, eg `4.4.1`, `4.5.0-devel` or the R-devel snapshot `4.5.0-2024-01-15`.
+/// Unlike package versions, it always has 3 numeric components (`4.3` is `4.3.0`) and can be
+/// followed by a pre-release, separated by `-` or a space.
+/// Pre-releases come before the release: `4.4.0-devel < 4.4.0-alpha < 4.4.0-rc < 4.4.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub pre: Option<PreRelease>,
+}
+
+impl RVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+
+    pub fn is_release(&self) -> bool {
+        self.pre.is_none()
+    }
+
+    pub fn major_minor(&self) -> [u32; 2] {
+        [self.major, self.minor]
+    }
+}
+
+impl FromStr for RVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("{s} cannot be parsed as an R version");
+        let trimmed = s.trim();
+        let (numbers, pre) = match trimmed.find(['-', ' ']) {
+            Some(idx) => (&trimmed[..idx], Some(trimmed[idx + 1..].trim())),
+            None => (trimmed, None),
+        };
+        let parts = numbers
+            .split('.')
+            .map(|x| x.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| err())?;
+        let (major, minor, patch) = match parts.as_slice() {
+            [major, minor] => (*major, *minor, 0),
+            [major, minor, patch] => (*major, *minor, *patch),
+            _ => return Err(err()),
+        };
+        let pre = pre.map(|p| p.parse()).transpose().map_err(|_| err())?;
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+}
+
+impl fmt::Display for RVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for RVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        [self.major, self.minor, self.patch]
+            .cmp(&[other.major, other.minor, other.patch])
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+    }
+}
+
+impl PartialOrd for RVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Serialize for RVersion {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The lowest version starting with `major.minor.patch`, used as an exclusive upper bound so
+/// the pre-releases of the next version are excluded as well
+fn lowest_version(major: u32, minor: u32, patch: u32) -> RVersion {
+    RVersion {
+        pre: Some(PreRelease::Devel(None)),
+        ..RVersion::new(major, minor, patch)
+    }
+}
+
+/// A single requirement on an R version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparator {
+    /// `=4.3.2`, also written `==4.3.2`
+    Exact(RVersion),
+    /// `>4.3.2`
+    Greater(RVersion),
+    /// `>=4.3`
+    GreaterOrEqual(RVersion),
+    /// `<4.3.2`
+    Lower(RVersion),
+    /// `<=4.3.2`
+    LowerOrEqual(RVersion),
+    /// `~=4.3` or `~=4.3.2`: compatible release, the last component given can increase.
+    /// `~=4.3` is `>=4.3, <5` and `~=4.3.2` is `>=4.3.2, <4.4`.
+    Compatible {
+        version: RVersion,
+        patch_given: bool,
+    },
+    /// `^4.3.0`: same minor version, `>=4.3.0, <4.4.0`
+    Caret(RVersion),
+}
+
+impl Comparator {
+    pub fn matches(&self, version: &RVersion) -> bool {
+        match self {
+            Self::Exact(v) => version == v,
+            Self::Greater(v) => version > v,
+            Self::GreaterOrEqual(v) => version >= v,
+            Self::Lower(v) => version < v,
+            Self::LowerOrEqual(v) => version <= v,
+            Self::Compatible {
+                version: v,
+                patch_given,
+            } => {
+                let upper = if *patch_given {
+                    lowest_version(v.major, v.minor + 1, 0)
+                } else {
+                    lowest_version(v.major + 1, 0, 0)
+                };
+                version >= v && version < &upper
+            }
+            Self::Caret(v) => version >= v && version < &lowest_version(v.major, v.minor + 1, 0),
+        }
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(v) => write!(f, "={v}"),
+            Self::Greater(v) => write!(f, ">{v}"),
+            Self::GreaterOrEqual(v) => write!(f, ">={v}"),
+            Self::Lower(v) => write!(f, "<{v}"),
+            Self::LowerOrEqual(v) => write!(f, "<={v}"),
+            Self::Compatible {
+                version,
+                patch_given: true,
+            } => write!(f, "~={version}"),
+            Self::Compatible {
+                version,
+                patch_given: false,
+            } => {
+                write!(f, "~={}.{}", version.major, version.minor)?;
+                if let Some(pre) = &version.pre {
+                    write!(f, "-{pre}")?;
+                }
+                Ok(())
+            }
+            Self::Caret(v) => write!(f, "^{v}"),
+        }
+    }
+}
+
+impl FromStr for Comparator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = s.trim();
+        // Longest operators first since `>` is a prefix of `>=`
+        let (op, rest) = [">=", "<=", "~=", "==", ">", "<", "=", "^"]
+            .into_iter()
+            .find_map(|op| inner.strip_prefix(op).map(|rest| (op, rest.trim())))
+            .ok_or_else(|| format!("{s} does not have a valid operator"))?;
+        let version: RVersion = rest.parse()?;
+
+        Ok(match op {
+            ">=" => Self::GreaterOrEqual(version),
+            "<=" => Self::LowerOrEqual(version),
+            "~=" => Self::Compatible {
+                version,
+                patch_given: rest
+                    .split(['-', ' '])
+                    .next()
+                    .unwrap_or_default()
+                    .split('.')
+                    .count()
+                    == 3,
+            },
+            "==" | "=" => Self::Exact(version),
+            ">" => Self::Greater(version),
+            "<" => Self::Lower(version),
+            "^" => Self::Caret(version),
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// Requirements on an R version, eg `>=4.2, <4.5`, that all need to be satisfied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionConstraint {
+    comparators: Vec<Comparator>,
+}
+
+impl VersionConstraint {
+    pub fn comparators(&self) -> &[Comparator] {
+        &self.comparators
+    }
+
+    pub fn matches(&self, version: &RVersion) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+impl From<Comparator> for VersionConstraint {
+    fn from(comparator: Comparator) -> Self {
+        Self {
+            comparators: vec![comparator],
+        }
+    }
+}
+
+impl FromStr for VersionConstraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let comparators = s
+            .split(',')
+            .map(|c| c.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { comparators })
+    }
+}
+
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparators: Vec<_> = self.comparators.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", comparators.join(", "))
+    }
+}
+
+/// Both constraints need to be satisfied
+impl BitAnd for VersionConstraint {
+    type Output = Self;
+
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        self.comparators.extend(rhs.comparators);
+        self
+    }
+}
+
+/// A released version of R.
+/// Fields we don't use are ignored so additions to the index don't break parsing.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RRelease {
+    pub version: String,
+    pub date: Option<String>,
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct RVersionManifest {
+    releases: Vec<RRelease>,
+}
+
+impl RVersionManifest {
+    pub fn from_json(content: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(content)
+    }
+
+    pub fn releases(&self) -> &[RRelease] {
+        &self.releases
+    }
+
+    /// All the released versions, oldest first. Versions we can't parse are skipped.
+    pub fn versions(&self) -> Vec<RVersion> {
+        let mut versions: Vec<RVersion> = self
+            .releases
+            .iter()
+            .filter_map(|r| r.version.parse().ok())
+            .filter(RVersion::is_release)
+            .collect();
+        versions.sort();
+        versions
+    }
+
+    /// The latest released version
+    pub fn release(&self) -> Option<RVersion> {
+        self.versions().pop()
+    }
+
+    /// The latest version of the minor release before the current one, eg 4.4.3 if the latest
+    /// release is 4.5.1
+    pub fn oldrel(&self) -> Option<RVersion> {
+        self.oldrel_n(1)
+    }
+
+    /// The latest version of the nth minor release before the current one, eg 4.3.3 for
+    /// `oldrel_n(2)` if the latest release is 4.5.1. `oldrel_n(0)` is the release.
+    pub fn oldrel_n(&self, n: usize) -> Option<RVersion> {
+        let versions = self.versions();
+        let mut minors: Vec<_> = versions.iter().map(|v| v.major_minor()).collect();
+        minors.dedup();
+        let minor = *minors.iter().rev().nth(n)?;
+        versions
+            .into_iter()
+            .rev()
+            .find(|v| v.major_minor() == minor)
+    }
+
+    /// The version being developed: the minor release after the current one, eg 4.6.0-devel if
+    /// the latest release is 4.5.1
+    pub fn devel(&self) -> Option<RVersion> {
+        let [major, minor] = self.release()?.major_minor();
+        Some(lowest_version(major, minor + 1, 0))
+    }
+}
+
+/// The aliases accepted by `resolve_alias`, shown when one is not recognized
+pub const R_VERSION_ALIASES: &[&str] = &[
+    "release",
+    "latest",
+    "oldrel",
+    "oldrel-<n>",
+    "devel",
+    "devel-<yyyy-mm-dd>",
+];
+
+/// Finds which version an alias used by the R community refers to in the manifest:
+/// - `release` or `latest`: the latest release
+/// - `oldrel`: the latest version of the previous minor release, same as `oldrel-1`
+/// - `oldrel-<n>`: the latest version of the nth minor release before the current one
+/// - `devel`: the version being developed
+/// - `devel-<yyyy-mm-dd>`, also written `R-devel-<yyyy-mm-dd>`: the snapshot of the version being
+///   developed from that day, eg `4.6.0-2025-07-01`
+///
+/// Returns `None` if the alias is not one of those or if the manifest doesn't have that version.
+/// The manifest only lists releases so the date of a devel snapshot is not validated: any
+/// well-formed date is accepted, even one without a snapshot.
+pub fn resolve_alias(alias: &str, manifest: &RVersionManifest) -> Option<RVersion> {
+    match alias.trim().to_ascii_lowercase().as_str() {
+        "release" | "latest" => manifest.release(),
+        "oldrel" => manifest.oldrel(),
+        "devel" => manifest.devel(),
+        other => {
+            if let Some(date) = other
+                .strip_prefix("r-devel-")
+                .or_else(|| other.strip_prefix("devel-"))
+            {
+                let Ok(pre @ PreRelease::Devel(Some(_))) = date.parse() else {
+                    return None;
+                };
+                return manifest.devel().map(|v| RVersion {
+                    pre: Some(pre),
+                    ..v
+                });
+            }
+            let n = other.strip_prefix("oldrel-")?.parse::<usize>().ok()?;
+            if n == 0 {
+                return None;
+            }
+            manifest.oldrel_n(n)
+        }
+    }
+}
+
+/// Whether it's written like one of the `R_VERSION_ALIASES`, without checking the manifest has a
+/// version for it
+pub fn is_alias(alias: &str) -> bool {
+    match alias.trim().to_ascii_lowercase().as_str() {
+        "release" | "latest" | "oldrel" | "devel" => true,
+        other => {
+            if let Some(date) = other
+                .strip_prefix("r-devel-")
+                .or_else(|| other.strip_prefix("devel-"))
+            {
+                return matches!(date.parse(), Ok(PreRelease::Devel(Some(_))));
+            }
+            other
+                .strip_prefix("oldrel-")
+                .and_then(|n| n.parse::<usize>().ok())
+                .is_some_and(|n| n > 0)
+        }
+    }
+}
+
+/// Gets the R versions manifest from the cache if it's recent enough, downloading it otherwise.
+/// `refresh` ignores the cached copy. When offline or if the download fails, an outdated
+/// cached copy is used if there is one.
+pub fn get_r_versions_manifest(
+    http: &impl HttpDownload,
+    cache: &DiskCache,
+    refresh: bool,
+) -> Result<RVersionManifest, ManifestError> {
+    let (path, is_fresh) = cache.get_r_versions_manifest_entry();
+    let from_cache = || -> Result<RVersionManifest, ManifestError> {
+        let content = fs_err::read_to_string(&path)?;
+        Ok(RVersionManifest::from_json(&content)?)
+    };
+
+    if !refresh && (is_fresh || is_offline()) && path.exists() {
+        match from_cache() {
+            Ok(manifest) => return Ok(manifest),
+            Err(e) => log::warn!("Ignoring the cached R versions manifest: {e}"),
+        }
+    }
+
+    let url = Url::parse(R_VERSIONS_URL).unwrap();
+    if let Err(e) = http.download_if_modified(&url, &path) {
+        if !refresh && path.exists() {
+            log::warn!("Failed to download the R versions manifest, using the cached one: {e}");
+        } else {
+            return Err(e.into());
+        }
+    }
+    from_cache()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error(transparent)]
+    Http(#[from] HttpError),
+    #[error("Failed to read the R versions manifest: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid R versions manifest: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn manifest() -> RVersionManifest {
+        RVersionManifest::from_json(
+            &std::fs::read_to_string("src/tests/r_versions/r-versions.json").unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn rv(s: &str) -> RVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn can_parse_r_versions() {
+        for (input, expected) in [
+            ("4.4.1", "4.4.1"),
+            ("4.3", "4.3.0"),
+            (" 4.3.2 ", "4.3.2"),
+            ("4.4.0 devel", "4.4.0-devel"),
+            ("4.4.0-devel", "4.4.0-devel"),
+            ("4.4.0-DEVEL", "4.4.0-devel"),
+            ("4.4.0-2024-01-15", "4.4.0-2024-01-15"),
+            ("4.4.0 2024-1-5", "4.4.0-2024-01-05"),
+            ("4.5.0-alpha", "4.5.0-alpha"),
+            ("4.5.0 beta", "4.5.0-beta"),
+            ("4.5.0-RC", "4.5.0-rc"),
+            ("4.10.0", "4.10.0"),
+        ] {
+            assert_eq!(rv(input).to_string(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn errors_on_invalid_r_versions() {
+        for input in [
+            "",
+            "4",
+            "4.4.1.1",
+            "4.x.1",
+            "4.4.1-",
+            "4.4.1-gamma",
+            "4.4.1-2024-13-01",
+            "4.4.1-2024-01",
+            "-4.4.1",
+            "4..1",
+        ] {
+            assert!(input.parse::<RVersion>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn compares_r_versions() {
+        use Ordering::*;
+
+        for (a, b, expected) in [
+            // Numeric components
+            ("4.4.1", "4.4.1", Equal),
+            ("4.4.0", "4.4.1", Less),
+            ("4.4.1", "4.3.3", Greater),
+            ("3.6.3", "4.0.0", Less),
+            ("4.10.0", "4.9.0", Greater),
+            ("4.2.10", "4.2.9", Greater),
+            ("10.0.0", "9.9.9", Greater),
+            ("4.1.0", "4.0.10", Greater),
+            // Missing patch is 0
+            ("4.3", "4.3.0", Equal),
+            ("4.3", "4.3.1", Less),
+            ("4.4", "4.3.3", Greater),
+            ("4.10", "4.9", Greater),
+            // Pre-releases come before their release
+            ("4.4.0-devel", "4.4.0", Less),
+            ("4.4.0 devel", "4.4", Less),
+            ("4.4.0-alpha", "4.4.0", Less),
+            ("4.4.0-beta", "4.4.0", Less),
+            ("4.4.0-rc", "4.4.0", Less),
+            // ... but after the previous releases
+            ("4.4.0-devel", "4.3.3", Greater),
+            ("4.4.0-devel", "4.3.99", Greater),
+            ("4.5.0-devel", "4.4.10", Greater),
+            // Pre-releases stages
+            ("4.4.0-devel", "4.4.0-alpha", Less),
+            ("4.4.0-alpha", "4.4.0-beta", Less),
+            ("4.4.0-beta", "4.4.0-rc", Less),
+            ("4.4.0-rc", "4.4.0-RC", Equal),
+            ("4.4.0 devel", "4.4.0-devel", Equal),
+            // Devel snapshots, compared by date
+            ("4.4.0-2024-01-15", "4.4.0-2024-01-16", Less),
+            ("4.4.0-2024-02-01", "4.4.0-2024-01-31", Greater),
+            ("4.4.0-2023-12-31", "4.4.0-2024-01-01", Less),
+            ("4.4.0-2024-01-15", "4.4.0-2024-1-15", Equal),
+            ("4.4.0-2024-01-15", "4.4.0-devel", Greater),
+            ("4.4.0-2024-01-15", "4.4.0-alpha", Less),
+            ("4.4.0-2024-01-15", "4.4.0", Less),
+            ("4.4.0-2024-01-15", "4.3.3", Greater),
+            // The version matters more than the snapshot date
+            ("4.4.0-2025-01-01", "4.5.0-2024-01-01", Less),
+            ("4.4.1-devel", "4.4.0-rc", Greater),
+        ] {
+            assert_eq!(rv(a).cmp(&rv(b)), expected, "{a} vs {b}");
+            assert_eq!(rv(b).cmp(&rv(a)), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn can_sort_r_versions() {
+        let mut versions: Vec<_> = [
+            "4.4.0",
+            "4.4.0-rc",
+            "4.3.3",
+            "4.4.0-2024-01-15",
+            "4.4.0-devel",
+            "4.10.0",
+            "4.4.0-alpha",
+            "4.4.1",
+        ]
+        .into_iter()
+        .map(rv)
+        .collect();
+        versions.sort();
+        assert_eq!(
+            versions.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            vec![
+                "4.3.3",
+                "4.4.0-devel",
+                "4.4.0-2024-01-15",
+                "4.4.0-alpha",
+                "4.4.0-rc",
+                "4.4.0",
+                "4.4.1",
+                "4.10.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn r_versions_roundtrip_through_serde() {
+        let versions = vec![rv("4.4.1"), rv("4.3"), rv("4.5.0 2024-01-15")];
+        let json = serde_json::to_string(&versions).unwrap();
+        assert_eq!(json, r#"["4.4.1","4.3.0","4.5.0-2024-01-15"]"#);
+        let back: Vec<RVersion> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, versions);
+        assert!(serde_json::from_str::<RVersion>(r#""4.x""#).is_err());
+    }
+
+    fn constraint(s: &str) -> VersionConstraint {
+        s.parse().unwrap()
+    }
+
+    /// Versions around the ones used in constraints, including pre-releases
+    fn all_versions() -> Vec<RVersion> {
+        let pres = [
+            None,
+            Some(PreRelease::Devel(None)),
+            Some(PreRelease::Devel(Some([2024, 1, 15]))),
+            Some(PreRelease::Alpha),
+            Some(PreRelease::Rc),
+        ];
+        let mut out = Vec::new();
+        for major in 3..=5 {
+            for minor in 0..=3 {
+                for patch in 0..=2 {
+                    for pre in pres {
+                        out.push(RVersion {
+                            pre,
+                            ..RVersion::new(major, minor, patch)
+                        });
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn can_match_version_constraints() {
+        for (c, matching, not_matching) in [
+            (
+                ">=4.2",
+                vec!["4.2.0", "4.2.1", "5.0.0"],
+                vec!["4.1.3", "4.2.0-rc"],
+            ),
+            (
+                ">4.2.1",
+                vec!["4.2.2", "4.3.0-devel"],
+                vec!["4.2.1", "4.2.0"],
+            ),
+            ("<4.2", vec!["4.1.3", "4.2.0-rc"], vec!["4.2.0", "4.2.1"]),
+            ("<=4.2.1", vec!["4.2.1", "4.2.0"], vec!["4.2.2"]),
+            ("=4.3.2", vec!["4.3.2"], vec!["4.3.1", "4.3.3", "4.3.2-rc"]),
+            ("==4.3", vec!["4.3.0"], vec!["4.3.1"]),
+            (
+                "~=4.3",
+                vec!["4.3.0", "4.4.1", "4.10.0"],
+                vec!["4.2.3", "5.0.0", "5.0.0-devel"],
+            ),
+            (
+                "~=4.3.2",
+                vec!["4.3.2", "4.3.9"],
+                vec!["4.3.1", "4.4.0", "4.4.0-rc"],
+            ),
+            (
+                "^4.3.0",
+                vec!["4.3.0", "4.3.3"],
+                vec!["4.2.3", "4.4.0", "4.4.0-devel"],
+            ),
+            ("^4.3.2", vec!["4.3.2", "4.3.3"], vec!["4.3.1", "4.4.0"]),
+            (
+                ">=4.2, <4.4",
+                vec!["4.2.0", "4.3.3"],
+                vec!["4.1.0", "4.4.0"],
+            ),
+            (" >= 4.2 ,<= 4.3.1 ", vec!["4.3.1"], vec!["4.3.2"]),
+            ("^4.4.0, >4.4.1", vec!["4.4.2"], vec!["4.4.1", "4.5.0"]),
+        ] {
+            let c = constraint(c);
+            for v in matching {
+                assert!(c.matches(&rv(v)), "{c} should match {v}");
+            }
+            for v in not_matching {
+                assert!(!c.matches(&rv(v)), "{c} should not match {v}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors_on_invalid_version_constraints() {
+        for input in [
+            "",
+            "4.3",
+            "≥4.3",
+            ">=",
+            ">=4",
+            "~=4.x",
+            ">=4.2,",
+            ">=4.2,,<5",
+            "=>4.2",
+        ] {
+            assert!(input.parse::<VersionConstraint>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn can_combine_version_constraints() {
+        let combined = constraint(">=4.2") & constraint("<4.4") & constraint("~=4.3.1");
+        assert_eq!(combined.to_string(), ">=4.2.0, <4.4.0, ~=4.3.1");
+        assert!(combined.matches(&rv("4.3.2")));
+        assert!(!combined.matches(&rv("4.3.0")));
+        assert!(!combined.matches(&rv("4.4.0")));
+    }
+
+    #[test]
+    fn version_constraints_algebra() {
+        let versions = all_versions();
+
+        for v in &versions {
+            // `~=4.3` doesn't have a patch version
+            let compatible_minor = VersionConstraint::from(Comparator::Compatible {
+                version: RVersion { patch: 0, ..*v },
+                patch_given: false,
+            });
+            let defining = [
+                constraint(&format!("={v}")),
+                constraint(&format!(">={v}")),
+                constraint(&format!("<={v}")),
+                constraint(&format!("~={v}")),
+                constraint(&format!("^{v}")),
+                compatible_minor.clone(),
+            ];
+            for c in &defining {
+                assert!(c.matches(v), "{c} should match {v}");
+                assert_eq!(c.to_string().parse::<VersionConstraint>(), Ok(c.clone()));
+            }
+
+            // From the narrowest to the widest
+            let chain = [
+                constraint(&format!("={v}")),
+                constraint(&format!("^{v}")),
+                constraint(&format!("~={v}")),
+                compatible_minor,
+                VersionConstraint::from(Comparator::GreaterOrEqual(RVersion { patch: 0, ..*v })),
+            ];
+            for other in &versions {
+                for pair in chain.windows(2) {
+                    if pair[0].matches(other) {
+                        assert!(
+                            pair[1].matches(other),
+                            "{} ⊄ {} for {other}",
+                            pair[0],
+                            pair[1]
+                        );
+                    }
+                }
+                // Strict comparisons are subsets of the non-strict ones
+                for (strict, loose) in [(">", ">="), ("<", "<=")] {
+                    if constraint(&format!("{strict}{v}")).matches(other) {
+                        assert!(constraint(&format!("{loose}{v}")).matches(other));
+                    }
+                }
+                // Combining is the intersection
+                let a = constraint(&format!(">={v}"));
+                let b = constraint(&format!("<{}.{}.0", v.major, v.minor + 2));
+                assert_eq!(
+                    (a.clone() & b.clone()).matches(other),
+                    a.matches(other) && b.matches(other)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn can_parse_manifest() {
+        let manifest = manifest();
+        assert_eq!(manifest.releases().len(), 10);
+        assert_eq!(manifest.releases()[7].nickname, None);
+        assert_eq!(manifest.releases()[9].nickname, None);
+        assert_eq!(manifest.release().unwrap(), rv("4.5.1"));
+        assert_eq!(manifest.oldrel().unwrap(), rv("4.4.3"));
+        assert_eq!(manifest.devel().unwrap(), rv("4.6.0-devel"));
+    }
+
+    #[test]
+    fn can_resolve_aliases() {
+        let manifest = manifest();
+        for (alias, expected) in [
+            ("release", Some("4.5.1")),
+            ("latest", Some("4.5.1")),
+            ("Release", Some("4.5.1")),
+            ("oldrel", Some("4.4.3")),
+            ("oldrel-1", Some("4.4.3")),
+            ("oldrel-2", Some("4.3.3")),
+            ("oldrel-3", None),
+            ("devel", Some("4.6.0-devel")),
+            ("devel-2025-07-01", Some("4.6.0-2025-07-01")),
+            ("R-devel-2025-07-01", Some("4.6.0-2025-07-01")),
+            ("devel-2025-13-01", None),
+            ("devel-alpha", None),
+            ("oldrel-0", None),
+            ("oldrel-x", None),
+            ("stable", None),
+            ("4.4.1", None),
+        ] {
+            assert_eq!(resolve_alias(alias, &manifest), expected.map(rv), "{alias}");
+            // `oldrel-3` is valid, the manifest just doesn't go back that far
+            assert_eq!(
+                is_alias(alias),
+                expected.is_some() || alias == "oldrel-3",
+                "{alias}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_manifest_has_no_versions() {
+        let manifest = RVersionManifest::from_json("[]").unwrap();
+        assert!(manifest.release().is_none());
+        assert!(manifest.oldrel().is_none());
+        assert!(manifest.devel().is_none());
+        assert!(resolve_alias("release", &manifest).is_none());
+    }
+
+    /// Serves the fixture and counts the downloads
+    struct Counting(Cell<usize>);
+
+    impl HttpDownload for Counting {
+        fn download<W: Write>(
+            &self,
+            _: &Url,
+            writer: &mut W,
+            _: Vec<(&str, String)>,
+        ) -> Result<u64, HttpError> {
+            self.0.set(self.0.get() + 1);
+            let content = std::fs::read("src/tests/r_versions/r-versions.json").unwrap();
+            writer.write_all(&content).unwrap();
+            Ok(content.len() as u64)
+        }
+
+        fn download_and_untar(
+            &self,
+            _: &Url,
+            _: impl AsRef<Path>,
+            _: bool,
+        ) -> Result<(Option<PathBuf>, String), HttpError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn manifest_is_cached() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4.1".parse().unwrap(),
+            crate::SystemInfo::from_os_info(),
+            tempdir.path(),
+        )
+        .unwrap();
+        let http = Counting(Cell::new(0));
+
+        let manifest = get_r_versions_manifest(&http, &cache, false).unwrap();
+        assert_eq!(manifest.release().unwrap(), rv("4.5.1"));
+        assert!(tempdir.path().join("manifest.json").is_file());
+        get_r_versions_manifest(&http, &cache, false).unwrap();
+        assert_eq!(http.0.get(), 1);
+        get_r_versions_manifest(&http, &cache, true).unwrap();
+        assert_eq!(http.0.get(), 2);
+    }
+}
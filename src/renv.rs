@@ -1,22 +1,26 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     error::Error,
     fmt,
     path::{Path, PathBuf},
 };
 
-use crate::consts::RECOMMENDED_PACKAGES;
+use crate::consts::{BASE_PACKAGES, RECOMMENDED_PACKAGES};
+use crate::git::url::GitUrl;
+use crate::lockfile::{LockedPackage, Source as LockedSource};
+use crate::package::{Dependency, parse_dcf_records};
 use crate::{
-    Repository, RepositoryDatabase,
+    BiocRepository, Lockfile, RVersion, Repository, RepositoryDatabase,
     package::{Operator, Version, VersionRequirement, deserialize_version},
 };
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use url::Url;
 
 #[derive(Debug, PartialEq, Clone)]
 // as enum since logic to resolve depends on this
 enum RenvSource {
     Repository,
+    Bioconductor,
     GitHub,
     Local,
     Other(String),
@@ -30,6 +34,7 @@ impl<'de> Deserialize<'de> for RenvSource {
         let s = String::deserialize(deserializer)?;
         let source_enum = match s.as_str() {
             "Repository" => RenvSource::Repository,
+            "Bioconductor" => RenvSource::Bioconductor,
             "GitHub" => RenvSource::GitHub,
             "Local" => RenvSource::Local,
             other => RenvSource::Other(other.to_string()),
@@ -38,36 +43,51 @@ impl<'de> Deserialize<'de> for RenvSource {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+impl Serialize for RenvSource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(match self {
+            RenvSource::Repository => "Repository",
+            RenvSource::Bioconductor => "Bioconductor",
+            RenvSource::GitHub => "GitHub",
+            RenvSource::Local => "Local",
+            RenvSource::Other(other) => other,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct PackageInfo {
     package: String,
     #[serde(deserialize_with = "deserialize_version")]
     version: Version,
     source: RenvSource,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     repository: Option<String>, // when source is Repository
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_type: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_host: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_repo: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_username: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_sha: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_subdir: Option<String>, // when source is GitHub
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     remote_url: Option<String>, // when source is Local
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     requirements: Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     hash: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct RenvRepository {
     name: String,
@@ -75,7 +95,7 @@ struct RenvRepository {
     url: String,
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct RInfo {
     #[serde(deserialize_with = "deserialize_version")]
@@ -83,11 +103,24 @@ struct RInfo {
     repositories: Vec<RenvRepository>,
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct BiocInfo {
+    version: String,
+}
+
+/// The alias of the Bioconductor software repository, where Bioconductor packages are looked for
+/// first
+const BIOC_SOFTWARE_ALIAS: &str = "BioCsoft";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct RenvLock {
     r: RInfo,
-    packages: HashMap<String, PackageInfo>,
+    /// Only there if the project uses Bioconductor packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bioconductor: Option<BiocInfo>,
+    packages: BTreeMap<String, PackageInfo>,
 }
 
 impl RenvLock {
@@ -103,10 +136,113 @@ impl RenvLock {
             }
         };
 
-        serde_json::from_str(content.as_str()).map_err(|e| FromJsonFileError {
+        let mut renv_lock: Self =
+            serde_json::from_str(content.as_str()).map_err(|e| FromJsonFileError {
+                path: path.into(),
+                source: FromJsonFileErrorKind::Parse(e),
+            })?;
+        renv_lock.add_bioconductor_repositories();
+        Ok(renv_lock)
+    }
+
+    /// Reads a packrat.lock, the lockfile of packrat which came before renv, so packrat projects
+    /// can be imported like renv ones.
+    /// The `Source` of a package is either `github`, `Bioconductor` or the name of one of the
+    /// `Repos` of the file.
+    pub fn parse_packrat_lock<P: AsRef<Path>>(path: P) -> Result<Self, PackratLockError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|source| PackratLockError::Io {
             path: path.into(),
-            source: FromJsonFileErrorKind::Parse(e),
-        })
+            source,
+        })?;
+        let invalid = |message: String| PackratLockError::Invalid {
+            path: path.into(),
+            message,
+        };
+
+        let mut records = parse_dcf_records(&content).into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| invalid("the file is empty".to_string()))?;
+        let field = |name: &str| {
+            header
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let version = field("RVersion").ok_or_else(|| invalid("`RVersion` not found".into()))?;
+        let version = version
+            .parse::<Version>()
+            .map_err(|_| invalid(format!("`{version}` is not a valid R version")))?;
+        let repositories = field("Repos")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|r| !r.trim().is_empty())
+            .map(|r| {
+                let (name, url) = r
+                    .trim()
+                    .split_once('=')
+                    .ok_or_else(|| invalid(format!("`{r}` is not a repository like NAME=URL")))?;
+                Ok(RenvRepository {
+                    name: name.trim().to_string(),
+                    url: url.trim().to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut packages = BTreeMap::new();
+        for record in records {
+            let package = packrat_package(&record, &repositories).map_err(invalid)?;
+            packages.insert(package.package.clone(), package);
+        }
+
+        let mut renv_lock = Self {
+            r: RInfo {
+                version,
+                repositories,
+            },
+            bioconductor: None,
+            packages,
+        };
+        renv_lock.add_bioconductor_repositories();
+        Ok(renv_lock)
+    }
+
+    /// renv doesn't list the Bioconductor repositories, only the Bioconductor release used.
+    /// We add its repositories if there are Bioconductor packages so they can be found.
+    fn add_bioconductor_repositories(&mut self) {
+        let uses_bioc = self
+            .packages
+            .values()
+            .any(|p| p.source == RenvSource::Bioconductor);
+        if !uses_bioc
+            || self
+                .r
+                .repositories
+                .iter()
+                .any(|r| r.name == BIOC_SOFTWARE_ALIAS)
+        {
+            return;
+        }
+
+        let release = self
+            .bioconductor
+            .as_ref()
+            .and_then(|b| {
+                let (major, minor) = b.version.split_once('.')?;
+                Some(BiocRepository {
+                    release: [major.parse().ok()?, minor.parse().ok()?],
+                })
+            })
+            .or_else(|| BiocRepository::from_r_version(&self.r.version.major_minor()));
+        if let Some(release) = release {
+            self.r
+                .repositories
+                .extend(release.repositories().into_iter().map(|r| RenvRepository {
+                    name: r.alias.clone(),
+                    url: r.url().to_string(),
+                }));
+        }
     }
 
     pub fn resolve(
@@ -125,7 +261,7 @@ impl RenvLock {
             }
 
             let res = match &package_info.source {
-                RenvSource::Repository => resolve_repository(
+                RenvSource::Repository | RenvSource::Bioconductor => resolve_repository(
                     package_info,
                     &self.r.repositories,
                     repository_database,
@@ -155,6 +291,237 @@ impl RenvLock {
         (resolved, unresolved)
     }
 
+    /// Maps the packages to their source as recorded in the renv.lock, without checking that the
+    /// repositories have them, and builds the matching rv lockfile.
+    /// Base and recommended packages are locked as builtin packages.
+    pub fn import(&self) -> (Vec<ResolvedRenv<'_>>, Vec<UnresolvedRenv>, Lockfile) {
+        let mut resolved = Vec::new();
+        let mut unresolved = Vec::new();
+        let mut packages = Vec::new();
+        let r_version = &self.r.version.original;
+
+        for package_info in self.packages.values() {
+            let name = package_info.package.as_str();
+            if package_info.source == RenvSource::Repository && RECOMMENDED_PACKAGES.contains(&name)
+            {
+                packages.push(builtin_package(name, &package_info.version.original));
+                continue;
+            }
+
+            let res = match &package_info.source {
+                RenvSource::Repository | RenvSource::Bioconductor => {
+                    self.find_repository(package_info)
+                }
+                RenvSource::GitHub => resolve_github(package_info),
+                RenvSource::Local => resolve_local(package_info),
+                RenvSource::Other(source) => {
+                    Err(format!("Source ({source}) is not supported").into())
+                }
+            };
+            let source = match res.and_then(|s| s.as_locked_source().map(|l| (s, l))) {
+                Ok((source, locked_source)) => {
+                    packages.push(LockedPackage {
+                        name: name.to_string(),
+                        version: package_info.version.original.clone(),
+                        source: locked_source,
+                        path: None,
+                        force_source: false,
+                        dependencies: package_info
+                            .requirements
+                            .iter()
+                            .filter(|r| *r != "R")
+                            .map(|r| Dependency::Simple(r.to_string()))
+                            .collect(),
+                        suggests: Vec::new(),
+                    });
+                    source
+                }
+                Err(error) => {
+                    unresolved.push(UnresolvedRenv {
+                        package_info: package_info.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            resolved.push(ResolvedRenv {
+                package_info,
+                source,
+            });
+        }
+
+        // The base packages are only in the requirements
+        let mut base_packages: Vec<_> = self
+            .packages
+            .values()
+            .flat_map(|p| &p.requirements)
+            .filter(|r| BASE_PACKAGES.contains(&r.as_str()))
+            .collect();
+        base_packages.sort();
+        base_packages.dedup();
+        packages.extend(
+            base_packages
+                .into_iter()
+                .map(|p| builtin_package(p, r_version)),
+        );
+
+        resolved.sort_by_key(|a| &a.package_info.package);
+        unresolved.sort_by_key(|a| a.package_info.package.clone());
+        let [major, minor] = self.r.version.major_minor();
+        let lockfile = Lockfile::from_packages(&format!("{major}.{minor}"), packages);
+        (resolved, unresolved, lockfile)
+    }
+
+    /// The repository the renv.lock says the package comes from
+    fn find_repository<'a>(
+        &'a self,
+        pkg_info: &'a PackageInfo,
+    ) -> Result<Source<'a>, Box<dyn Error>> {
+        if let (Some(git), Some(sha)) = (&pkg_info.remote_url, &pkg_info.remote_sha) {
+            return Ok(Source::Git {
+                git: git.to_string(),
+                sha,
+                directory: pkg_info.remote_subdir.as_deref(),
+            });
+        }
+        let name = match pkg_info.source {
+            RenvSource::Bioconductor => BIOC_SOFTWARE_ALIAS,
+            _ => pkg_info
+                .repository
+                .as_deref()
+                .ok_or("Repository not found")?,
+        };
+        self.r
+            .repositories
+            .iter()
+            .find(|r| r.name == name)
+            .map(Source::Repository)
+            .ok_or_else(|| format!("Repository `{name}` is not in the renv.lock").into())
+    }
+
+    /// The renv.lock of a rv lockfile, the opposite of `import`, so the project can also be used
+    /// with renv. `r_version` is the R version used, renv records the patch.
+    /// Base packages are left out like renv does and recommended packages are recorded as coming
+    /// from the first repository. Bioconductor repositories are not listed, only their release.
+    pub fn from_lockfile(
+        lockfile: &Lockfile,
+        repositories: &[Repository],
+        r_version: &Version,
+    ) -> Self {
+        let mut bioc_release = None;
+        let mut renv_repositories = Vec::new();
+        for repository in repositories {
+            match BiocRepository::from_url(repository.url()) {
+                Some(bioc) => {
+                    bioc_release.get_or_insert(bioc.release);
+                }
+                None => renv_repositories.push(RenvRepository {
+                    name: repository.alias.clone(),
+                    url: repository.url().to_string(),
+                }),
+            }
+        }
+
+        let mut packages = BTreeMap::new();
+        for package in lockfile.packages() {
+            if BASE_PACKAGES.contains(&package.name.as_str()) {
+                continue;
+            }
+            let mut info = PackageInfo {
+                package: package.name.clone(),
+                version: package.version.parse().expect("valid version"),
+                source: RenvSource::Repository,
+                repository: None,
+                remote_type: None,
+                remote_host: None,
+                remote_repo: None,
+                remote_username: None,
+                remote_sha: None,
+                remote_subdir: None,
+                remote_url: None,
+                requirements: package
+                    .dependencies
+                    .iter()
+                    .map(|d| d.name().to_string())
+                    .collect(),
+                hash: None,
+            };
+            match &package.source {
+                LockedSource::Builtin { .. } => {
+                    info.repository = renv_repositories.first().map(|r| r.name.clone());
+                }
+                LockedSource::Repository { repository } => {
+                    if let Some(bioc) = BiocRepository::from_url(repository.as_str()) {
+                        bioc_release.get_or_insert(bioc.release);
+                        info.source = RenvSource::Bioconductor;
+                    } else {
+                        info.repository =
+                            Some(repository_name(&mut renv_repositories, repository.as_str()));
+                    }
+                }
+                LockedSource::RUniverse {
+                    repository,
+                    git,
+                    sha,
+                    directory,
+                } => {
+                    // How renv records packages from R-Universe, see `resolve_repository`
+                    info.repository =
+                        Some(repository_name(&mut renv_repositories, repository.as_str()));
+                    info.remote_url = Some(git.url().to_string());
+                    info.remote_sha = Some(sha.clone());
+                    info.remote_subdir = directory.clone();
+                }
+                LockedSource::Git {
+                    git,
+                    sha,
+                    directory,
+                    ..
+                } => {
+                    if let Some((username, repo)) = github_repo(git.url()) {
+                        info.source = RenvSource::GitHub;
+                        info.remote_type = Some("github".to_string());
+                        info.remote_host = Some("api.github.com".to_string());
+                        info.remote_username = Some(username.to_string());
+                        info.remote_repo = Some(repo.to_string());
+                    } else {
+                        info.source = RenvSource::Other("git".to_string());
+                        info.remote_type = Some("git".to_string());
+                        info.remote_url = Some(git.url().to_string());
+                    }
+                    info.remote_sha = Some(sha.clone());
+                    info.remote_subdir = directory.clone();
+                }
+                LockedSource::Url { url, .. } => {
+                    info.source = RenvSource::Other("URL".to_string());
+                    info.remote_type = Some("url".to_string());
+                    info.remote_url = Some(url.to_string());
+                }
+                LockedSource::Local { path, .. } => {
+                    info.source = RenvSource::Local;
+                    info.remote_type = Some("local".to_string());
+                    info.remote_url = Some(path.display().to_string());
+                }
+            }
+            packages.insert(info.package.clone(), info);
+        }
+
+        Self {
+            r: RInfo {
+                version: r_version.clone(),
+                repositories: renv_repositories,
+            },
+            bioconductor: bioc_release.map(|[major, minor]| BiocInfo {
+                version: format!("{major}.{minor}"),
+            }),
+            packages,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("valid json")
+    }
+
     pub fn r_version(&self) -> &Version {
         &self.r.version
     }
@@ -352,7 +719,7 @@ impl fmt::Display for ResolvedRenv<'_> {
                     r#"{{ name = "{name}", git = "{git}", commit = "{sha}"{} }}"#,
                     directory
                         .as_ref()
-                        .map(|d| format!(", directory = {d}"))
+                        .map(|d| format!(r#", directory = "{d}""#))
                         .unwrap_or_default()
                 )
             }
@@ -374,6 +741,119 @@ enum Source<'a> {
     Local(PathBuf),
 }
 
+impl Source<'_> {
+    fn as_locked_source(&self) -> Result<LockedSource, Box<dyn Error>> {
+        Ok(match self {
+            Source::Repository(r) => LockedSource::Repository {
+                repository: Url::parse(&r.url)?,
+            },
+            Source::Git {
+                git,
+                sha,
+                directory,
+            } => LockedSource::Git {
+                git: GitUrl::try_from(git.as_str())?,
+                sha: sha.to_string(),
+                directory: directory.map(|d| d.to_string()),
+                tag: None,
+                branch: None,
+            },
+            Source::Local(path) => LockedSource::Local {
+                path: path.clone(),
+                sha: None,
+            },
+        })
+    }
+}
+
+/// A package record of a packrat.lock, as the renv.lock would have it
+fn packrat_package(
+    record: &[(String, String)],
+    repositories: &[RenvRepository],
+) -> Result<PackageInfo, String> {
+    let field = |name: &str| {
+        record
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    let package = field("Package").ok_or("a package has no `Package` field")?;
+    let version = field("Version").ok_or_else(|| format!("`{package}` has no `Version`"))?;
+    let version = version
+        .parse()
+        .map_err(|_| format!("`{package}` has an invalid version: `{version}`"))?;
+    let source = field("Source").ok_or_else(|| format!("`{package}` has no `Source`"))?;
+
+    let (source, repository, remote_host) = match source.as_str() {
+        "github" => (RenvSource::GitHub, None, Some("api.github.com".to_string())),
+        "Bioconductor" => (RenvSource::Bioconductor, None, None),
+        s if repositories.iter().any(|r| r.name == s) => {
+            (RenvSource::Repository, Some(source.clone()), None)
+        }
+        _ => (RenvSource::Other(source), None, None),
+    };
+
+    Ok(PackageInfo {
+        package,
+        version,
+        source,
+        repository,
+        remote_type: None,
+        remote_host,
+        remote_repo: field("GithubRepo"),
+        remote_username: field("GithubUsername"),
+        remote_sha: field("GithubSha1"),
+        remote_subdir: field("GithubSubdir"),
+        remote_url: None,
+        requirements: field("Requires")
+            .map(|r| {
+                r.split(',')
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        hash: field("Hash"),
+    })
+}
+
+/// The name of the repository with that URL, adding it named after its URL if it's not there.
+/// renv does the same when a package comes from a repository that is not configured.
+fn repository_name(repositories: &mut Vec<RenvRepository>, url: &str) -> String {
+    if let Some(r) = repositories
+        .iter()
+        .find(|r| r.url.trim_end_matches('/') == url.trim_end_matches('/'))
+    {
+        return r.name.clone();
+    }
+    repositories.push(RenvRepository {
+        name: url.to_string(),
+        url: url.to_string(),
+    });
+    url.to_string()
+}
+
+/// The username and repository of a GitHub git URL
+fn github_repo(url: &str) -> Option<(&str, &str)> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("git@github.com:"))?;
+    let (username, repo) = path.trim_end_matches('/').split_once('/')?;
+    Some((username, repo.trim_end_matches(".git")))
+}
+
+fn builtin_package(name: &str, version: &str) -> LockedPackage {
+    LockedPackage {
+        name: name.to_string(),
+        version: version.to_string(),
+        source: LockedSource::Builtin { builtin: true },
+        path: None,
+        force_source: false,
+        dependencies: Vec::new(),
+        suggests: Vec::new(),
+    }
+}
+
 pub struct UnresolvedRenv {
     package_info: PackageInfo,
     error: Box<dyn Error>,
@@ -389,6 +869,63 @@ impl fmt::Display for UnresolvedRenv {
     }
 }
 
+/// Reads only the R version of a renv.lock, eg to check it against the one used by rv.
+/// Unlike `RenvLock::parse_renv_lock`, this doesn't care whether the packages can be parsed.
+pub fn read_renv_lock_r_version(
+    renv_lock: impl AsRef<Path>,
+) -> Result<RVersion, RenvRVersionError> {
+    let path = renv_lock.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|source| RenvRVersionError::Io {
+        path: path.into(),
+        source,
+    })?;
+    let value: serde_json::Value =
+        serde_json::from_str(&content).map_err(|source| RenvRVersionError::Json {
+            path: path.into(),
+            source,
+        })?;
+    let version = value
+        .get("R")
+        .and_then(|r| r.get("Version"))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| RenvRVersionError::MissingVersion { path: path.into() })?;
+    version
+        .parse()
+        .map_err(|message| RenvRVersionError::InvalidVersion {
+            path: path.into(),
+            message,
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RenvRVersionError {
+    #[error("Failed to read `{path}`: {source}")]
+    Io {
+        path: Box<Path>,
+        source: std::io::Error,
+    },
+    #[error("`{path}` is not valid JSON, is it a renv.lock file? {source}")]
+    Json {
+        path: Box<Path>,
+        source: serde_json::Error,
+    },
+    #[error("`{path}` has no `R.Version` field, renv records it in the `R` section of the file")]
+    MissingVersion { path: Box<Path> },
+    #[error("Invalid `R.Version` in `{path}`: {message}")]
+    InvalidVersion { path: Box<Path>, message: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PackratLockError {
+    #[error("Failed to read `{path}`: {source}")]
+    Io {
+        path: Box<Path>,
+        source: std::io::Error,
+    },
+    #[error("Invalid packrat.lock `{path}`: {message}")]
+    Invalid { path: Box<Path>, message: String },
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error(transparent)]
 pub enum FromJsonFileErrorKind {
@@ -406,9 +943,10 @@ pub struct FromJsonFileError {
 
 #[cfg(test)]
 mod tests {
+    use crate::consts::BASE_PACKAGES;
     use crate::{Repository, RepositoryDatabase, Version};
 
-    use super::RenvLock;
+    use super::{RenvLock, RenvRVersionError, read_renv_lock_r_version};
 
     fn repository_databases(
         r_version: &Version,
@@ -450,4 +988,118 @@ mod tests {
 
         insta::assert_snapshot!("renv_resolver".to_string(), out);
     }
+
+    #[test]
+    fn can_import_renv_lock() {
+        let renv_lock = RenvLock::parse_renv_lock("src/tests/renv/import/renv.lock").unwrap();
+        let (resolved, unresolved, lockfile) = renv_lock.import();
+        assert!(unresolved.is_empty());
+
+        // The lockfile is complete and can be read back
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("rv.lock");
+        lockfile.save(&path).unwrap();
+        assert_eq!(
+            crate::Lockfile::load(&path).unwrap(),
+            Some(lockfile.clone())
+        );
+
+        let mut out = String::new();
+        for r in resolved {
+            out.push_str(&format!("{r}\n"));
+        }
+        out.push_str("--- lockfile ---\n");
+        out.push_str(&lockfile.as_toml_string());
+        insta::assert_snapshot!("renv_import".to_string(), out);
+    }
+
+    #[test]
+    fn can_export_and_import_again_renv_lock() {
+        let renv_lock = RenvLock::parse_renv_lock("src/tests/renv/import/renv.lock").unwrap();
+        let (_, _, lockfile) = renv_lock.import();
+        let exported = RenvLock::from_lockfile(
+            &lockfile,
+            &renv_lock.config_repositories(),
+            renv_lock.r_version(),
+        );
+        let json = exported.to_json();
+        insta::assert_snapshot!("renv_export".to_string(), json);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("renv.lock");
+        std::fs::write(&path, &json).unwrap();
+        let reimported = RenvLock::parse_renv_lock(&path).unwrap();
+        assert_eq!(reimported.r_version(), renv_lock.r_version());
+        let (_, unresolved, reimported_lockfile) = reimported.import();
+        assert!(unresolved.is_empty());
+        // Base packages are only listed when something requires them and the requirements of
+        // the builtin recommended packages are not known, we only get some of them back
+        let packages = |l: &crate::Lockfile| {
+            l.packages()
+                .iter()
+                .filter(|p| !BASE_PACKAGES.contains(&p.name.as_str()))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(packages(&reimported_lockfile), packages(&lockfile));
+        assert_eq!(packages(&lockfile).len(), 7);
+    }
+
+    #[test]
+    fn can_read_renv_lock_r_version() {
+        let version = read_renv_lock_r_version("src/tests/renv/import/renv.lock").unwrap();
+        assert_eq!(version, crate::RVersion::new(4, 3, 2));
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("renv.lock");
+        assert!(matches!(
+            read_renv_lock_r_version(&path),
+            Err(RenvRVersionError::Io { .. })
+        ));
+        for (content, expected) in [
+            ("not json", "is not valid JSON"),
+            (r#"{"Packages": {}}"#, "has no `R.Version` field"),
+            (r#"{"R": {"Repositories": []}}"#, "has no `R.Version` field"),
+            (r#"{"R": {"Version": "four"}}"#, "Invalid `R.Version`"),
+        ] {
+            std::fs::write(&path, content).unwrap();
+            let err = read_renv_lock_r_version(&path).unwrap_err().to_string();
+            assert!(err.contains(expected), "{content}: {err}");
+        }
+    }
+
+    #[test]
+    fn can_import_packrat_lock() {
+        let packrat_lock = RenvLock::parse_packrat_lock("src/tests/packrat/packrat.lock").unwrap();
+        assert_eq!(packrat_lock.r_version().original, "4.3.2");
+        let (resolved, unresolved, lockfile) = packrat_lock.import();
+        assert!(unresolved.is_empty());
+
+        let mut out = String::new();
+        for r in resolved {
+            out.push_str(&format!("{r}\n"));
+        }
+        out.push_str("--- lockfile ---\n");
+        out.push_str(&lockfile.as_toml_string());
+        insta::assert_snapshot!("packrat_import".to_string(), out);
+    }
+
+    #[test]
+    fn errors_on_invalid_packrat_lock() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("packrat.lock");
+        for (content, expected) in [
+            ("", "the file is empty"),
+            ("PackratFormat: 1.4\n", "`RVersion` not found"),
+            ("RVersion: 4.3.2\nRepos: CRAN\n", "is not a repository"),
+            (
+                "RVersion: 4.3.2\n\nPackage: R6\nSource: CRAN\n",
+                "`R6` has no `Version`",
+            ),
+        ] {
+            std::fs::write(&path, content).unwrap();
+            let err = RenvLock::parse_packrat_lock(&path).unwrap_err().to_string();
+            assert!(err.contains(expected), "{content}: {err}");
+        }
+    }
 }
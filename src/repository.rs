@@ -6,7 +6,9 @@ use std::path::Path;
 
 use crate::consts::RECOMMENDED_PACKAGES;
 use crate::git::url::GitUrl;
-use crate::package::{Dependency, Package, PackageType, deserialize_version, parse_package_file};
+use crate::package::{
+    BuildPreference, Dependency, Package, PackageType, deserialize_version, parse_package_file,
+};
 use crate::package::{Version, VersionRequirement, parse_remote};
 
 #[derive(Debug, Default, PartialEq, Clone, Decode, Encode)]
@@ -66,13 +68,14 @@ impl RepositoryDatabase {
             .collect();
     }
 
-    // We always prefer binary unless `force_source` is set to true
+    // Binaries are looked up in the database matching the R major.minor version, which is
+    // fetched for the current platform. `PreferBinary` falls back to source if none is found.
     pub(crate) fn find_package<'a>(
         &'a self,
         name: &str,
         version_requirement: Option<&VersionRequirement>,
         r_version: &Version,
-        force_source: bool,
+        preference: impl Into<BuildPreference>,
     ) -> Option<(&'a Package, PackageType)> {
         let find_package = |db: &'a HashMap<String, Vec<Package>>| -> Option<&'a Package> {
             // If we find multiple packages matching the requirement, we grab the one with the
@@ -114,7 +117,8 @@ impl RepositoryDatabase {
             })
         };
 
-        if !force_source {
+        let preference = preference.into();
+        if preference != BuildPreference::Source {
             if let Some(db) = self.binary_packages.get(&r_version.major_minor()) {
                 if let Some(package) = find_package(db) {
                     return Some((package, PackageType::Binary));
@@ -122,9 +126,27 @@ impl RepositoryDatabase {
             }
         }
 
+        if preference == BuildPreference::Binary {
+            return None;
+        }
+
         find_package(&self.source_packages).map(|p| (p, PackageType::Source))
     }
 
+    /// The highest version of the package available for that R version, binary or source
+    #[cfg(feature = "cli")]
+    pub(crate) fn latest_version(&self, name: &str, r_version: &Version) -> Option<&Version> {
+        self.binary_packages
+            .get(&r_version.major_minor())
+            .and_then(|db| db.get(name))
+            .into_iter()
+            .chain(self.source_packages.get(name))
+            .flatten()
+            .filter(|p| p.works_with_r_version(r_version))
+            .map(|p| &p.version)
+            .max()
+    }
+
     pub(crate) fn get_binary_count(&self, r_version: &[u32; 2]) -> usize {
         self.binary_packages
             .get(r_version)
@@ -289,8 +311,38 @@ pub enum RepositoryDatabaseErrorKind {
 #[cfg(test)]
 mod test {
     use std::fs;
+    use std::str::FromStr;
+
+    use crate::package::{BuildPreference, PackageType};
+    use crate::{RepositoryDatabase, Version};
 
-    use crate::RepositoryDatabase;
+    #[test]
+    fn find_package_falls_back_to_source_when_binary_is_missing() {
+        let mut repo_db = RepositoryDatabase::new("http://example.com");
+        repo_db.parse_source("Package: A3\nVersion: 1.0.0\n\nPackage: BRugs\nVersion: 0.9-2\n");
+        repo_db.parse_binary("Package: A3\nVersion: 1.0.0\n", [4, 4]);
+        let r_version = Version::from_str("4.4.1").unwrap();
+
+        let find = |name, preference| {
+            repo_db
+                .find_package(name, None, &r_version, preference)
+                .map(|(p, t)| (p.name.as_str(), t))
+        };
+
+        assert_eq!(
+            find("A3", BuildPreference::PreferBinary),
+            Some(("A3", PackageType::Binary))
+        );
+        assert_eq!(
+            find("A3", BuildPreference::Source),
+            Some(("A3", PackageType::Source))
+        );
+        assert_eq!(
+            find("BRugs", BuildPreference::PreferBinary),
+            Some(("BRugs", PackageType::Source))
+        );
+        assert_eq!(find("BRugs", BuildPreference::Binary), None);
+    }
 
     #[test]
     fn test_r_universe_api_parse() {
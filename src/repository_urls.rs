@@ -2,7 +2,7 @@ use std::error::Error;
 
 use crate::consts::PACKAGE_FILENAME;
 use crate::lockfile::Source;
-use crate::{OsType, ResolvedDependency, SystemInfo};
+use crate::{OsType, Repository, ResolvedDependency, SystemInfo};
 use url::Url;
 
 /// This is based on the mapping on PPM config <https://packagemanager.posit.co/client/#/repos/cran/setup>.
@@ -267,6 +267,77 @@ pub fn get_package_file_urls(
     )
 }
 
+const BIOC_BASE_URL: &str = "https://bioconductor.org/packages";
+
+/// The sub-repositories of a Bioconductor release, with the aliases BiocManager uses
+const BIOC_REPOSITORIES: [(&str, &str); 4] = [
+    ("BioCsoft", "bioc"),
+    ("BioCann", "data/annotation"),
+    ("BioCexp", "data/experiment"),
+    ("BioCworkflows", "workflows"),
+];
+
+/// A Bioconductor release. Each release only supports a single R minor version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BiocRepository {
+    pub release: [u32; 2],
+}
+
+impl BiocRepository {
+    /// The latest Bioconductor release for the given R version, if there is one.
+    /// See <https://bioconductor.org/about/release-announcements/>
+    pub fn from_r_version(r_version: &[u32; 2]) -> Option<Self> {
+        let release = match r_version {
+            [3, 5] => [3, 8],
+            [3, 6] => [3, 10],
+            [4, 0] => [3, 12],
+            [4, 1] => [3, 14],
+            [4, 2] => [3, 16],
+            [4, 3] => [3, 18],
+            [4, 4] => [3, 20],
+            [4, 5] => [3, 22],
+            _ => return None,
+        };
+        Some(Self { release })
+    }
+
+    /// The release of one of the Bioconductor repositories, eg
+    /// `https://bioconductor.org/packages/3.18/bioc`
+    pub fn from_url(url: &str) -> Option<Self> {
+        let rest = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .strip_prefix(BIOC_BASE_URL.trim_start_matches("https://"))?;
+        let (major, minor) = rest
+            .trim_start_matches('/')
+            .split('/')
+            .next()?
+            .split_once('.')?;
+        Some(Self {
+            release: [major.parse().ok()?, minor.parse().ok()?],
+        })
+    }
+
+    /// The CRAN-like repositories of that release: software, annotation data, experiment data
+    /// and workflows. Their PACKAGES files are found like any other repository.
+    pub fn repositories(&self) -> Vec<Repository> {
+        BIOC_REPOSITORIES
+            .iter()
+            .map(|(alias, path)| {
+                let url = format!(
+                    "{BIOC_BASE_URL}/{}.{}/{path}",
+                    self.release[0], self.release[1]
+                );
+                Repository::new(
+                    alias.to_string(),
+                    Url::parse(&url).expect("valid url"),
+                    false,
+                )
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::LazyLock;
@@ -378,4 +449,50 @@ mod tests {
         );
         assert_eq!(source_url.as_str(), ref_url);
     }
+
+    #[test]
+    fn test_bioc_repositories() {
+        assert_eq!(
+            BiocRepository::from_r_version(&[4, 3]).unwrap().release,
+            [3, 18]
+        );
+        assert_eq!(BiocRepository::from_r_version(&[3, 4]), None);
+        assert_eq!(
+            BiocRepository::from_url("https://bioconductor.org/packages/3.18/data/annotation")
+                .unwrap()
+                .release,
+            [3, 18]
+        );
+        assert_eq!(
+            BiocRepository::from_url("https://cran.r-project.org/packages/3.18/bioc"),
+            None
+        );
+
+        let sysinfo = SystemInfo::new(
+            OsType::Linux("ubuntu"),
+            Some("x86_64".to_string()),
+            Some("jammy".to_string()),
+            "22.04",
+        );
+        let repositories = BiocRepository::from_r_version(&[4, 3])
+            .unwrap()
+            .repositories();
+        let urls: Vec<_> = repositories
+            .iter()
+            .map(|r| {
+                let url = Url::parse(r.url()).unwrap();
+                get_package_file_urls(&url, &[4, 3], &sysinfo).0.to_string()
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://bioconductor.org/packages/3.18/bioc/src/contrib/PACKAGES",
+                "https://bioconductor.org/packages/3.18/data/annotation/src/contrib/PACKAGES",
+                "https://bioconductor.org/packages/3.18/data/experiment/src/contrib/PACKAGES",
+                "https://bioconductor.org/packages/3.18/workflows/src/contrib/PACKAGES",
+            ]
+        );
+        assert_eq!(repositories[0].alias, "BioCsoft");
+    }
 }
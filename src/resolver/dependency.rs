@@ -299,7 +299,7 @@ impl fmt::Debug for ResolvedDependency<'_> {
 /// A dependency that we could not
 #[derive(Debug, PartialEq, Clone)]
 pub struct UnresolvedDependency<'d> {
-    pub(crate) name: Cow<'d, str>,
+    pub name: Cow<'d, str>,
     pub(crate) error: Option<String>,
     pub(crate) version_requirement: Option<Cow<'d, VersionRequirement>>,
     // The first parent we encountered requiring that package
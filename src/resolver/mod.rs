@@ -12,7 +12,8 @@ mod dependency;
 mod result;
 mod sat;
 
-use crate::fs::untar_archive;
+use crate::cache::MissingFromCache;
+use crate::fs::{HashAlgorithm, UntarOptions, untar_archive};
 use crate::git::url::GitUrl;
 use crate::git::{GitReference, GitRemote};
 use crate::http::HttpDownload;
@@ -23,7 +24,7 @@ use crate::package::{
 };
 use crate::utils::create_spinner;
 pub use dependency::{ResolvedDependency, UnresolvedDependency};
-pub use result::Resolution;
+pub use result::{DependencyCycle, Resolution};
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub(crate) struct QueueItem<'d> {
@@ -108,6 +109,8 @@ pub struct Resolver<'d> {
     lockfile: Option<&'d Lockfile>,
     /// Progress bar is only shown for git dependencies
     show_progress_bar: bool,
+    /// Only use what is in the cache and the lockfile, without any network access
+    offline: bool,
 }
 
 impl<'d> Resolver<'d> {
@@ -129,9 +132,14 @@ impl<'d> Resolver<'d> {
             builtin_packages,
             packages_env_vars,
             show_progress_bar: false,
+            offline: false,
         }
     }
 
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
     pub fn show_progress_bar(&mut self) {
         self.show_progress_bar = true;
     }
@@ -150,8 +158,14 @@ impl<'d> Resolver<'d> {
             // We have a file, it should be a tarball.
             // even though we might have to extract again in sync?
             let tempdir = tempfile::tempdir()?;
-            let (path, hash) =
-                untar_archive(fs::read(&canon_path)?.as_slice(), tempdir.path(), true)?;
+            let (path, hash) = untar_archive(
+                fs::File::open(&canon_path)?,
+                tempdir.path(),
+                UntarOptions {
+                    hash: Some(HashAlgorithm::Sha256),
+                    ..Default::default()
+                },
+            )?;
             (
                 parse_description_file_in_folder(path.unwrap_or_else(|| canon_path.clone()))?,
                 hash,
@@ -202,8 +216,8 @@ impl<'d> Resolver<'d> {
             .and_then(|l| l.get_package(&item.name, item.dep))
         {
             // For some type of packages we will always refresh directly from the source
-            // eg a branch might have added commits
-            if package.source.could_have_changed() {
+            // eg a branch might have added commits, unless we can't reach it
+            if package.source.could_have_changed() && !self.offline {
                 return None;
             }
 
@@ -291,6 +305,13 @@ impl<'d> Resolver<'d> {
         git_executor: &'d (impl CommandExecutor + Clone + 'static),
         cache: &'d DiskCache,
     ) -> Result<(ResolvedDependency<'d>, Vec<QueueItem<'d>>), Box<dyn std::error::Error>> {
+        if self.offline {
+            return Err(MissingFromCache {
+                package: item.name.to_string(),
+                version: None,
+            }
+            .into());
+        }
         let clone_path = cache.get_git_clone_path(repo_url.url());
 
         let mut remote = GitRemote::new(repo_url.url());
@@ -363,6 +384,13 @@ impl<'d> Resolver<'d> {
         cache: &'d DiskCache,
         http_downloader: &'d impl HttpDownload,
     ) -> Result<(ResolvedDependency<'d>, Vec<QueueItem<'d>>), Box<dyn std::error::Error>> {
+        if self.offline {
+            return Err(MissingFromCache {
+                package: item.name.to_string(),
+                version: None,
+            }
+            .into());
+        }
         let out_path = cache.get_url_download_path(url);
         let (dir, sha) = http_downloader.download_and_untar(url, &out_path, true)?;
 
@@ -447,7 +475,7 @@ impl<'d> Resolver<'d> {
             .map(|d| QueueItem {
                 name: Cow::Borrowed(d.name()),
                 dep: Some(d),
-                version_requirement: None,
+                version_requirement: d.version_requirement().map(Cow::Borrowed),
                 install_suggestions: d.install_suggestions(),
                 force_source: d.force_source(),
                 parent: None,
@@ -569,7 +597,7 @@ impl<'d> Resolver<'d> {
                             Err(e) => {
                                 result.failed.push(
                                     UnresolvedDependency::from_item(&item)
-                                        .with_error(format!("{e:?}"))
+                                        .with_error(lookup_error(e.as_ref()))
                                         .with_remote(remote.clone()),
                                 );
                             }
@@ -620,7 +648,7 @@ impl<'d> Resolver<'d> {
                         Err(e) => {
                             result.failed.push(
                                 UnresolvedDependency::from_item(&item)
-                                    .with_error(format!("{e:?}"))
+                                    .with_error(lookup_error(e.as_ref()))
                                     .with_url(url.as_str()),
                             );
                         }
@@ -659,7 +687,8 @@ impl<'d> Resolver<'d> {
                         }
                         Err(e) => {
                             result.failed.push(
-                                UnresolvedDependency::from_item(&item).with_error(format!("{e:?}")),
+                                UnresolvedDependency::from_item(&item)
+                                    .with_error(lookup_error(e.as_ref())),
                             );
                         }
                     }
@@ -677,11 +706,23 @@ impl<'d> Resolver<'d> {
             }
         }
         result.finalize();
+        if self.offline {
+            result.fail_missing_from_cache();
+        }
 
         result
     }
 }
 
+/// Running offline is reported like for the packages from repositories, other errors are
+/// shown with their details
+fn lookup_error(e: &(dyn std::error::Error + 'static)) -> String {
+    match e.downcast_ref::<MissingFromCache>() {
+        Some(e) => e.to_string(),
+        None => format!("{e:?}"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -921,8 +962,103 @@ mod tests {
                     out.push_str("\n");
                 }
             }
+            if !resolution.cycles.is_empty() {
+                out.push_str("--- dependency cycles --- \n");
+                for cycle in resolution.cycles {
+                    out.push_str(&cycle.to_string());
+                    out.push('\n');
+                }
+            }
             // Output has been compared with pkgr for the same PACKAGE file
             insta::assert_snapshot!(p.file_name().unwrap().to_string_lossy().to_string(), out);
         }
     }
+
+    /// Any network access would panic
+    #[derive(Clone)]
+    struct NoNetwork;
+
+    impl CommandExecutor for NoNetwork {
+        fn execute(&self, _: &mut Command) -> Result<String, std::io::Error> {
+            panic!("git was called offline")
+        }
+    }
+
+    impl HttpDownload for NoNetwork {
+        fn download<W: Write>(
+            &self,
+            url: &Url,
+            _: &mut W,
+            _: Vec<(&str, String)>,
+        ) -> Result<u64, HttpError> {
+            panic!("{url} was downloaded offline")
+        }
+
+        fn download_and_untar(
+            &self,
+            url: &Url,
+            _: impl AsRef<Path>,
+            _: bool,
+        ) -> Result<(Option<PathBuf>, String), HttpError> {
+            panic!("{url} was downloaded offline")
+        }
+    }
+
+    #[test]
+    fn resolving_offline_only_uses_cache() {
+        let config = Config::from_str(
+            r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = []
+dependencies = [
+    "R6",
+    "jsonlite",
+    { name = "dplyr", url = "https://cran.r-project.org/src/contrib/Archive/dplyr/dplyr_1.1.3.tar.gz" },
+]
+"#,
+        )
+        .unwrap();
+        let r_version = config.r_version().clone();
+        let mut repo = RepositoryDatabase::new("http://cran/");
+        repo.parse_source("Package: R6\nVersion: 2.5.1\n\nPackage: jsonlite\nVersion: 2.0.0\n");
+        let repositories = vec![(repo, false)];
+        let (_cache_dir, cache) = setup_cache(&r_version);
+        let source = Source::Repository {
+            repository: Url::parse("http://cran/").unwrap(),
+        };
+        let paths = cache.get_package_paths(&source, Some("R6"), Some("2.5.1"));
+        fs::create_dir_all(paths.binary.join("R6")).unwrap();
+
+        let builtin_packages = HashMap::new();
+        let mut resolver = Resolver::new(
+            Path::new("."),
+            &repositories,
+            repositories.iter().map(|(x, _)| x.url.as_str()).collect(),
+            &r_version,
+            &builtin_packages,
+            None,
+            config.packages_env_vars(),
+        );
+        resolver.set_offline(true);
+        let resolution = resolver.resolve(
+            config.dependencies(),
+            config.prefer_repositories_for(),
+            &cache,
+            &NoNetwork,
+            &NoNetwork,
+        );
+
+        let mut failed: Vec<_> = resolution.failed.iter().map(|d| d.to_string()).collect();
+        failed.sort();
+        assert_eq!(
+            failed,
+            vec![
+                "dplyr [listed in rproject.toml]: dplyr is not in the cache and can't be downloaded offline",
+                "jsonlite [listed in rproject.toml]: jsonlite (2.0.0) is not in the cache and can't be downloaded offline",
+            ]
+        );
+        assert!(resolution.found.iter().any(|d| d.name == "R6"));
+    }
 }
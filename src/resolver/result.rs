@@ -1,3 +1,4 @@
+use crate::cache::MissingFromCache;
 use crate::resolver::sat::DependencySolver;
 use crate::{ResolvedDependency, UnresolvedDependency};
 use std::collections::{HashMap, HashSet};
@@ -15,11 +16,44 @@ impl fmt::Display for RequirementFailure {
     }
 }
 
+/// Packages depending on each other, R can't install any of them.
+/// The path starts and ends with the same package, eg `A -> B -> A`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Dependency cycle: {}", .0.join(" -> "))]
+pub struct DependencyCycle(pub Vec<String>);
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Resolution<'d> {
     pub found: Vec<ResolvedDependency<'d>>,
     pub failed: Vec<UnresolvedDependency<'d>>,
     pub req_failures: HashMap<String, Vec<RequirementFailure>>,
+    pub cycles: Vec<DependencyCycle>,
+}
+
+/// Depth first search, a dependency that is already in `path` closes a cycle
+fn visit<'a>(
+    name: &'a str,
+    edges: &HashMap<&'a str, Vec<&'a str>>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    cycles: &mut Vec<DependencyCycle>,
+) {
+    if done.contains(name) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|p| *p == name) {
+        let mut cycle: Vec<_> = path[start..].iter().map(|p| p.to_string()).collect();
+        cycle.push(name.to_string());
+        cycles.push(DependencyCycle(cycle));
+        return;
+    }
+
+    path.push(name);
+    for dep in edges.get(name).into_iter().flatten() {
+        visit(dep, edges, path, done, cycles);
+    }
+    path.pop();
+    done.insert(name);
 }
 
 impl<'d> Resolution<'d> {
@@ -110,7 +144,8 @@ impl<'d> Resolution<'d> {
                     let keep = indices.contains(&current_idx);
                     current_idx += 1;
                     keep
-                })
+                });
+                self.cycles = self.find_cycles();
             }
             Err(req_errors) => {
                 let mut out = HashMap::new();
@@ -127,8 +162,68 @@ impl<'d> Resolution<'d> {
         }
     }
 
+    /// Finds cycles in the dependencies of the packages found, only looking at what is required
+    /// for installation: suggestions are allowed to depend on the package that suggests them.
+    /// Each cycle is only reported once, starting from the package found first.
+    fn find_cycles(&self) -> Vec<DependencyCycle> {
+        let edges: HashMap<&str, Vec<&str>> = self
+            .found
+            .iter()
+            .filter(|p| !p.ignored)
+            .map(|p| {
+                (
+                    p.name.as_ref(),
+                    p.dependencies.iter().map(|d| d.name()).collect(),
+                )
+            })
+            .collect();
+
+        let mut cycles = Vec::new();
+        // Packages we are done with, either not part of a cycle or already reported
+        let mut done = HashSet::new();
+        for package in &self.found {
+            let mut path = Vec::new();
+            visit(
+                package.name.as_ref(),
+                &edges,
+                &mut path,
+                &mut done,
+                &mut cycles,
+            );
+        }
+        cycles
+    }
+
+    /// When running offline, everything that would need to be downloaded to be installed
+    /// is marked as failed.
+    pub(crate) fn fail_missing_from_cache(&mut self) {
+        for dep in &self.found {
+            if dep.ignored || dep.is_local() || dep.installation_status.available() {
+                continue;
+            }
+            let parent = self
+                .found
+                .iter()
+                .find(|p| p.dependencies.iter().any(|d| d.name() == dep.name))
+                .map(|p| p.name.clone());
+            let error = MissingFromCache {
+                package: dep.name.to_string(),
+                version: Some(dep.version.original.clone()),
+            };
+            self.failed.push(UnresolvedDependency {
+                name: dep.name.clone(),
+                error: Some(error.to_string()),
+                version_requirement: None,
+                parent,
+                remote: None,
+                local_path: None,
+                url: None,
+            });
+        }
+    }
+
     pub fn is_success(&self) -> bool {
-        self.failed.is_empty() && self.req_failures.is_empty()
+        self.failed.is_empty() && self.req_failures.is_empty() && self.cycles.is_empty()
     }
 
     pub fn req_error_messages(&self) -> Vec<String> {
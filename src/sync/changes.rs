@@ -4,10 +4,10 @@ use std::time::Duration;
 
 use serde::{Serialize, Serializer};
 
-use crate::DiskCache;
 use crate::lockfile::Source;
 use crate::package::PackageType;
 use crate::system_req::{SysDep, SysInstallationStatus};
+use crate::{DiskCache, Version};
 
 fn serialize_duration_as_ms<S>(
     duration: &Option<Duration>,
@@ -83,38 +83,46 @@ impl SyncChange {
         }
     }
 
+    /// `name (version, kind from source)` followed by the system dependencies if there are any
+    fn describe(&self, version: &str, supports_sysdeps_status: bool) -> String {
+        let sys_deps = {
+            let mut out = Vec::new();
+            for sys_dep in &self.sys_deps {
+                let status = if !supports_sysdeps_status {
+                    String::new()
+                } else {
+                    format!(
+                        "{} ",
+                        if sys_dep.status == SysInstallationStatus::Present {
+                            "✓"
+                        } else {
+                            "✗"
+                        }
+                    )
+                };
+                out.push(format!("{status}{}", sys_dep.name))
+            }
+            out
+        };
+        format!(
+            "{} ({}, {} from {}){}",
+            self.name,
+            version,
+            self.kind.unwrap(),
+            self.source.as_ref().map(|x| x.to_string()).unwrap(),
+            if sys_deps.is_empty() {
+                String::new()
+            } else {
+                format!(" with sys deps: {}", sys_deps.join(", "))
+            }
+        )
+    }
+
     pub fn print(&self, include_timings: bool, supports_sysdeps_status: bool) -> String {
         if self.installed {
-            let sys_deps = {
-                let mut out = Vec::new();
-                for sys_dep in &self.sys_deps {
-                    let status = if !supports_sysdeps_status {
-                        String::new()
-                    } else {
-                        format!(
-                            "{} ",
-                            if sys_dep.status == SysInstallationStatus::Present {
-                                "✓"
-                            } else {
-                                "✗"
-                            }
-                        )
-                    };
-                    out.push(format!("{status}{}", sys_dep.name))
-                }
-                out
-            };
             let mut base = format!(
-                "+ {} ({}, {} from {}){}",
-                self.name,
-                self.version.as_ref().unwrap(),
-                self.kind.unwrap(),
-                self.source.as_ref().map(|x| x.to_string()).unwrap(),
-                if sys_deps.is_empty() {
-                    String::new()
-                } else {
-                    format!(" with sys deps: {}", sys_deps.join(", "))
-                }
+                "+ {}",
+                self.describe(self.version.as_ref().unwrap(), supports_sysdeps_status)
             );
 
             if include_timings {
@@ -144,3 +152,172 @@ impl SyncChange {
         }
     }
 }
+
+/// What applying a change will do to the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Install,
+    Upgrade,
+    Downgrade,
+    /// Same version but it needs to be installed again, eg coming from another source
+    Reinstall,
+    Remove,
+}
+
+/// A change of the sync compared to what is currently in the library
+#[derive(Debug, Serialize)]
+pub struct PlannedChange<'a> {
+    pub name: &'a str,
+    pub action: PlanAction,
+    /// The version currently in the library
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// The version that will be installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<&'a str>,
+    #[serde(skip)]
+    change: Option<&'a SyncChange>,
+}
+
+impl PlannedChange<'_> {
+    pub fn print(&self, supports_sysdeps_status: bool) -> String {
+        let Some(change) = self.change else {
+            return match &self.from {
+                Some(from) => format!("- {} ({from})", self.name),
+                None => format!("- {}", self.name),
+            };
+        };
+        let to = self.to.unwrap_or_default();
+        let (symbol, version) = match (self.action, &self.from) {
+            (PlanAction::Upgrade, Some(from)) => ("↑", format!("{from} → {to}")),
+            (PlanAction::Downgrade, Some(from)) => ("↓", format!("{from} → {to}")),
+            (PlanAction::Reinstall, _) => ("~", to.to_string()),
+            _ => ("+", to.to_string()),
+        };
+        format!(
+            "{symbol} {}",
+            change.describe(&version, supports_sysdeps_status)
+        )
+    }
+}
+
+/// Classifies the changes of a sync against the versions of the packages currently installed.
+/// A package that is removed and installed again is a single upgrade, downgrade or reinstall.
+/// The plan is sorted by package name.
+pub fn plan_changes<'a>(
+    changes: &'a [SyncChange],
+    installed: &HashMap<String, Version>,
+) -> Vec<PlannedChange<'a>> {
+    let mut plan = Vec::new();
+
+    for change in changes {
+        let current = installed.get(&change.name);
+        if change.installed {
+            let to = change.version.as_deref();
+            let action = match (current, to.and_then(|v| v.parse::<Version>().ok())) {
+                (Some(current), Some(to)) if &to > current => PlanAction::Upgrade,
+                (Some(current), Some(to)) if &to < current => PlanAction::Downgrade,
+                (Some(_), _) => PlanAction::Reinstall,
+                (None, _) => PlanAction::Install,
+            };
+            plan.push(PlannedChange {
+                name: &change.name,
+                action,
+                from: current.map(|v| v.original.clone()),
+                to,
+                change: Some(change),
+            });
+        } else if !changes.iter().any(|c| c.installed && c.name == change.name) {
+            plan.push(PlannedChange {
+                name: &change.name,
+                action: PlanAction::Remove,
+                from: current.map(|v| v.original.clone()),
+                to: None,
+                change: None,
+            });
+        }
+    }
+
+    plan.sort_by_key(|c| c.name);
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(name: &str, version: &str) -> SyncChange {
+        SyncChange::installed(
+            name,
+            version,
+            Source::Repository {
+                repository: "https://cran.r-project.org".parse().unwrap(),
+            },
+            PackageType::Binary,
+            Duration::ZERO,
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn can_classify_planned_changes() {
+        let library = HashMap::from([
+            ("cli".to_string(), "3.6.1".parse().unwrap()),
+            ("rlang".to_string(), "1.1.4".parse().unwrap()),
+            ("old".to_string(), "0.1.0".parse().unwrap()),
+        ]);
+        let changes = vec![
+            SyncChange::removed("cli"),
+            SyncChange::removed("rlang"),
+            SyncChange::removed("old"),
+            installed("dplyr", "1.1.4"),
+            installed("cli", "3.6.4"),
+            installed("rlang", "1.1.0"),
+        ];
+        let plan: Vec<_> = plan_changes(&changes, &library)
+            .into_iter()
+            .map(|c| (c.name, c.action, c.from, c.to))
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                (
+                    "cli",
+                    PlanAction::Upgrade,
+                    Some("3.6.1".to_string()),
+                    Some("3.6.4")
+                ),
+                ("dplyr", PlanAction::Install, None, Some("1.1.4")),
+                ("old", PlanAction::Remove, Some("0.1.0".to_string()), None),
+                (
+                    "rlang",
+                    PlanAction::Downgrade,
+                    Some("1.1.4".to_string()),
+                    Some("1.1.0")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_print_planned_changes() {
+        let library = HashMap::from([("cli".to_string(), "3.6.1".parse().unwrap())]);
+        let changes = vec![
+            SyncChange::removed("cli"),
+            installed("cli", "3.6.4"),
+            installed("dplyr", "1.1.4"),
+        ];
+        let plan: Vec<_> = plan_changes(&changes, &library)
+            .iter()
+            .map(|c| c.print(false))
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                "↑ cli (3.6.1 → 3.6.4, binary from https://cran.r-project.org/)",
+                "+ dplyr (1.1.4, binary from https://cran.r-project.org/)",
+            ]
+        );
+    }
+}
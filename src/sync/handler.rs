@@ -9,12 +9,17 @@ use crossbeam::{channel, thread};
 use ctrlc;
 use fs_err as fs;
 use indicatif::{ProgressBar, ProgressStyle};
+use url::Url;
 
+use crate::cache::{evict_least_recently_used, mark_used, resolved_entries};
 use crate::consts::{BASE_PACKAGES, NO_CHECK_OPEN_FILE_ENV_VAR_NAME, RECOMMENDED_PACKAGES};
+use crate::fs::replace_dir_atomically;
 use crate::lockfile::Source;
 use crate::package::PackageType;
 use crate::sync::changes::SyncChange;
 use crate::sync::errors::{SyncError, SyncErrorKind, SyncErrors};
+use crate::sync::prefetch::prefetch_binaries;
+use crate::sync::link::{copy_link, is_dangling_link, remove_link};
 use crate::sync::{LinkMode, sources};
 use crate::utils::get_max_workers;
 use crate::{
@@ -81,6 +86,10 @@ pub struct SyncHandler<'a> {
     show_progress_bar: bool,
     max_workers: usize,
     uses_lockfile: bool,
+    /// The mirrors of each repository, by repository URL
+    mirrors: HashMap<String, Vec<Url>>,
+    /// The least recently used packages are evicted from the cache once it's bigger than that
+    cache_max_size: Option<u64>,
 }
 
 impl<'a> SyncHandler<'a> {
@@ -101,6 +110,8 @@ impl<'a> SyncHandler<'a> {
             show_progress_bar: false,
             uses_lockfile: false,
             max_workers: get_max_workers(),
+            mirrors: HashMap::new(),
+            cache_max_size: None,
         }
     }
 
@@ -121,12 +132,39 @@ impl<'a> SyncHandler<'a> {
         self.uses_lockfile = uses_lockfile;
     }
 
+    pub fn set_mirrors(&mut self, mirrors: HashMap<String, Vec<Url>>) {
+        self.mirrors = mirrors;
+    }
+
+    pub fn set_cache_max_size(&mut self, max_size: Option<u64>) {
+        self.cache_max_size = max_size;
+    }
+
+    /// Removes the least recently used packages from the cache if it's too big, keeping the
+    /// ones we just resolved. Failing to do so doesn't fail the sync.
+    fn evict_from_cache(&self, deps: &[ResolvedDependency]) {
+        let Some(max_size) = self.cache_max_size else {
+            return;
+        };
+        let keep = resolved_entries(self.cache, deps);
+        match evict_least_recently_used(self.cache, max_size, &keep) {
+            Ok(report) if report.removed > 0 => log::debug!("Cache eviction: {report}"),
+            Ok(_) => (),
+            Err(e) => log::warn!("Failed to evict packages from the cache: {e}"),
+        }
+    }
+
     fn copy_package(&self, dep: &ResolvedDependency) -> Result<(), SyncError> {
         if self.dry_run {
             return Ok(());
         }
 
         log::debug!("Copying package {} from current library", &dep.name);
+        let in_library = self.library.path().join(dep.name.as_ref());
+        if in_library.is_symlink() && LinkMode::new() == LinkMode::SymlinkDir {
+            copy_link(&in_library, self.staging_path.join(dep.name.as_ref()))?;
+            return Ok(());
+        }
         LinkMode::Copy.link_files(
             &dep.name,
             self.library.path().join(dep.name.as_ref()),
@@ -148,12 +186,16 @@ impl<'a> SyncHandler<'a> {
         // we want the staging to take precedence over the library, but still have
         // the library in the paths for lookup
         let library_dirs = vec![&self.staging_path, self.library.path()];
-        match dep.source {
-            Source::Repository { .. } => sources::repositories::install_package(
+        let res = match dep.source {
+            Source::Repository { ref repository } => sources::repositories::install_package(
                 dep,
                 &library_dirs,
                 self.cache,
                 r_cmd,
+                self.mirrors
+                    .get(repository.as_str())
+                    .map(|m| m.as_slice())
+                    .unwrap_or_default(),
                 cancellation,
             ),
             Source::Git { .. } | Source::RUniverse { .. } => sources::git::install_package(
@@ -176,7 +218,11 @@ impl<'a> SyncHandler<'a> {
                 sources::url::install_package(dep, &library_dirs, self.cache, r_cmd, cancellation)
             }
             Source::Builtin { .. } => Ok(()),
+        };
+        if res.is_ok() {
+            mark_used(self.cache, dep);
         }
+        res
     }
 
     /// We want to figure out:
@@ -335,6 +381,19 @@ impl<'a> SyncHandler<'a> {
         // Create staging only if we need to build stuff
         fs::create_dir_all(&self.staging_path)?;
 
+        // Download and extract the binaries we need upfront, the workers will find them in the cache
+        if !self.dry_run {
+            prefetch_binaries(
+                deps.iter().filter(|d| {
+                    !deps_seen.contains(d.name.as_ref()) && !deps_to_copy.contains(d.name.as_ref())
+                }),
+                self.cache,
+                &self.mirrors,
+                self.max_workers,
+                &cancellation,
+            );
+        }
+
         // Then we mark the deps seen so they won't be installed into the staging dir
         for d in &deps_seen {
             // builtin packages will not be in the library
@@ -556,6 +615,10 @@ impl<'a> SyncHandler<'a> {
                 if !self.dry_run && notify {
                     log::debug!("Removing {name} from library");
                     fs::remove_dir_all(&p)?;
+                } else if !self.dry_run && is_dangling_link(&p) {
+                    // The cache was cleaned, the package will be linked again if still needed
+                    log::debug!("Removing dangling link {name} from library");
+                    remove_link(&p)?;
                 }
 
                 if notify {
@@ -568,16 +631,14 @@ impl<'a> SyncHandler<'a> {
                 let path = entry.path();
                 let name = path.file_name().unwrap().to_str().unwrap().to_string();
                 if !deps_seen.contains(name.as_str()) {
-                    let out = self.library.path().join(&name);
-                    if out.is_dir() {
-                        fs::remove_dir_all(&out)?;
-                    }
-                    fs::rename(path, out)?;
+                    replace_dir_atomically(&path, &self.library.path().join(&name))?;
                 }
             }
 
             // Then delete staging
             fs::remove_dir_all(&self.staging_path)?;
+
+            self.evict_from_cache(deps);
         }
 
         // Sort all changes by a-z and fall back on installed status for things with the same name
@@ -1,18 +1,31 @@
 //! How packages are linked from the cache to each project library
 //! Taken from uv: clone (CoW) on MacOS and hard links on Mac/Linux by default
 //! Maybe with optional symlink support for cross disk linking
+//! `symlink-dir` links the whole package folder instead so each project library only contains
+//! one symlink per package, or a junction on Windows since they don't require admin rights.
+//! Those links break if the cache is cleaned, in which case the library sees the package as
+//! broken and it gets installed again.
 
 use fs_err as fs;
 use fs_err::DirEntry;
 use reflink_copy as reflink;
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use walkdir::WalkDir;
 
-use crate::fs::copy_folder;
+use crate::fs::{CopyOptions, copy_folder, copy_folder_parallel, link_dir};
 
 const LINK_ENV_NAME: &str = "RV_LINK_MODE";
 
+static FORCE_COPY: AtomicBool = AtomicBool::new(false);
+
+/// Makes `LinkMode::new` always copy, whatever `RV_LINK_MODE` says, eg for `--copy` on
+/// filesystems without hard links or symlinks
+pub fn set_force_copy(copy: bool) {
+    FORCE_COPY.store(copy, Ordering::Relaxed);
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum LinkError {
     #[error(transparent)]
@@ -38,6 +51,8 @@ pub enum LinkMode {
     Hardlink,
     /// Use symlinks for all elements
     Symlink,
+    /// Symlink the package folder itself, pointing to the cache. Uses a junction on Windows.
+    SymlinkDir,
 }
 
 impl Default for LinkMode {
@@ -52,6 +67,10 @@ impl Default for LinkMode {
 
 impl LinkMode {
     pub fn new() -> Self {
+        if FORCE_COPY.load(Ordering::Relaxed) {
+            return Self::Copy;
+        }
+
         // First try to find out if the mode is set in the env
         let from_env = if let Ok(val) = env::var(LINK_ENV_NAME) {
             match val.to_lowercase().as_str() {
@@ -59,6 +78,7 @@ impl LinkMode {
                 "clone" => Some(Self::Clone),
                 "hardlink" => Some(Self::Hardlink),
                 "symlink" => Some(Self::Symlink),
+                "symlink-dir" => Some(Self::SymlinkDir),
                 _ => None,
             }
         } else {
@@ -84,6 +104,7 @@ impl LinkMode {
             Self::Clone => "clone",
             Self::Symlink => "symlink",
             Self::Hardlink => "hardlink",
+            Self::SymlinkDir => "symlink-dir",
         }
     }
 
@@ -95,17 +116,28 @@ impl LinkMode {
     ) -> Result<(), LinkError> {
         // If it's already exists for some reasons (eg failed halfway before), delete it first
         let pkg_in_lib = destination.as_ref().join(package_name);
-        if pkg_in_lib.is_dir() {
+        if pkg_in_lib.is_symlink() {
+            // Including dangling ones, we don't want to delete what they point to
+            remove_link(&pkg_in_lib)?;
+        } else if pkg_in_lib.is_dir() {
             fs::remove_dir_all(&pkg_in_lib)?;
         }
 
         let res = match self {
-            LinkMode::Copy => {
-                copy_folder(source.as_ref(), destination.as_ref()).map_err(Into::into)
-            }
+            LinkMode::Copy => copy_folder_parallel(
+                source.as_ref(),
+                destination.as_ref(),
+                &CopyOptions::default(),
+            )
+            .map(|_| ())
+            .map_err(Into::into),
             LinkMode::Clone => clone_package(source.as_ref(), destination.as_ref()),
             LinkMode::Hardlink => hardlink_package(source.as_ref(), destination.as_ref()),
             LinkMode::Symlink => symlink_package(source.as_ref(), destination.as_ref()),
+            LinkMode::SymlinkDir => {
+                fs::create_dir_all(destination.as_ref())?;
+                link_dir(source.as_ref().join(package_name), &pkg_in_lib).map_err(LinkError::Io)
+            }
         };
 
         if let Err(e) = res {
@@ -113,14 +145,20 @@ impl LinkMode {
                 return Err(e);
             }
             // Cleanup a bit in case it failed halfway through
-            if pkg_in_lib.is_dir() {
+            if pkg_in_lib.is_symlink() {
+                remove_link(&pkg_in_lib)?;
+            } else if pkg_in_lib.is_dir() {
                 fs::remove_dir_all(&pkg_in_lib)?
             }
             log::warn!(
                 "Failed to {} files: {e}. Falling back to copying files.",
                 self.name()
             );
-            copy_folder(source.as_ref(), destination.as_ref())?;
+            copy_folder(
+                source.as_ref(),
+                destination.as_ref(),
+                &CopyOptions::default(),
+            )?;
         }
 
         Ok(())
@@ -223,3 +261,105 @@ fn create_symlink(original: impl AsRef<Path>, link: impl AsRef<Path>) -> std::io
         std::os::windows::fs::symlink_file(original, link)
     }
 }
+
+/// Removes the symlink or junction itself, not what it points to
+pub(crate) fn remove_link(path: impl AsRef<Path>) -> std::io::Result<()> {
+    // Windows treats symlinks to directories and junctions as directories
+    if cfg!(windows) && fs::remove_dir(path.as_ref()).is_ok() {
+        return Ok(());
+    }
+    fs::remove_file(path.as_ref())
+}
+
+/// A symlink pointing to something that doesn't exist anymore, eg a package folder in the cache
+/// that was cleaned
+pub(crate) fn is_dangling_link(path: impl AsRef<Path>) -> bool {
+    path.as_ref().is_symlink() && !path.as_ref().exists()
+}
+
+/// Recreates the symlink `link` at `new_link`, pointing to the same target
+pub(crate) fn copy_link(link: impl AsRef<Path>, new_link: impl AsRef<Path>) -> std::io::Result<()> {
+    let target = fs::read_link(link.as_ref())?;
+    if let Some(parent) = new_link.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    link_dir(target, new_link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Library, SystemInfo};
+
+    fn make_package(folder: &Path, name: &str) {
+        let path = folder.join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(
+            path.join("DESCRIPTION"),
+            format!("Package: {name}\nVersion: 1.0.0\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn can_symlink_package_folder() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = tempdir.path().join("cache");
+        let library = tempdir.path().join("library");
+        make_package(&cache, "pkgA");
+
+        LinkMode::SymlinkDir
+            .link_files("pkgA", &cache, &library)
+            .unwrap();
+        let linked = library.join("pkgA");
+        assert!(linked.is_symlink());
+        assert_eq!(fs::read_link(&linked).unwrap(), cache.join("pkgA"));
+        assert!(linked.join("DESCRIPTION").is_file());
+
+        // Linking again replaces the link, without touching the cache
+        LinkMode::SymlinkDir
+            .link_files("pkgA", &cache, &library)
+            .unwrap();
+        assert!(linked.is_symlink());
+        assert!(cache.join("pkgA").join("DESCRIPTION").is_file());
+
+        // And we can keep it as a link when copying the library
+        let staging = tempdir.path().join("staging").join("pkgA");
+        copy_link(&linked, &staging).unwrap();
+        assert_eq!(fs::read_link(&staging).unwrap(), cache.join("pkgA"));
+    }
+
+    #[test]
+    fn dangling_links_are_detected_and_repaired() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = tempdir.path().join("cache");
+        let mut library = Library::new(tempdir.path(), &SystemInfo::from_os_info(), [4, 4]);
+        make_package(&cache, "pkgA");
+        make_package(&cache, "pkgB");
+        for name in ["pkgA", "pkgB"] {
+            LinkMode::SymlinkDir
+                .link_files(name, &cache, &library.path)
+                .unwrap();
+        }
+        library.find_content();
+        assert_eq!(library.packages.len(), 2);
+
+        // The cache was cleaned
+        fs::remove_dir_all(cache.join("pkgA")).unwrap();
+        assert!(is_dangling_link(library.path.join("pkgA")));
+        assert!(!is_dangling_link(library.path.join("pkgB")));
+        library.find_content();
+        assert!(library.broken.contains("pkgA"));
+        assert!(library.packages.contains_key("pkgB"));
+
+        // Installing it again replaces the dangling link
+        make_package(&cache, "pkgA");
+        LinkMode::SymlinkDir
+            .link_files("pkgA", &cache, &library.path)
+            .unwrap();
+        assert!(!is_dangling_link(library.path.join("pkgA")));
+        library.find_content();
+        assert!(library.broken.is_empty());
+        assert_eq!(library.packages.len(), 2);
+    }
+}
@@ -3,9 +3,10 @@ mod changes;
 mod errors;
 mod handler;
 mod link;
+mod prefetch;
 mod sources;
 
 pub use build_plan::{BuildPlan, BuildStep};
-pub use changes::SyncChange;
+pub use changes::{PlanAction, PlannedChange, SyncChange, plan_changes};
 pub use handler::SyncHandler;
-pub use link::{LinkError, LinkMode};
+pub use link::{LinkError, LinkMode, set_force_copy};
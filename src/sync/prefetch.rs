@@ -0,0 +1,261 @@
+//! Downloads the binaries we need into the cache ahead of their installation, several at once.
+//! Each archive is extracted as it's downloaded, going through the mirrors and the retry policy
+//! like when installing the package.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crossbeam::{channel, thread};
+use url::Url;
+
+use crate::cache::InstallationStatus;
+use crate::cancellation::Cancellation;
+use crate::http::{HttpError, is_offline};
+use crate::lockfile::Source;
+use crate::mirrors::download_and_untar_from_mirrors;
+use crate::package::PackageType;
+use crate::sync::sources::repositories::write_install_metadata;
+use crate::{DiskCache, HttpDownload, ResolvedDependency, get_tarball_urls};
+
+/// An archive to download from a repository and extract in `destination`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Archive<'a> {
+    pub url: Url,
+    pub repository: &'a str,
+    pub mirrors: &'a [Url],
+    pub destination: PathBuf,
+}
+
+/// Downloads and extracts the archives with `workers` threads.
+/// No new download is started once the sync is cancelled.
+/// Returns the SHA256 of each archive, why it failed or `None` if it was not downloaded because
+/// of the cancellation, in the same order as `archives`.
+pub(crate) fn download_and_extract(
+    http: &(impl HttpDownload + Sync),
+    cache: &DiskCache,
+    archives: &[Archive],
+    workers: usize,
+    cancellation: &Cancellation,
+) -> Vec<Option<Result<String, HttpError>>> {
+    let results: Mutex<Vec<Option<Result<String, HttpError>>>> =
+        Mutex::new(archives.iter().map(|_| None).collect());
+    let (todo_sender, todo_receiver) = channel::unbounded();
+    for i in 0..archives.len() {
+        todo_sender.send(i).unwrap();
+    }
+    drop(todo_sender);
+
+    thread::scope(|s| {
+        for _ in 0..workers.max(1) {
+            let todo_receiver = todo_receiver.clone();
+            let results = &results;
+            s.spawn(move |_| {
+                while let Ok(i) = todo_receiver.recv() {
+                    if cancellation.is_cancelled() {
+                        break;
+                    }
+                    let archive = &archives[i];
+                    let res = download_and_untar_from_mirrors(
+                        http,
+                        cache,
+                        &archive.url,
+                        archive.repository,
+                        archive.mirrors,
+                        &archive.destination,
+                        false,
+                    )
+                    .map(|(_, sha)| sha);
+                    results.lock().unwrap()[i] = Some(res);
+                }
+            });
+        }
+    })
+    .expect("threads to not panic");
+
+    results.into_inner().unwrap()
+}
+
+/// Downloads and extracts in the cache the binaries of the packages from repositories that
+/// are not there yet.
+/// Failures are only logged: the package is downloaded again when installing it, falling back
+/// to the source package.
+pub(crate) fn prefetch_binaries<'a>(
+    deps: impl Iterator<Item = &'a ResolvedDependency<'a>>,
+    cache: &DiskCache,
+    mirrors: &'a HashMap<String, Vec<Url>>,
+    workers: usize,
+    cancellation: &Cancellation,
+) {
+    if is_offline() {
+        return;
+    }
+
+    let (deps, archives): (Vec<_>, Vec<_>) = deps
+        .filter(|d| {
+            d.kind == PackageType::Binary && d.installation_status == InstallationStatus::Absent
+        })
+        .filter_map(|d| {
+            let Source::Repository { repository } = &d.source else {
+                return None;
+            };
+            let url = get_tarball_urls(d, &cache.r_version, &cache.system_info)
+                .ok()?
+                .binary?;
+            let destination = cache
+                .get_package_paths(&d.source, Some(&d.name), Some(&d.version.original))
+                .binary;
+            Some((
+                d,
+                Archive {
+                    url,
+                    repository: repository.as_str(),
+                    mirrors: mirrors
+                        .get(repository.as_str())
+                        .map(|m| m.as_slice())
+                        .unwrap_or_default(),
+                    destination,
+                },
+            ))
+        })
+        .unzip();
+    if archives.is_empty() {
+        return;
+    }
+
+    log::debug!("Prefetching {} binary packages", archives.len());
+    let http = crate::Http {};
+    let results = download_and_extract(&http, cache, &archives, workers, cancellation);
+    for ((dep, archive), res) in deps.iter().zip(&archives).zip(results) {
+        match res {
+            Some(Ok(sha)) => {
+                if let Err(e) = write_install_metadata(
+                    dep,
+                    cache,
+                    Some(archive.url.clone()),
+                    Some(sha),
+                    PackageType::Binary,
+                ) {
+                    log::debug!("Failed to write the metadata of {}: {e}", dep.name);
+                }
+            }
+            Some(Err(e)) => log::debug!("Failed to prefetch {}: {e}", archive.url),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::path::Path;
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::http::{HttpErrorKind, untar_into};
+
+    /// Serves the archives from memory
+    struct InMemory(HashMap<Url, Vec<u8>>);
+
+    impl HttpDownload for InMemory {
+        fn download<W: Write>(
+            &self,
+            _: &Url,
+            _: &mut W,
+            _: Vec<(&str, String)>,
+        ) -> Result<u64, HttpError> {
+            unreachable!()
+        }
+
+        fn download_and_untar(
+            &self,
+            url: &Url,
+            destination: impl AsRef<Path>,
+            use_sha_in_path: bool,
+        ) -> Result<(Option<PathBuf>, String), HttpError> {
+            match self.0.get(url) {
+                Some(bytes) => untar_into(
+                    url,
+                    bytes.as_slice(),
+                    Some(bytes.len() as u64),
+                    destination.as_ref().to_path_buf(),
+                    use_sha_in_path,
+                ),
+                None => Err(HttpError {
+                    url: url.to_string(),
+                    source: HttpErrorKind::Http(404),
+                }),
+            }
+        }
+    }
+
+    fn make_archive(name: &str) -> Vec<u8> {
+        let description = format!("Package: {name}\nVersion: 1.0.0\n");
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(description.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                format!("{name}/DESCRIPTION"),
+                description.as_bytes(),
+            )
+            .unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn can_download_and_extract_archives_concurrently() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4.1".parse().unwrap(),
+            crate::SystemInfo::from_os_info(),
+            tempdir.path().join("cache"),
+        )
+        .unwrap();
+        let names = ["pkgA", "pkgB", "pkgC", "pkgD", "pkgE"];
+        let mut served = HashMap::new();
+        let mut archives = Vec::new();
+        for name in names {
+            let url = Url::parse(&format!("https://example.com/{name}_1.0.0.tar.gz")).unwrap();
+            served.insert(url.clone(), make_archive(name));
+            archives.push(Archive {
+                url,
+                repository: "https://example.com",
+                mirrors: &[],
+                destination: tempdir.path().join(name).join("1.0.0"),
+            });
+        }
+        archives.push(Archive {
+            url: Url::parse("https://example.com/missing_1.0.0.tar.gz").unwrap(),
+            repository: "https://example.com",
+            mirrors: &[],
+            destination: tempdir.path().join("missing"),
+        });
+        let http = InMemory(served);
+
+        let results = download_and_extract(&http, &cache, &archives, 3, &Cancellation::default());
+        assert_eq!(results.len(), archives.len());
+        for (archive, res) in archives.iter().zip(&results).take(names.len()) {
+            let expected = format!("{:x}", Sha256::digest(&http.0[&archive.url]));
+            assert_eq!(res.as_ref().unwrap().as_ref().unwrap(), &expected);
+            let name = archive.destination.parent().unwrap().file_name().unwrap();
+            assert!(archive.destination.join(name).join("DESCRIPTION").is_file());
+        }
+        let missing = results.last().unwrap().as_ref().unwrap();
+        assert!(missing.as_ref().unwrap_err().is_not_found());
+        assert!(!tempdir.path().join("missing").exists());
+
+        // Nothing is downloaded once cancelled
+        let cancellation = Cancellation::default();
+        cancellation.cancel();
+        let results = download_and_extract(&http, &cache, &archives, 3, &cancellation);
+        assert!(results.iter().all(|r| r.is_none()));
+    }
+}
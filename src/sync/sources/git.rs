@@ -1,15 +1,37 @@
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use fs_err as fs;
 
+use crate::git::url::GitUrl;
 use crate::git::{GitReference, GitRemote};
+use crate::http::Http;
 use crate::library::LocalMetadata;
 use crate::lockfile::Source;
 use crate::sync::LinkMode;
 use crate::sync::errors::SyncError;
-use crate::{Cancellation, CommandExecutor, DiskCache, RCmd, ResolvedDependency};
+use crate::sync::sources::{restore_from_content_cache, store_in_content_cache};
+use crate::{Cancellation, CommandExecutor, DiskCache, HttpDownload, RCmd, ResolvedDependency};
+
+/// GitHub generates a tarball for any commit, which is a lot faster to download than cloning the
+/// repository. Returns the root of the repository extracted in `destination`, or None if it's not
+/// on GitHub or the download failed, eg for a private repository.
+fn download_github_archive(
+    http: &impl HttpDownload,
+    git: &GitUrl,
+    sha: &str,
+    destination: &Path,
+) -> Option<PathBuf> {
+    let url = git.github_archive_url(sha)?;
+    match http.download_and_untar(&url, destination, false) {
+        Ok((dir, _)) => dir,
+        Err(e) => {
+            log::debug!("Failed to download {url}: {e}, cloning the repository instead");
+            None
+        }
+    }
+}
 
 pub(crate) fn install_package(
     pkg: &ResolvedDependency,
@@ -21,29 +43,43 @@ pub(crate) fn install_package(
 ) -> Result<(), SyncError> {
     let pkg_paths = cache.get_package_paths(&pkg.source, None, None);
 
-    // We will have the source version since we needed to clone it to get the DESCRIPTION file
-    if !pkg.installation_status.binary_available() {
-        let repo_url = pkg.source.git_url().unwrap();
+    // We usually have the source already since we needed it to get the DESCRIPTION file, unless
+    // we resolved from the lockfile
+    if !pkg.installation_status.binary_available()
+        && !restore_from_content_cache(pkg, cache, &pkg_paths.binary)
+    {
+        let (git, directory) = match &pkg.source {
+            Source::Git { git, directory, .. } | Source::RUniverse { git, directory, .. } => {
+                (git, directory)
+            }
+            _ => unreachable!("Dependency has a git source"),
+        };
         let sha = pkg.source.sha();
-        // TODO: this won't work if multiple projects are trying to checkout different refs
-        // on the same user at the same time
-        let remote = GitRemote::new(repo_url);
-        remote.checkout(
-            &pkg_paths.source,
-            &GitReference::Commit(sha),
-            git_exec.clone(),
-        )?;
-        // If we have a directory, don't forget to set it before building it
-        let source_path = match &pkg.source {
-            Source::Git {
-                directory: Some(dir),
-                ..
+        // Only used if we download the archive, it needs to live until the package is built
+        let tempdir = tempfile::tempdir()?;
+        let downloaded = if pkg_paths.source.is_dir() {
+            None
+        } else {
+            download_github_archive(&Http {}, git, sha, tempdir.path())
+        };
+        let repo_root = match downloaded {
+            Some(root) => root,
+            None => {
+                // TODO: this won't work if multiple projects are trying to checkout different refs
+                // on the same user at the same time
+                let remote = GitRemote::new(git.url());
+                remote.checkout(
+                    &pkg_paths.source,
+                    &GitReference::Commit(sha),
+                    git_exec.clone(),
+                )?;
+                pkg_paths.source
             }
-            | Source::RUniverse {
-                directory: Some(dir),
-                ..
-            } => pkg_paths.source.join(dir),
-            _ => pkg_paths.source,
+        };
+        // If we have a directory, don't forget to set it before building it
+        let source_path = match directory {
+            Some(dir) => repo_root.join(dir),
+            None => repo_root,
         };
 
         let output = r_cmd.install(
@@ -63,9 +99,101 @@ pub(crate) fn install_package(
 
         let metadata = LocalMetadata::Sha(sha.to_owned());
         metadata.write(pkg_paths.binary.join(pkg.name.as_ref()))?;
+        store_in_content_cache(pkg, cache, &pkg_paths.binary);
     }
 
     // And then we always link the binary folder into the staging library
     LinkMode::new().link_files(&pkg.name, &pkg_paths.binary, library_dirs.first().unwrap())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpError, HttpErrorKind, untar_into};
+    use url::Url;
+
+    /// Only serves the archive of one commit
+    struct GitHub {
+        url: Url,
+        archive: Vec<u8>,
+    }
+
+    impl HttpDownload for GitHub {
+        fn download<W: Write>(
+            &self,
+            _: &Url,
+            _: &mut W,
+            _: Vec<(&str, String)>,
+        ) -> Result<u64, HttpError> {
+            unreachable!()
+        }
+
+        fn download_and_untar(
+            &self,
+            url: &Url,
+            destination: impl AsRef<Path>,
+            use_sha_in_path: bool,
+        ) -> Result<(Option<PathBuf>, String), HttpError> {
+            if url != &self.url {
+                return Err(HttpError {
+                    url: url.to_string(),
+                    source: HttpErrorKind::Http(404),
+                });
+            }
+            untar_into(
+                url,
+                self.archive.as_slice(),
+                Some(self.archive.len() as u64),
+                destination.as_ref().to_path_buf(),
+                use_sha_in_path,
+            )
+        }
+    }
+
+    #[test]
+    fn can_download_github_archive_of_commit() {
+        let sha = "c67018fa4970e20bac2b2e4f3d3b3b2a1e0d7c6b";
+        let description = "Package: httr\nVersion: 1.4.7\n";
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(description.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                format!("httr-{sha}/DESCRIPTION"),
+                description.as_bytes(),
+            )
+            .unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let http = GitHub {
+            url: Url::parse(&format!(
+                "https://github.com/r-lib/httr/archive/{sha}.tar.gz"
+            ))
+            .unwrap(),
+            archive: encoder.finish().unwrap(),
+        };
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let git = GitUrl::try_from("https://github.com/r-lib/httr").unwrap();
+        let root = download_github_archive(&http, &git, sha, tempdir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("DESCRIPTION")).unwrap(),
+            description
+        );
+
+        // Another commit, or a repository elsewhere
+        assert_eq!(
+            download_github_archive(&http, &git, "0123456789", tempdir.path()),
+            None
+        );
+        let git = GitUrl::try_from("https://gitlab.com/r-lib/httr").unwrap();
+        assert_eq!(
+            download_github_archive(&http, &git, sha, tempdir.path()),
+            None
+        );
+    }
+}
@@ -3,13 +3,27 @@ use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 
-use crate::fs::{mtime_recursive, untar_archive};
+use crate::consts::{
+    INSTALL_METADATA_FILENAME, LIBRARY_METADATA_FILENAME, LOCAL_FINGERPRINT_ENV_VAR_NAME,
+};
+use crate::fs::{
+    CopyMode, CopyOptions, UntarOptions, copy_folder_parallel, fingerprint_recursive,
+    is_tooling_metadata, mtime_recursive, untar_archive,
+};
 use crate::library::LocalMetadata;
 use crate::lockfile::Source;
-use crate::sync::LinkMode;
 use crate::sync::errors::SyncError;
 use crate::{Cancellation, DiskCache, RCmd, ResolvedDependency, is_binary_package};
 
+/// Whether local folders are compared by content rather than by mtime, eg on CI where the
+/// mtimes change on every checkout
+fn use_fingerprint() -> bool {
+    let val = std::env::var(LOCAL_FINGERPRINT_ENV_VAR_NAME)
+        .unwrap_or_default()
+        .to_lowercase();
+    val == "true" || val == "1"
+}
+
 pub(crate) fn install_package(
     pkg: &ResolvedDependency,
     project_dir: &Path,
@@ -28,7 +42,11 @@ pub(crate) fn install_package(
 
     let actual_path = if canon_path.is_file() {
         // TODO: we're already untarring in resolve, that's wasteful
-        let (path, _) = untar_archive(fs::read(&canon_path)?.as_slice(), tempdir.path(), false)?;
+        let (path, _) = untar_archive(
+            fs::File::open(&canon_path)?,
+            tempdir.path(),
+            UntarOptions::default(),
+        )?;
         path.unwrap_or_else(|| canon_path.clone())
     } else {
         canon_path.clone()
@@ -39,11 +57,46 @@ pub(crate) fn install_package(
             "Local package in {} is a binary package, copying files to library.",
             actual_path.display()
         );
-        LinkMode::Copy.link_files(
-            pkg.name.as_ref(),
+        let destination = library_dirs.first().unwrap().join(pkg.name.as_ref());
+        // If we had a previous version installed, start from it so only the files that changed
+        // since are copied. Hard links are fine since files are replaced rather than written to.
+        // What rv wrote there is left out since it gets written again below.
+        if let Some(installed) = library_dirs.get(1).map(|l| l.join(pkg.name.as_ref()))
+            && installed.is_dir()
+            && !installed.is_symlink()
+        {
+            copy_folder_parallel(
+                &installed,
+                &destination,
+                &CopyOptions {
+                    filter: Some(&|path| {
+                        path != Path::new(LIBRARY_METADATA_FILENAME)
+                            && path != Path::new(INSTALL_METADATA_FILENAME)
+                    }),
+                    use_hard_links: true,
+                    ..Default::default()
+                },
+            )?;
+        }
+        // The folder might be a checkout, no need to copy its VCS or IDE folders
+        let stats = copy_folder_parallel(
             &actual_path,
-            library_dirs.first().unwrap().join(pkg.name.as_ref()),
+            &destination,
+            &CopyOptions {
+                filter: Some(&|path| !is_tooling_metadata(path)),
+                mode: CopyMode::IfChanged,
+                prune: true,
+                ..Default::default()
+            },
         )?;
+        log::debug!(
+            "Copied {} files ({} bytes) for {}, {} were unchanged and {} removed.",
+            stats.copied,
+            stats.bytes_copied,
+            pkg.name,
+            stats.skipped,
+            stats.deleted
+        );
     } else {
         log::debug!("Building the local package in {}", actual_path.display());
         let output = r_cmd.install(
@@ -62,9 +115,11 @@ pub(crate) fn install_package(
         }
     }
 
-    // If it's a dir, save the dir mtime and if it's a tarball its sha
-    let metadata = if canon_path.is_dir() {
-        let local_mtime = mtime_recursive(&actual_path)?;
+    // If it's a dir, save the dir mtime or fingerprint and if it's a tarball its sha
+    let metadata = if canon_path.is_dir() && use_fingerprint() {
+        LocalMetadata::Fingerprint(fingerprint_recursive(&actual_path)?)
+    } else if canon_path.is_dir() {
+        let local_mtime = mtime_recursive(&actual_path, None, false)?.max_mtime;
         LocalMetadata::Mtime(local_mtime.unix_seconds())
     } else {
         LocalMetadata::Sha(sha.unwrap())
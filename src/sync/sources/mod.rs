@@ -3,4 +3,53 @@ pub(crate) mod local;
 pub(crate) mod repositories;
 pub(crate) mod url;
 
+use std::path::Path;
+
+use fs_err as fs;
+
+use crate::{DiskCache, ResolvedDependency};
+
+/// Git and URL packages are cached by where they come from, so the same commit or archive from
+/// another URL, eg a fork, would be built again. Their builds are also kept by content, keyed by
+/// that SHA, to be linked in `binary_folder` instead.
+/// Returns whether the package was found there.
+pub(crate) fn restore_from_content_cache(
+    pkg: &ResolvedDependency,
+    cache: &DiskCache,
+    binary_folder: &Path,
+) -> bool {
+    let sha = pkg.source.sha();
+    match cache.restore_package(&pkg.name, &pkg.version.original, sha, binary_folder) {
+        Ok(restored) => {
+            if restored {
+                log::debug!("Package {} ({sha}) found in the content cache", pkg.name);
+            }
+            restored
+        }
+        Err(e) => {
+            log::debug!("Failed to restore {} from the content cache: {e}", pkg.name);
+            // It would look installed otherwise
+            let _ = fs::remove_dir_all(binary_folder.join(pkg.name.as_ref()));
+            false
+        }
+    }
+}
+
+/// Keeps the package built in `binary_folder` in the content cache, see
+/// `restore_from_content_cache`. Failing to do so doesn't fail the installation.
+pub(crate) fn store_in_content_cache(
+    pkg: &ResolvedDependency,
+    cache: &DiskCache,
+    binary_folder: &Path,
+) {
+    if let Err(e) = cache.store_package(
+        &pkg.name,
+        &pkg.version.original,
+        pkg.source.sha(),
+        binary_folder.join(pkg.name.as_ref()),
+    ) {
+        log::warn!("Failed to store {} in the content cache: {e}", pkg.name);
+    }
+}
+
 // TODO: verify local/url since they feel weird
@@ -5,21 +5,55 @@ use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 
+use url::Url;
+
 use crate::cache::InstallationStatus;
+use crate::cache::utils::get_current_system_path;
 use crate::http::Http;
+use crate::lockfile::Source;
+use crate::mirrors::download_and_untar_from_mirrors;
 use crate::package::PackageType;
 use crate::sync::LinkMode;
 use crate::sync::errors::SyncError;
 use crate::{
-    Cancellation, DiskCache, HttpDownload, RCmd, ResolvedDependency, get_tarball_urls,
+    Cancellation, DiskCache, InstallMetadata, RCmd, ResolvedDependency, get_tarball_urls,
     is_binary_package,
 };
 
+/// Records how that package version got in the cache, in its binary folder
+pub(crate) fn write_install_metadata(
+    pkg: &ResolvedDependency,
+    cache: &DiskCache,
+    source_url: Option<Url>,
+    archive_sha256: Option<String>,
+    kind: PackageType,
+) -> Result<(), std::io::Error> {
+    let binary_path = cache
+        .get_package_paths(&pkg.source, Some(&pkg.name), Some(&pkg.version.original))
+        .binary;
+    let platform = get_current_system_path(&cache.system_info, cache.r_version);
+    let metadata = InstallMetadata {
+        name: pkg.name.to_string(),
+        version: pkg.version.original.clone(),
+        installed_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+        source_url,
+        archive_sha256,
+        kind,
+        platform: platform.to_string_lossy().replace('\\', "/"),
+        cache_path: binary_path.clone(),
+    };
+    metadata.write(binary_path.join(pkg.name.as_ref()))
+}
+
 pub(crate) fn install_package(
     pkg: &ResolvedDependency,
     library_dirs: &[&Path],
     cache: &DiskCache,
     r_cmd: &impl RCmd,
+    mirrors: &[Url],
     cancellation: Arc<Cancellation>,
 ) -> Result<(), SyncError> {
     let pkg_paths =
@@ -60,6 +94,8 @@ pub(crate) fn install_package(
                 pkg.version.original
             );
             compile_package()?;
+            // We don't know where that source came from anymore
+            write_install_metadata(pkg, cache, None, None, PackageType::Source)?;
         }
         InstallationStatus::Absent => {
             log::debug!(
@@ -71,6 +107,20 @@ pub(crate) fn install_package(
             let tarball_url = get_tarball_urls(pkg, &cache.r_version, &cache.system_info)
                 .expect("Dependency has source Repository");
             let http = Http {};
+            let Source::Repository { repository } = &pkg.source else {
+                unreachable!("Dependency has source Repository");
+            };
+            let download_and_untar = |url: &Url, destination: &Path| {
+                download_and_untar_from_mirrors(
+                    &http,
+                    cache,
+                    url,
+                    repository.as_str(),
+                    mirrors,
+                    destination,
+                    false,
+                )
+            };
 
             let download_and_install_source_or_archive = || -> Result<(), SyncError> {
                 log::debug!(
@@ -78,41 +128,54 @@ pub(crate) fn install_package(
                     pkg.name,
                     pkg.version.original
                 );
-                if let Err(e) =
-                    http.download_and_untar(&tarball_url.source, &pkg_paths.source, false)
-                {
-                    log::warn!(
-                        "Failed to download/untar source package from {}: {e:?}, falling back to {}",
-                        tarball_url.source,
-                        tarball_url.archive
-                    );
-                    log::debug!(
-                        "Downloading package {} ({}) from archive",
-                        pkg.name,
-                        pkg.version.original
-                    );
-                    http.download_and_untar(&tarball_url.archive, &pkg_paths.source, false)?;
-                }
+                let (url, sha) = match download_and_untar(&tarball_url.source, &pkg_paths.source) {
+                    Ok((_, sha)) => (&tarball_url.source, sha),
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to download/untar source package from {}: {e:?}, falling back to {}",
+                            tarball_url.source,
+                            tarball_url.archive
+                        );
+                        log::debug!(
+                            "Downloading package {} ({}) from archive",
+                            pkg.name,
+                            pkg.version.original
+                        );
+                        let (_, sha) = download_and_untar(&tarball_url.archive, &pkg_paths.source)?;
+                        (&tarball_url.archive, sha)
+                    }
+                };
                 compile_package()?;
+                write_install_metadata(
+                    pkg,
+                    cache,
+                    Some(url.clone()),
+                    Some(sha),
+                    PackageType::Source,
+                )?;
                 Ok(())
             };
 
             if pkg.kind == PackageType::Source || tarball_url.binary.is_none() {
                 download_and_install_source_or_archive()?;
             } else {
+                // It might have been prefetched already, its metadata was written then
+                let downloaded = if pkg_paths.binary.join(pkg.name.as_ref()).is_dir() {
+                    log::debug!("Package {} was already downloaded", pkg.name);
+                    Ok(None)
+                } else {
+                    download_and_untar(&tarball_url.binary.clone().unwrap(), &pkg_paths.binary)
+                        .map(|(_, sha)| Some(sha))
+                };
                 // If we get an error doing the binary download, fall back to source
-                if let Err(e) = http.download_and_untar(
-                    &tarball_url.binary.clone().unwrap(),
-                    &pkg_paths.binary,
-                    false,
-                ) {
+                if let Err(e) = downloaded {
                     log::warn!(
                         "Failed to download/untar binary package from {}: {e:?}, falling back to {}",
                         tarball_url.binary.clone().unwrap(),
                         tarball_url.source
                     );
                     download_and_install_source_or_archive()?;
-                } else {
+                } else if let Ok(sha) = downloaded {
                     // Ok we download some tarball. We can't assume it's actually compiled though, it could be just
                     // source files. We have to check first whether what we have is actually binary content.
                     if !is_binary_package(
@@ -128,6 +191,21 @@ pub(crate) fn install_package(
                             fs::rename(&pkg_paths.binary, &pkg_paths.source)?;
                         }
                         compile_package()?;
+                        write_install_metadata(
+                            pkg,
+                            cache,
+                            tarball_url.binary.clone(),
+                            sha,
+                            PackageType::Source,
+                        )?;
+                    } else if let Some(sha) = sha {
+                        write_install_metadata(
+                            pkg,
+                            cache,
+                            tarball_url.binary.clone(),
+                            Some(sha),
+                            PackageType::Binary,
+                        )?;
                     }
                 }
             }
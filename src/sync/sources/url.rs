@@ -8,6 +8,7 @@ use crate::library::LocalMetadata;
 use crate::package::PackageType;
 use crate::sync::LinkMode;
 use crate::sync::errors::SyncError;
+use crate::sync::sources::{restore_from_content_cache, store_in_content_cache};
 use crate::{Cancellation, DiskCache, RCmd, ResolvedDependency};
 
 pub(crate) fn install_package(
@@ -20,8 +21,15 @@ pub(crate) fn install_package(
     let pkg_paths = cache.get_package_paths(&pkg.source, None, None);
     let download_path = pkg_paths.source.join(pkg.name.as_ref());
 
-    // If we have a binary, copy it since we don't keep cache around for binary URL packages
-    if pkg.kind == PackageType::Binary {
+    let restored = !pkg.installation_status.binary_available()
+        && restore_from_content_cache(pkg, cache, &pkg_paths.binary);
+    if restored {
+        log::debug!(
+            "Package from URL in {} was already built",
+            download_path.display()
+        );
+    } else if pkg.kind == PackageType::Binary {
+        // If we have a binary, copy it since we don't keep cache around for binary URL packages
         log::debug!(
             "Package from URL in {} is already a binary",
             download_path.display()
@@ -50,8 +58,11 @@ pub(crate) fn install_package(
         }
     }
 
-    let metadata = LocalMetadata::Sha(pkg.source.sha().to_owned());
-    metadata.write(pkg_paths.binary.join(pkg.name.as_ref()))?;
+    if !restored {
+        let metadata = LocalMetadata::Sha(pkg.source.sha().to_owned());
+        metadata.write(pkg_paths.binary.join(pkg.name.as_ref()))?;
+        store_in_content_cache(pkg, cache, &pkg_paths.binary);
+    }
 
     // And then we always link the binary folder into the staging library
     LinkMode::new().link_files(&pkg.name, &pkg_paths.binary, library_dirs.first().unwrap())?;
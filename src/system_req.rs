@@ -88,7 +88,6 @@ pub fn is_supported(system_info: &SystemInfo) -> bool {
 
 /// This should only be run on Linux
 pub fn get_system_requirements(system_info: &SystemInfo) -> HashMap<String, Vec<String>> {
-    let agent = http::get_agent();
     let mut url = Url::parse(&get_sysreq_url()).unwrap();
 
     {
@@ -102,6 +101,7 @@ pub fn get_system_requirements(system_info: &SystemInfo) -> HashMap<String, Vec<
     }
 
     log::debug!("Getting sysreq data from {}", url.as_str());
+    let agent = http::get_agent(&url);
 
     let response = agent
         .get(url.as_str())